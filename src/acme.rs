@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use rustls::server::ClientHello;
+use rustls::sign::{CertifiedKey, SingleCertAndKey};
+use rustls::ServerConfig;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::select::{SelectAccept, SelectConfig};
+use crate::{server, AlpnProtocol};
+
+/// Where an [`AcmeAcceptor`] finds the certificates for pending TLS-ALPN-01
+/// challenges (RFC 8737).
+pub trait ChallengeStore: Send + Sync {
+    /// Returns the challenge certificate for `server_name`, if a challenge for it is
+    /// pending.
+    fn challenge(&self, server_name: &str) -> Option<Arc<CertifiedKey>>;
+}
+
+/// A [`ChallengeStore`] in memory, that an ACME client adds challenges to while it
+/// validates a domain.
+#[derive(Debug, Default)]
+pub struct AcmeChallenges {
+    challenges: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl AcmeChallenges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer challenges for `server_name` with `cert`.
+    pub fn insert(&self, server_name: &str, cert: Arc<CertifiedKey>) {
+        self.lock().insert(server_name.to_ascii_lowercase(), cert);
+    }
+
+    /// Stop answering challenges for `server_name`, once it is validated.
+    pub fn remove(&self, server_name: &str) {
+        self.lock().remove(&server_name.to_ascii_lowercase());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<CertifiedKey>>> {
+        self.challenges
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl ChallengeStore for AcmeChallenges {
+    fn challenge(&self, server_name: &str) -> Option<Arc<CertifiedKey>> {
+        self.lock().get(&server_name.to_ascii_lowercase()).cloned()
+    }
+}
+
+/// An acceptor that answers ACME TLS-ALPN-01 challenges, and accepts all other
+/// connections with its `ServerConfig`.
+///
+/// This reads the ClientHello with a [`LazyConfigAcceptor`](crate::LazyConfigAcceptor)
+/// before starting the handshake. Clients that offer the `acme-tls/1` protocol are validation servers:
+/// they are served the challenge certificate for the name they send, and the
+/// connection is closed after the handshake. Certificates can be renewed this way
+/// while the server keeps serving its current ones.
+#[derive(Clone)]
+pub struct AcmeAcceptor {
+    config: Arc<ServerConfig>,
+    challenges: Arc<dyn ChallengeStore>,
+}
+
+impl AcmeAcceptor {
+    pub fn new(config: Arc<ServerConfig>, challenges: Arc<dyn ChallengeStore>) -> Self {
+        AcmeAcceptor { config, challenges }
+    }
+
+    /// Returns whether `client_hello` comes from an ACME validation server.
+    pub fn is_challenge(client_hello: &ClientHello<'_>) -> bool {
+        let acme = AlpnProtocol::AcmeTls1.as_bytes();
+        client_hello.alpn().map_or(false, |mut offered| {
+            offered.any(|protocol| protocol == acme)
+        })
+    }
+
+    /// Returns the config to answer the challenge for `server_name` with.
+    fn challenge_config(&self, server_name: Option<&str>) -> io::Result<Arc<ServerConfig>> {
+        let cert = server_name
+            .and_then(|name| self.challenges.challenge(name))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    "no pending ACME challenge for server name",
+                )
+            })?;
+
+        let mut config = ServerConfig::builder_with_provider(self.config.crypto_provider().clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(SingleCertAndKey::from(cert)));
+        config.alpn_protocols = AlpnProtocol::to_ids(&[AlpnProtocol::AcmeTls1]);
+        Ok(Arc::new(config))
+    }
+
+    /// Accepts a connection; resolves to `None` for a challenge that was answered.
+    pub fn accept<IO>(&self, stream: IO) -> AcmeAccept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        AcmeAccept {
+            state: AcmeAcceptState::Accepting(SelectAccept::new(stream, self.clone())),
+        }
+    }
+}
+
+impl SelectConfig for AcmeAcceptor {
+    /// Whether the connection answers a challenge.
+    type Choice = bool;
+
+    fn select_config(
+        &self,
+        client_hello: &ClientHello<'_>,
+    ) -> io::Result<(Arc<ServerConfig>, bool)> {
+        match AcmeAcceptor::is_challenge(client_hello) {
+            true => Ok((self.challenge_config(client_hello.server_name())?, true)),
+            false => Ok((self.config.clone(), false)),
+        }
+    }
+}
+
+/// Future returned from `AcmeAcceptor::accept` which will resolve
+/// once the accept handshake has finished, or the challenge was answered.
+pub struct AcmeAccept<IO> {
+    state: AcmeAcceptState<IO>,
+}
+
+// Accepting holds the handshake state the stream is closed from; boxing it would
+// only add an allocation per connection.
+#[allow(clippy::large_enum_variant)]
+enum AcmeAcceptState<IO> {
+    Accepting(SelectAccept<IO, AcmeAcceptor>),
+    Closing(server::TlsStream<IO>),
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Future for AcmeAccept<IO> {
+    type Output = io::Result<Option<server::TlsStream<IO>>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match &mut self.state {
+                AcmeAcceptState::Accepting(accept) => match ready!(Pin::new(accept).poll(cx))? {
+                    (stream, true) => self.state = AcmeAcceptState::Closing(stream),
+                    (stream, false) => return Poll::Ready(Ok(Some(stream))),
+                },
+                AcmeAcceptState::Closing(stream) => {
+                    ready!(Pin::new(stream).poll_shutdown(cx))?;
+                    return Poll::Ready(Ok(None));
+                }
+            }
+        }
+    }
+}
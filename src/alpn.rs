@@ -0,0 +1,187 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use rustls::server::ClientHello;
+use rustls::ServerConfig;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::select::{SelectAccept, SelectConfig};
+use crate::server;
+
+/// An ALPN protocol identifier.
+///
+/// Converts to and from the raw identifiers rustls works with; identifiers without a
+/// variant of their own are kept as [`AlpnProtocol::Other`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AlpnProtocol {
+    /// HTTP/2 over TLS, `h2`.
+    H2,
+    /// HTTP/1.1, `http/1.1`.
+    Http11,
+    /// DNS over TLS, `dot`.
+    Dot,
+    /// ACME TLS-ALPN-01 validation, `acme-tls/1`.
+    AcmeTls1,
+    /// Any other protocol.
+    Other(Vec<u8>),
+}
+
+impl AlpnProtocol {
+    /// HTTP/2, falling back to HTTP/1.1.
+    pub const HTTP: &'static [AlpnProtocol] = &[AlpnProtocol::H2, AlpnProtocol::Http11];
+
+    /// Returns the protocol for the raw identifier `id`.
+    pub fn from_bytes(id: &[u8]) -> Self {
+        match id {
+            b"h2" => AlpnProtocol::H2,
+            b"http/1.1" => AlpnProtocol::Http11,
+            b"dot" => AlpnProtocol::Dot,
+            b"acme-tls/1" => AlpnProtocol::AcmeTls1,
+            _ => AlpnProtocol::Other(id.to_vec()),
+        }
+    }
+
+    /// Returns the raw identifier of the protocol.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            AlpnProtocol::H2 => b"h2",
+            AlpnProtocol::Http11 => b"http/1.1",
+            AlpnProtocol::Dot => b"dot",
+            AlpnProtocol::AcmeTls1 => b"acme-tls/1",
+            AlpnProtocol::Other(id) => id,
+        }
+    }
+
+    /// Returns the raw identifiers of `protocols`, for `alpn_protocols` in a config.
+    pub fn to_ids<'a>(protocols: impl IntoIterator<Item = &'a AlpnProtocol>) -> Vec<Vec<u8>> {
+        protocols
+            .into_iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect()
+    }
+}
+
+impl From<&[u8]> for AlpnProtocol {
+    fn from(id: &[u8]) -> Self {
+        AlpnProtocol::from_bytes(id)
+    }
+}
+
+impl From<Vec<u8>> for AlpnProtocol {
+    fn from(id: Vec<u8>) -> Self {
+        match AlpnProtocol::from_bytes(&id) {
+            AlpnProtocol::Other(_) => AlpnProtocol::Other(id),
+            protocol => protocol,
+        }
+    }
+}
+
+impl From<AlpnProtocol> for Vec<u8> {
+    fn from(protocol: AlpnProtocol) -> Self {
+        match protocol {
+            AlpnProtocol::Other(id) => id,
+            protocol => protocol.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl PartialEq<[u8]> for AlpnProtocol {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_bytes() == other
+    }
+}
+
+impl fmt::Display for AlpnProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+/// An acceptor that picks its `ServerConfig` by the ALPN protocols a client offers.
+///
+/// This reads the ClientHello with a [`LazyConfigAcceptor`](crate::LazyConfigAcceptor)
+/// before starting the handshake, so each protocol can have its own settings (tickets,
+/// record limits, certificates, ...). Protocols are matched in the order they were
+/// added; clients that offer none of them get the default config.
+#[derive(Clone)]
+pub struct AlpnAcceptor {
+    default: Arc<ServerConfig>,
+    protocols: Arc<Vec<(Vec<u8>, Arc<ServerConfig>)>>,
+}
+
+impl AlpnAcceptor {
+    pub fn new(default: Arc<ServerConfig>) -> Self {
+        AlpnAcceptor {
+            default,
+            protocols: Arc::default(),
+        }
+    }
+
+    /// Use `config` for clients that offer `protocol`.
+    ///
+    /// If `config` does not list `protocol` among its `alpn_protocols`, a copy
+    /// negotiating just `protocol` is used instead.
+    pub fn protocol(mut self, protocol: impl Into<Vec<u8>>, config: Arc<ServerConfig>) -> Self {
+        let protocol = protocol.into();
+        let config = if config.alpn_protocols.contains(&protocol) {
+            config
+        } else {
+            let mut config = ServerConfig::clone(&config);
+            config.alpn_protocols = vec![protocol.clone()];
+            Arc::new(config)
+        };
+
+        Arc::make_mut(&mut self.protocols).push((protocol, config));
+        self
+    }
+
+    /// Returns the config used for a client that sent `client_hello`.
+    pub fn select(&self, client_hello: &ClientHello<'_>) -> Arc<ServerConfig> {
+        let offered = match client_hello.alpn() {
+            Some(offered) => offered.collect::<Vec<_>>(),
+            None => return self.default.clone(),
+        };
+
+        self.protocols
+            .iter()
+            .find(|(protocol, _)| offered.contains(&protocol.as_slice()))
+            .map_or_else(|| self.default.clone(), |(_, config)| config.clone())
+    }
+
+    pub fn accept<IO>(&self, stream: IO) -> AlpnAccept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        AlpnAccept {
+            inner: SelectAccept::new(stream, self.clone()),
+        }
+    }
+}
+
+impl SelectConfig for AlpnAcceptor {
+    type Choice = ();
+
+    fn select_config(&self, client_hello: &ClientHello<'_>) -> io::Result<(Arc<ServerConfig>, ())> {
+        Ok((self.select(client_hello), ()))
+    }
+}
+
+/// Future returned from `AlpnAcceptor::accept` which will resolve
+/// once the accept handshake has finished.
+pub struct AlpnAccept<IO> {
+    inner: SelectAccept<IO, AlpnAcceptor>,
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Future for AlpnAccept<IO> {
+    type Output = io::Result<server::TlsStream<IO>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.inner)
+            .poll(cx)
+            .map_ok(|(stream, ())| stream)
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use rustls::{
+    AlertDescription, CertificateError, CommonState, Error, HandshakeKind, PeerMisbehaved, Side,
+};
+
+/// A security-relevant occurrence during a handshake, see
+/// [`TlsConnector::audit`](crate::TlsConnector::audit) and
+/// [`TlsAcceptor::audit`](crate::TlsAcceptor::audit).
+#[derive(Debug)]
+pub struct AuditEvent<'a> {
+    /// Which end of the connection emitted the event.
+    pub side: Side,
+    /// What happened.
+    pub kind: AuditKind<'a>,
+}
+
+/// The kinds of [`AuditEvent`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AuditKind<'a> {
+    /// The peer's certificate was rejected by the certificate verifier.
+    CertificateRejected { reason: &'a CertificateError },
+    /// A server rejected the client's certificate, or the client presented none
+    /// although one was required.
+    ///
+    /// Follows a [`AuditKind::CertificateRejected`] event if the certificate was rejected.
+    ClientAuthFailed { error: &'a Error },
+    /// The handshake failed, and a fatal alert was sent to the peer.
+    ///
+    /// `description` is `None` if rustls does not expose which alert it sent for `error`.
+    AlertSent {
+        description: Option<AlertDescription>,
+        error: &'a Error,
+    },
+    /// The peer sent a fatal alert.
+    ///
+    /// Only alerts received during the handshake are reported. With TLS 1.3 a client
+    /// completes its handshake before the server checks the client certificate, so
+    /// it does not see the alert sent when that check fails.
+    AlertReceived { description: AlertDescription },
+    /// A client found the TLS 1.3 downgrade sentinel in a TLS 1.2 server hello,
+    /// meaning something between client and server stripped TLS 1.3 support.
+    DowngradeDetected,
+    /// The handshake completed after a HelloRetryRequest.
+    HelloRetryRequest,
+}
+
+impl fmt::Display for AuditKind<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditKind::CertificateRejected { reason } => {
+                write!(f, "certificate rejected: {:?}", reason)
+            }
+            AuditKind::ClientAuthFailed { error } => write!(f, "client auth failed: {}", error),
+            AuditKind::AlertSent {
+                description: Some(description),
+                ..
+            } => write!(f, "sent fatal alert {:?}", description),
+            AuditKind::AlertSent { error, .. } => write!(f, "sent fatal alert: {}", error),
+            AuditKind::AlertReceived { description } => {
+                write!(f, "received fatal alert {:?}", description)
+            }
+            AuditKind::DowngradeDetected => f.write_str("TLS 1.3 downgrade detected"),
+            AuditKind::HelloRetryRequest => f.write_str("HelloRetryRequest"),
+        }
+    }
+}
+
+type Hook = dyn Fn(&AuditEvent<'_>) + Send + Sync;
+
+/// The audit hook of a connector or acceptor, if any.
+#[derive(Clone, Default)]
+pub(crate) struct Audit(Option<Arc<Hook>>);
+
+impl Audit {
+    pub(crate) fn new(hook: impl Fn(&AuditEvent<'_>) + Send + Sync + 'static) -> Self {
+        Self(Some(Arc::new(hook)))
+    }
+
+    pub(crate) fn handshake_completed(&self, side: Side, session: &CommonState) {
+        if session.handshake_kind() == Some(HandshakeKind::FullWithHelloRetryRequest) {
+            self.emit(side, AuditKind::HelloRetryRequest);
+        }
+    }
+
+    pub(crate) fn handshake_failed(&self, side: Side, error: &io::Error) {
+        if self.0.is_none() {
+            return;
+        }
+
+        let error = match error.get_ref().and_then(|err| err.downcast_ref::<Error>()) {
+            Some(error) => error,
+            None => return,
+        };
+
+        match error {
+            Error::AlertReceived(description) => {
+                return self.emit(
+                    side,
+                    AuditKind::AlertReceived {
+                        description: *description,
+                    },
+                );
+            }
+            Error::InvalidCertificate(reason) => {
+                self.emit(side, AuditKind::CertificateRejected { reason });
+                if side == Side::Server {
+                    self.emit(side, AuditKind::ClientAuthFailed { error });
+                }
+            }
+            Error::NoCertificatesPresented if side == Side::Server => {
+                self.emit(side, AuditKind::ClientAuthFailed { error });
+            }
+            Error::PeerMisbehaved(
+                PeerMisbehaved::AttemptedDowngradeToTls12WhenTls13IsSupported,
+            ) => {
+                self.emit(side, AuditKind::DowngradeDetected);
+            }
+            _ => {}
+        }
+
+        let description = match error {
+            Error::InvalidCertificate(reason) => Some(reason.clone().into()),
+            Error::InvalidMessage(reason) => Some((*reason).into()),
+            _ => None,
+        };
+        self.emit(side, AuditKind::AlertSent { description, error });
+    }
+
+    fn emit(&self, side: Side, kind: AuditKind<'_>) {
+        if let Some(hook) = &self.0 {
+            hook(&AuditEvent { side, kind });
+        }
+    }
+}
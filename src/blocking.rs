@@ -0,0 +1,84 @@
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::Poll;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::runtime::Handle;
+
+use crate::common::poll_fn;
+use crate::TlsStream;
+
+/// A blocking [`Read`] and [`Write`] view of a [`TlsStream`].
+///
+/// This lets synchronous code use a connection set up by async code: each call
+/// blocks the current thread on the runtime behind `handle`, which must be the one
+/// driving the underlying IO.
+///
+/// Like [`Handle::block_on`], the methods of this type panic when called from
+/// within an asynchronous execution context; use it from plain threads or
+/// `spawn_blocking` tasks.
+pub struct SyncTlsStream<IO> {
+    stream: TlsStream<IO>,
+    handle: Handle,
+}
+
+impl<IO> SyncTlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(stream: impl Into<TlsStream<IO>>, handle: Handle) -> Self {
+        SyncTlsStream {
+            stream: stream.into(),
+            handle,
+        }
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &TlsStream<IO> {
+        &self.stream
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut TlsStream<IO> {
+        &mut self.stream
+    }
+
+    /// Returns the async stream, to hand the connection back to async code.
+    #[inline]
+    pub fn into_inner(self) -> TlsStream<IO> {
+        self.stream
+    }
+}
+
+impl<IO> Read for SyncTlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let stream = &mut self.stream;
+        self.handle.block_on(poll_fn(|cx| {
+            let mut buf = ReadBuf::new(buf);
+            ready!(Pin::new(&mut *stream).poll_read(cx, &mut buf))?;
+            Poll::Ready(Ok(buf.filled().len()))
+        }))
+    }
+}
+
+impl<IO> Write for SyncTlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Like `TlsStream::poll_write`, this does not guarantee the data has been sent;
+    /// call `flush`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let stream = &mut self.stream;
+        self.handle
+            .block_on(poll_fn(|cx| Pin::new(&mut *stream).poll_write(cx, buf)))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let stream = &mut self.stream;
+        self.handle
+            .block_on(poll_fn(|cx| Pin::new(&mut *stream).poll_flush(cx)))
+    }
+}
@@ -0,0 +1,28 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::Context;
+
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+/// The cancellation token of a handshake future, if any.
+#[derive(Default)]
+pub(crate) struct Cancel(Option<Pin<Box<WaitForCancellationFutureOwned>>>);
+
+impl Cancel {
+    pub(crate) fn new(token: CancellationToken) -> Self {
+        Self(Some(Box::pin(token.cancelled_owned())))
+    }
+
+    /// Whether the token was cancelled; registers for a wakeup if not.
+    pub(crate) fn poll_cancelled(&mut self, cx: &mut Context<'_>) -> bool {
+        match &mut self.0 {
+            Some(cancelled) => cancelled.as_mut().poll(cx).is_ready(),
+            None => false,
+        }
+    }
+}
+
+pub(crate) fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "handshake cancelled")
+}
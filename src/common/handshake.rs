@@ -8,15 +8,25 @@ use rustls::server::AcceptedAlert;
 use rustls::{ConnectionCommon, SideData};
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::common::{Stream, SyncWriteAdapter, TlsState};
+use crate::common::{ReadAhead, Stream, SyncWriteAdapter, TlsState};
 
 pub(crate) trait IoSession {
     type Io;
     type Session;
 
     fn skip_handshake(&self) -> bool;
-    fn get_mut(&mut self) -> (&mut TlsState, &mut Self::Io, &mut Self::Session);
+    fn get_mut(
+        &mut self,
+    ) -> (
+        &mut TlsState,
+        &mut Self::Io,
+        &mut Self::Session,
+        &mut ReadAhead,
+    );
     fn into_io(self) -> Self::Io;
+
+    /// Called when the handshake fails, before the session is dropped.
+    fn handshake_failed(&mut self) {}
 }
 
 pub(crate) enum MidHandshake<IS: IoSession> {
@@ -62,14 +72,19 @@ where
         };
 
         if !stream.skip_handshake() {
-            let (state, io, session) = stream.get_mut();
-            let mut tls_stream = Stream::new(io, session).set_eof(!state.readable());
+            let (state, io, session, read_ahead) = stream.get_mut();
+            let mut tls_stream = Stream::new(io, session)
+                .set_eof(!state.readable())
+                .set_read_ahead(read_ahead);
 
             macro_rules! try_poll {
                 ( $e:expr ) => {
                     match $e {
                         Poll::Ready(Ok(_)) => (),
-                        Poll::Ready(Err(err)) => return Poll::Ready(Err((err, stream.into_io()))),
+                        Poll::Ready(Err(err)) => {
+                            stream.handshake_failed();
+                            return Poll::Ready(Err((err, stream.into_io())));
+                        }
                         Poll::Pending => {
                             *this = MidHandshake::Handshaking(stream);
                             return Poll::Pending;
@@ -82,9 +97,43 @@ where
                 try_poll!(tls_stream.handshake(cx));
             }
 
-            try_poll!(Pin::new(&mut tls_stream).poll_flush(cx));
+            try_poll!(tls_stream.poll_flush_io(cx));
         }
 
         Poll::Ready(Ok(stream))
     }
 }
+
+impl<IS, SD> MidHandshake<IS>
+where
+    IS: IoSession,
+    IS::Io: AsyncRead + AsyncWrite + Unpin,
+    IS::Session: DerefMut + Deref<Target = ConnectionCommon<SD>>,
+    SD: SideData,
+{
+    /// Abandons the handshake with `error`, telling the peer with a `close_notify` alert.
+    ///
+    /// rustls has no way to send `user_canceled`, so `close_notify` is the closest
+    /// alert available. It is written without waiting: if the IO is not ready to take
+    /// it, the peer only sees the connection close.
+    pub(crate) fn abort(&mut self, cx: &mut Context<'_>, error: io::Error) -> (io::Error, IS::Io) {
+        match mem::replace(self, MidHandshake::End) {
+            MidHandshake::Handshaking(mut stream) => {
+                let (_, io, session, _) = stream.get_mut();
+                session.send_close_notify();
+                let mut tls_stream = Stream::new(io, session);
+                while tls_stream.session.wants_write() {
+                    match tls_stream.write_io(cx) {
+                        Poll::Ready(Ok(n)) if n > 0 => {}
+                        _ => break,
+                    }
+                }
+                let _ = tls_stream.poll_flush_io(cx);
+                stream.handshake_failed();
+                (error, stream.into_io())
+            }
+            MidHandshake::SendAlert { io, .. } | MidHandshake::Error { io, .. } => (error, io),
+            MidHandshake::End => panic!("unexpected polling after handshake"),
+        }
+    }
+}
@@ -1,10 +1,16 @@
-use std::io::{self, IoSlice, Read, Write};
+use std::io::{self, BufRead, IoSlice, Read, Write};
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
+#[cfg(feature = "bytes")]
+use bytes::{Buf, BufMut};
 use rustls::{ConnectionCommon, SideData};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::BufferPool;
 
 mod handshake;
 pub(crate) use handshake::{IoSession, MidHandshake};
@@ -19,13 +25,41 @@ pub enum TlsState {
     FullyShutdown,
 }
 
+/// The state of a stream's connection, see `TlsStream::connection_state`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The handshake has not completed yet, as with a client sending early data.
+    Handshaking,
+    /// Both directions are open.
+    Open,
+    /// The read side has ended, by `close_notify` or EOF; writing is still possible.
+    ReadClosed,
+    /// The stream was shut down for writing; reading is still possible.
+    WriteClosed,
+    /// Both directions have ended.
+    Closed,
+}
+
 impl TlsState {
+    #[inline]
+    pub fn connection_state(&self) -> ConnectionState {
+        match *self {
+            #[cfg(feature = "early-data")]
+            TlsState::EarlyData(..) => ConnectionState::Handshaking,
+            TlsState::Stream => ConnectionState::Open,
+            TlsState::ReadShutdown => ConnectionState::ReadClosed,
+            TlsState::WriteShutdown => ConnectionState::WriteClosed,
+            TlsState::FullyShutdown => ConnectionState::Closed,
+        }
+    }
+
     #[inline]
     pub fn shutdown_read(&mut self) {
         match *self {
             TlsState::WriteShutdown | TlsState::FullyShutdown => *self = TlsState::FullyShutdown,
             _ => *self = TlsState::ReadShutdown,
         }
+        event!(debug, state = ?self.connection_state(), "read side shut down");
     }
 
     #[inline]
@@ -34,6 +68,7 @@ impl TlsState {
             TlsState::ReadShutdown | TlsState::FullyShutdown => *self = TlsState::FullyShutdown,
             _ => *self = TlsState::WriteShutdown,
         }
+        event!(debug, state = ?self.connection_state(), "write side shut down");
     }
 
     #[inline]
@@ -59,10 +94,195 @@ impl TlsState {
     }
 }
 
-pub struct Stream<'a, IO, C> {
+impl ConnectionState {
+    /// Returns whether the read side is still open.
+    #[inline]
+    pub fn is_readable(self) -> bool {
+        !matches!(self, ConnectionState::ReadClosed | ConnectionState::Closed)
+    }
+
+    /// Returns whether the write side is still open.
+    #[inline]
+    pub fn is_writable(self) -> bool {
+        !matches!(self, ConnectionState::WriteClosed | ConnectionState::Closed)
+    }
+}
+
+/// The error writes fail with once the write side of a stream has been shut down.
+pub(crate) fn write_after_shutdown() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "write after the write side was shut down",
+    )
+}
+
+/// Why reading from a stream stopped, see `TlsStream::read_eof_reason`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EofReason {
+    /// The peer sent `close_notify`: all data it sent has been received.
+    CloseNotify,
+    /// The underlying IO reached EOF without a `close_notify`, so the data may
+    /// have been truncated.
+    Transport,
+}
+
+/// Ciphertext read from the IO ahead of what rustls has consumed.
+///
+/// rustls pulls at most a few kilobytes per `read_tls` call. When enabled, this
+/// reads up to `size` bytes from the IO at once and feeds rustls from memory,
+/// saving syscalls on bulk transfers.
+#[derive(Debug, Default)]
+pub(crate) struct ReadAhead {
+    size: usize,
+    buf: Vec<u8>,
+    pos: usize,
+    /// How much of the capacity of `buf` has been initialized by earlier reads.
+    init: usize,
+    pool: Option<Arc<BufferPool>>,
+}
+
+impl ReadAhead {
+    #[inline]
+    pub fn new(size: usize) -> Self {
+        ReadAhead {
+            size,
+            buf: Vec::new(),
+            pos: 0,
+            init: 0,
+            pool: None,
+        }
+    }
+
+    /// Take the buffer from `pool` while reading ahead, and put it back once rustls
+    /// has consumed what was read.
+    #[inline]
+    pub fn with_pool(mut self, pool: Option<Arc<BufferPool>>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    #[inline]
+    pub fn set_size(&mut self, size: usize) {
+        self.size = size;
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.size != 0
+    }
+
+    #[inline]
+    pub fn has_buffered(&self) -> bool {
+        self.pos < self.buf.len()
+    }
+
+    fn poll_fill<IO: AsyncRead + Unpin>(
+        &mut self,
+        io: &mut IO,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let (Some(pool), 0) = (&self.pool, self.buf.capacity()) {
+            self.buf = pool.take(self.size);
+            self.init = 0;
+        }
+        self.buf.clear();
+        self.pos = 0;
+        if self.buf.capacity() < self.size {
+            // Reallocating only keeps the bytes up to `len`, which is 0.
+            self.buf.reserve(self.size);
+            self.init = 0;
+        }
+
+        let (len, init) = {
+            let mut buf = ReadBuf::uninit(&mut self.buf.spare_capacity_mut()[..self.size]);
+            // SAFETY: earlier reads initialized this much of the spare capacity.
+            unsafe { buf.assume_init(self.init.min(self.size)) };
+            ready!(Pin::new(io).poll_read(cx, &mut buf))?;
+            (buf.filled().len(), buf.initialized().len())
+        };
+
+        // SAFETY: `poll_read` initialized the first `len` bytes of the spare capacity.
+        unsafe { self.buf.set_len(len) };
+        self.init = self.init.max(init);
+        if len == 0 {
+            self.release();
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Puts the buffer back into the pool, if there is one.
+    fn release(&mut self) {
+        if let Some(pool) = &self.pool {
+            pool.put(mem::take(&mut self.buf));
+            self.pos = 0;
+            self.init = 0;
+        }
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// The buffer limit rustls gives new connections.
+pub(crate) const DEFAULT_BUFFER_LIMIT: usize = 64 * 1024;
+
+/// Bounds on the ciphertext a stream buffers for writing.
+///
+/// Once more than `high` bytes of ciphertext are waiting to be written to the IO,
+/// writes return `Poll::Pending` until writing out has brought the backlog down to
+/// `low` bytes. Disabled by default, which leaves rustls' buffer limit as the only bound.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct WriteWatermarks {
+    high: usize,
+    low: usize,
+    draining: bool,
+}
+
+impl WriteWatermarks {
+    /// # Panics
+    ///
+    /// If `low` is greater than `high`.
+    #[inline]
+    pub fn new(high: usize, low: usize) -> Self {
+        assert!(low <= high, "low watermark above high watermark");
+        WriteWatermarks {
+            high,
+            low,
+            draining: false,
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.high != 0
+    }
+}
+
+impl Read for ReadAhead {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = (&self.buf[self.pos..]).read(buf)?;
+        self.pos += len;
+        if !self.has_buffered() {
+            self.release();
+        }
+        Ok(len)
+    }
+}
+
+/// A borrowed view of a connection's IO and session, driving the TLS state machine.
+///
+/// Stream types build one of these on every poll from their own fields, along with
+/// the read-ahead buffer and write watermarks they keep.
+pub(crate) struct Stream<'a, IO, C> {
     pub io: &'a mut IO,
     pub session: &'a mut C,
     pub eof: bool,
+    pub read_ahead: Option<&'a mut ReadAhead>,
+    pub write_watermarks: Option<&'a mut WriteWatermarks>,
 }
 
 impl<'a, IO: AsyncRead + AsyncWrite + Unpin, C, SD> Stream<'a, IO, C>
@@ -77,6 +297,8 @@ where
             // The state so far is only used to detect EOF, so either Stream
             // or EarlyData state should both be all right.
             eof: false,
+            read_ahead: None,
+            write_watermarks: None,
         }
     }
 
@@ -85,14 +307,138 @@ where
         self
     }
 
+    pub fn set_read_ahead(mut self, read_ahead: &'a mut ReadAhead) -> Self {
+        self.read_ahead = Some(read_ahead);
+        self
+    }
+
+    pub fn set_write_watermarks(mut self, watermarks: &'a mut WriteWatermarks) -> Self {
+        self.write_watermarks = Some(watermarks);
+        self
+    }
+
     pub fn as_mut_pin(&mut self) -> Pin<&mut Self> {
         Pin::new(self)
     }
 
+    /// Returns why reading stopped, once the read side has ended.
+    pub fn eof_reason(&mut self) -> EofReason {
+        match self.session.reader().into_first_chunk() {
+            Ok([]) => EofReason::CloseNotify,
+            _ => EofReason::Transport,
+        }
+    }
+
+    #[inline]
+    fn has_buffered_ciphertext(&self) -> bool {
+        self.read_ahead
+            .as_ref()
+            .map_or(false, |read_ahead| read_ahead.has_buffered())
+    }
+
+    /// How much plaintext may be written without going over the high watermark, once
+    /// the backlog of ciphertext allows writing at all.
+    fn poll_write_budget(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let (high, low, mut draining) = match self.write_watermarks.as_deref() {
+            Some(w) if w.is_enabled() => (w.high, w.low, w.draining),
+            _ => return Poll::Ready(Ok(usize::MAX)),
+        };
+
+        let result = loop {
+            // Received packets are processed as they are read, so this only
+            // queries how much ciphertext is waiting to be written.
+            let buffered = match self.session.process_new_packets() {
+                Ok(state) => state.tls_bytes_to_write(),
+                Err(err) => {
+                    break Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, err)))
+                }
+            };
+            if draining && buffered <= low {
+                draining = false;
+            }
+            if !draining && buffered < high {
+                break Poll::Ready(Ok(high - buffered));
+            }
+
+            draining = true;
+            match self.write_io(cx) {
+                Poll::Ready(Ok(0)) => break Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(_)) => {}
+                result => break result,
+            }
+        };
+
+        if let Some(watermarks) = self.write_watermarks.as_deref_mut() {
+            watermarks.draining = draining;
+        }
+        result
+    }
+
+    /// Like `poll_write`, but hands rustls the chunks of `buf` as they are, rather
+    /// than one contiguous slice at a time.
+    #[cfg(feature = "bytes")]
+    pub fn poll_write_buf<B: Buf>(
+        &mut self,
+        cx: &mut Context,
+        buf: &mut B,
+    ) -> Poll<io::Result<usize>> {
+        let budget = ready!(self.poll_write_budget(cx))?;
+        let buf = &mut buf.take(budget);
+        let mut written = 0;
+
+        while buf.has_remaining() {
+            let mut would_block = false;
+
+            let mut chunks = [IoSlice::new(&[]); 64];
+            let count = buf.chunks_vectored(&mut chunks);
+            match self.session.writer().write_vectored(&chunks[..count]) {
+                // The buffer limit is too low to take even a byte.
+                Ok(0) if !self.session.wants_write() => return Poll::Ready(Ok(written)),
+                Ok(n) => {
+                    buf.advance(n);
+                    written += n;
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            };
+
+            while self.session.wants_write() {
+                match self.write_io(cx) {
+                    Poll::Ready(Ok(0)) | Poll::Pending => {
+                        would_block = true;
+                        break;
+                    }
+                    Poll::Ready(Ok(_)) => (),
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                }
+            }
+
+            return match (written, would_block) {
+                (0, true) => Poll::Pending,
+                (n, true) => Poll::Ready(Ok(n)),
+                (_, false) => continue,
+            };
+        }
+
+        Poll::Ready(Ok(written))
+    }
+
     pub fn read_io(&mut self, cx: &mut Context) -> Poll<io::Result<usize>> {
-        let mut reader = SyncReadAdapter { io: self.io, cx };
+        let result = match self.read_ahead.as_deref_mut() {
+            Some(read_ahead) if read_ahead.is_enabled() || read_ahead.has_buffered() => {
+                if !read_ahead.has_buffered() {
+                    ready!(read_ahead.poll_fill(self.io, cx))?;
+                }
+
+                // An empty buffer at this point means the IO reached EOF.
+                self.session.read_tls(read_ahead)
+            }
+            _ => {
+                let mut reader = SyncReadAdapter { io: self.io, cx };
+                self.session.read_tls(&mut reader)
+            }
+        };
 
-        let n = match self.session.read_tls(&mut reader) {
+        let n = match result {
             Ok(n) => n,
             Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
             Err(err) => return Poll::Ready(Err(err)),
@@ -104,10 +450,12 @@ where
             // error.
             let _ = self.write_io(cx);
 
+            event!(debug, error = %err, "failed to process TLS records");
             io::Error::new(io::ErrorKind::InvalidData, err)
         })?;
 
         if stats.peer_has_closed() && self.session.is_handshaking() {
+            event!(debug, "peer closed the connection during the handshake");
             return Poll::Ready(Err(io::Error::new(
                 io::ErrorKind::UnexpectedEof,
                 "tls handshake alert",
@@ -117,6 +465,11 @@ where
         Poll::Ready(Ok(n))
     }
 
+    /// Writes out queued ciphertext.
+    ///
+    /// rustls hands all queued records, up to 64 of them, to a single
+    /// `poll_write_vectored` call, so IO that supports vectored writes gets them in
+    /// one syscall rather than one write per record.
     pub fn write_io(&mut self, cx: &mut Context) -> Poll<io::Result<usize>> {
         let mut writer = SyncWriteAdapter { io: self.io, cx };
 
@@ -171,11 +524,18 @@ where
 
             return match (self.eof, self.session.is_handshaking()) {
                 (true, true) => {
+                    event!(debug, "IO reached EOF during the handshake");
                     let err = io::Error::new(io::ErrorKind::UnexpectedEof, "tls handshake eof");
                     Poll::Ready(Err(err))
                 }
                 (_, false) => Poll::Ready(Ok((rdlen, wrlen))),
                 (_, true) if write_would_block || read_would_block => {
+                    event!(
+                        trace,
+                        read_would_block,
+                        write_would_block,
+                        "handshake waiting for IO"
+                    );
                     if rdlen != 0 || wrlen != 0 {
                         Poll::Ready(Ok((rdlen, wrlen)))
                     } else {
@@ -186,15 +546,14 @@ where
             };
         }
     }
-}
 
-impl<'a, IO: AsyncRead + AsyncWrite + Unpin, C, SD> AsyncRead for Stream<'a, IO, C>
-where
-    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
-    SD: SideData,
-{
-    fn poll_read(
-        mut self: Pin<&mut Self>,
+    /// Read plaintext into `buf`, reading and decrypting records from the IO as needed.
+    /// Plaintext is copied into the uninitialized part of `buf` without zeroing it first.
+    ///
+    /// Filling nothing signals that the peer has closed the connection with
+    /// `close_notify`; the IO reaching EOF without it is an `UnexpectedEof` error.
+    pub fn poll_read_plaintext_buf(
+        &mut self,
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
@@ -215,55 +574,104 @@ where
             }
         }
 
-        match self.session.reader().read(buf.initialize_unfilled()) {
-            // If Rustls returns `Ok(0)` (while `buf` is non-empty), the peer closed the
-            // connection with a `CloseNotify` message and no more data will be forthcoming.
-            //
-            // Rustls yielded more data: advance the buffer, then see if more data is coming.
-            //
-            // We don't need to modify `self.eof` here, because it is only a temporary mark.
-            // rustls will only return 0 if is has received `CloseNotify`,
-            // in which case no additional processing is required.
-            Ok(n) => {
-                buf.advance(n);
-                Poll::Ready(Ok(()))
+        let start = buf.filled().len();
+        loop {
+            return match read_plaintext(self.session, buf) {
+                // Ciphertext that was read ahead may hold further records: decrypt them
+                // while there is room left, without going back to the IO. Should that
+                // fail, rustls reports the error again on the next read.
+                Ok(n) if n != 0 && buf.remaining() != 0 && self.has_buffered_ciphertext() => {
+                    match self.read_io(cx) {
+                        Poll::Ready(Ok(_)) => continue,
+                        _ => Poll::Ready(Ok(())),
+                    }
+                }
+
+                // If Rustls returns `Ok(0)` (while `buf` is non-empty), the peer closed the
+                // connection with a `CloseNotify` message and no more data will be forthcoming.
+                //
+                // Rustls yielded more data: advance the buffer, then see if more data is coming.
+                //
+                // We don't need to modify `self.eof` here, because it is only a temporary mark.
+                // rustls will only return 0 if is has received `CloseNotify`,
+                // in which case no additional processing is required.
+                Ok(_) => Poll::Ready(Ok(())),
+
+                // Whatever else happens, hand out the data decrypted so far first.
+                Err(_) if buf.filled().len() != start => Poll::Ready(Ok(())),
+
+                // Rustls doesn't have more data to yield, but it believes the connection is open.
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if !io_pending {
+                        // If `wants_read()` is satisfied, rustls will not return `WouldBlock`.
+                        // but if it does, we can try again.
+                        //
+                        // If the rustls state is abnormal, it may cause a cyclic wakeup.
+                        // but tokio's cooperative budget will prevent infinite wakeup.
+                        cx.waker().wake_by_ref();
+                    }
+
+                    Poll::Pending
+                }
+
+                Err(err) => Poll::Ready(Err(err)),
+            };
+        }
+    }
+
+    /// Like [`Stream::poll_read_plaintext_buf`], but leaves the plaintext in rustls' buffer,
+    /// to be borrowed with `reader().into_first_chunk()`.
+    ///
+    /// Returns the length of the first chunk of plaintext, which is 0 once the peer has
+    /// closed the connection with `close_notify`.
+    pub fn poll_fill_plaintext(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let mut io_pending = false;
+
+        while !self.eof && self.session.wants_read() {
+            match self.read_io(cx) {
+                Poll::Ready(Ok(0)) => break,
+                Poll::Ready(Ok(_)) => (),
+                Poll::Pending => {
+                    io_pending = true;
+                    break;
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
             }
+        }
 
-            // Rustls doesn't have more data to yield, but it believes the connection is open.
+        match self.session.reader().into_first_chunk() {
+            Ok(chunk) => Poll::Ready(Ok(chunk.len())),
             Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
                 if !io_pending {
-                    // If `wants_read()` is satisfied, rustls will not return `WouldBlock`.
-                    // but if it does, we can try again.
-                    //
-                    // If the rustls state is abnormal, it may cause a cyclic wakeup.
-                    // but tokio's cooperative budget will prevent infinite wakeup.
+                    // See `poll_read_plaintext_buf`.
                     cx.waker().wake_by_ref();
                 }
-
                 Poll::Pending
             }
-
             Err(err) => Poll::Ready(Err(err)),
         }
     }
-}
 
-impl<'a, IO: AsyncRead + AsyncWrite + Unpin, C, SD> AsyncWrite for Stream<'a, IO, C>
-where
-    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
-    SD: SideData,
-{
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context,
+    /// Encrypt plaintext from `buf`, writing records to the IO as it accepts them.
+    ///
+    /// Like `poll_write`, this does not guarantee the data has been sent; call
+    /// [`Stream::poll_flush_io`]. With [`WriteWatermarks`] set, this returns
+    /// `Poll::Pending` while the backlog of ciphertext is too large.
+    pub fn poll_write_plaintext(
+        &mut self,
+        cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
+        let budget = ready!(self.poll_write_budget(cx))?;
+        let buf = &buf[..buf.len().min(budget)];
         let mut pos = 0;
 
         while pos != buf.len() {
             let mut would_block = false;
 
             match self.session.writer().write(&buf[pos..]) {
+                // The buffer limit is too low to take even a byte.
+                Ok(0) if !self.session.wants_write() => return Poll::Ready(Ok(pos)),
                 Ok(n) => pos += n,
                 Err(err) => return Poll::Ready(Err(err)),
             };
@@ -289,7 +697,55 @@ where
         Poll::Ready(Ok(pos))
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+    /// Like [`Stream::poll_write_plaintext`], but encrypts from several buffers at
+    /// once: rustls packs them into as few records as it can, without copying them
+    /// into one buffer first.
+    pub fn poll_write_plaintext_vectored(
+        &mut self,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let budget = ready!(self.poll_write_budget(cx))?;
+        let len = bufs
+            .iter()
+            .fold(0usize, |len, buf| len.saturating_add(buf.len()))
+            .min(budget);
+        let mut pos = 0;
+
+        while pos != len {
+            let mut would_block = false;
+
+            let mut chunks = [IoSlice::new(&[]); 64];
+            let count = slice_io(bufs, pos, len, &mut chunks);
+            match self.session.writer().write_vectored(&chunks[..count]) {
+                Ok(0) if !self.session.wants_write() => return Poll::Ready(Ok(pos)),
+                Ok(n) => pos += n,
+                Err(err) => return Poll::Ready(Err(err)),
+            };
+
+            while self.session.wants_write() {
+                match self.write_io(cx) {
+                    Poll::Ready(Ok(0)) | Poll::Pending => {
+                        would_block = true;
+                        break;
+                    }
+                    Poll::Ready(Ok(_)) => (),
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                }
+            }
+
+            return match (pos, would_block) {
+                (0, true) => Poll::Pending,
+                (n, true) => Poll::Ready(Ok(n)),
+                (_, false) => continue,
+            };
+        }
+
+        Poll::Ready(Ok(pos))
+    }
+
+    /// Write out all pending TLS records, then flush the IO.
+    pub fn poll_flush_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         self.session.writer().flush()?;
         while self.session.wants_write() {
             ready!(self.write_io(cx))?;
@@ -297,7 +753,10 @@ where
         Pin::new(&mut self.io).poll_flush(cx)
     }
 
-    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    /// Write out all pending TLS records, then shut down the IO for writing.
+    ///
+    /// This does not send `close_notify`; queue it on the session first.
+    pub fn poll_shutdown_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         while self.session.wants_write() {
             ready!(self.write_io(cx))?;
         }
@@ -311,6 +770,144 @@ where
     }
 }
 
+impl<'a, IO: AsyncRead + AsyncWrite + Unpin, C, SD> AsyncRead for Stream<'a, IO, C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
+    SD: SideData,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.poll_read_plaintext_buf(cx, buf)
+    }
+}
+
+impl<'a, IO: AsyncRead + AsyncWrite + Unpin, C, SD> AsyncWrite for Stream<'a, IO, C>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
+    SD: SideData,
+{
+    #[inline]
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_plaintext(cx, buf)
+    }
+
+    #[inline]
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_plaintext_vectored(cx, bufs)
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.poll_flush_io(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_shutdown_io(cx)
+    }
+}
+
+/// Reads into the spare capacity of `buf` with `poll_read`, advancing it past the
+/// bytes read.
+#[cfg(feature = "bytes")]
+pub(crate) fn poll_read_buf<R: AsyncRead + ?Sized, B: BufMut>(
+    io: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    buf: &mut B,
+) -> Poll<io::Result<usize>> {
+    if !buf.has_remaining_mut() {
+        return Poll::Ready(Ok(0));
+    }
+
+    let n = {
+        // SAFETY: `ReadBuf` only ever writes initialized bytes to the slice.
+        let dst = unsafe { buf.chunk_mut().as_uninit_slice_mut() };
+        let mut read_buf = ReadBuf::uninit(dst);
+        ready!(io.poll_read(cx, &mut read_buf))?;
+        read_buf.filled().len()
+    };
+
+    // SAFETY: `poll_read` initialized the first `n` bytes of the chunk.
+    unsafe { buf.advance_mut(n) };
+    Poll::Ready(Ok(n))
+}
+
+/// Copies what `poll_fill_buf` returns into `buf`, without consuming it.
+pub(crate) fn poll_peek<R: AsyncBufRead + ?Sized>(
+    io: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+) -> Poll<io::Result<usize>> {
+    let available = ready!(io.poll_fill_buf(cx))?;
+    let n = available.len().min(buf.remaining());
+    buf.put_slice(&available[..n]);
+    Poll::Ready(Ok(n))
+}
+
+/// Copies plaintext from `session` into `buf`, like `session.reader().read(..)`, but
+/// straight into the uninitialized part of `buf`.
+fn read_plaintext<SD: SideData>(
+    session: &mut ConnectionCommon<SD>,
+    buf: &mut ReadBuf<'_>,
+) -> io::Result<usize> {
+    let mut reader = session.reader();
+    let mut copied = 0;
+    while buf.remaining() != 0 {
+        let chunk = match reader.fill_buf() {
+            Ok(chunk) => chunk,
+            Err(_) if copied != 0 => break,
+            Err(err) => return Err(err),
+        };
+        if chunk.is_empty() {
+            break;
+        }
+
+        let n = chunk.len().min(buf.remaining());
+        buf.put_slice(&chunk[..n]);
+        reader.consume(n);
+        copied += n;
+    }
+    Ok(copied)
+}
+
+/// Turns a closure into a future, like `std::future::poll_fn` (which needs Rust 1.64).
+pub(crate) fn poll_fn<T, F>(f: F) -> PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin,
+{
+    PollFn(f)
+}
+
+pub(crate) struct PollFn<F>(F);
+
+impl<T, F> std::future::Future for PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin,
+{
+    type Output = T;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
 /// An adapter that implements a [`Read`] interface for [`AsyncRead`] types and an
 /// associated [`Context`].
 ///
@@ -332,6 +929,30 @@ impl<'a, 'b, T: AsyncRead + Unpin> Read for SyncReadAdapter<'a, 'b, T> {
     }
 }
 
+/// Fills `out` with the bytes of `bufs` from offset `start` up to `end`, returning
+/// how many slices were filled.
+fn slice_io<'a>(
+    bufs: &'a [IoSlice<'_>],
+    start: usize,
+    end: usize,
+    out: &mut [IoSlice<'a>],
+) -> usize {
+    let mut offset = 0;
+    let mut count = 0;
+    for buf in bufs {
+        if count == out.len() || offset >= end {
+            break;
+        }
+        let (from, to) = (start.max(offset), end.min(offset + buf.len()));
+        if from < to {
+            out[count] = IoSlice::new(&buf[from - offset..to - offset]);
+            count += 1;
+        }
+        offset += buf.len();
+    }
+    count
+}
+
 /// An adapter that implements a [`Write`] interface for [`AsyncWrite`] types and an
 /// associated [`Context`].
 ///
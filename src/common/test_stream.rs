@@ -1,5 +1,6 @@
-use std::io::{self, Cursor, Read, Write};
+use std::io::{self, Cursor, IoSlice, Read, Write};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures_util::future::poll_fn;
@@ -7,7 +8,8 @@ use futures_util::task::noop_waker_ref;
 use rustls::{ClientConnection, Connection, ServerConnection};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 
-use super::Stream;
+use super::{ReadAhead, Stream};
+use crate::BufferPool;
 
 struct Good<'a>(&'a mut Connection);
 
@@ -153,6 +155,208 @@ async fn stream_good() -> io::Result<()> {
     Ok(()) as io::Result<()>
 }
 
+#[tokio::test]
+async fn stream_read_ahead() -> io::Result<()> {
+    let data = (0..48 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+
+    let (server, mut client) = make_pair();
+    let mut server = Connection::from(server);
+    poll_fn(|cx| do_handshake(&mut client, &mut server, cx)).await?;
+
+    io::copy(&mut Cursor::new(&data), &mut server.writer())?;
+    server.send_close_notify();
+
+    let mut read_ahead = ReadAhead::new(32 * 1024);
+    let mut good = Good(&mut server);
+    let mut stream = Stream::new(&mut good, &mut client).set_read_ahead(&mut read_ahead);
+
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await?;
+    assert_eq!(buf, data);
+    assert!(!read_ahead.has_buffered());
+
+    Ok(()) as io::Result<()>
+}
+
+#[tokio::test]
+async fn stream_read_ahead_pooled() -> io::Result<()> {
+    let data = (0..48 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+    let pool = Arc::new(BufferPool::new(4));
+
+    for _ in 0..2 {
+        let (server, mut client) = make_pair();
+        let mut server = Connection::from(server);
+        poll_fn(|cx| do_handshake(&mut client, &mut server, cx)).await?;
+
+        io::copy(&mut Cursor::new(&data), &mut server.writer())?;
+        server.send_close_notify();
+
+        let mut read_ahead = ReadAhead::new(32 * 1024).with_pool(Some(pool.clone()));
+        let mut good = Good(&mut server);
+        let mut stream = Stream::new(&mut good, &mut client).set_read_ahead(&mut read_ahead);
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await?;
+        assert_eq!(buf, data);
+
+        // The drained buffer went back to the pool, and is reused next time.
+        assert_eq!(pool.idle(), 1);
+    }
+
+    Ok(()) as io::Result<()>
+}
+
+#[test]
+fn stream_read_ahead_pending() {
+    let pool = Arc::new(BufferPool::new(4));
+    let mut read_ahead = ReadAhead::new(32 * 1024).with_pool(Some(pool.clone()));
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    // The buffer is kept while the IO has nothing to read, not put back on every poll.
+    for _ in 0..2 {
+        assert!(read_ahead.poll_fill(&mut Pending, &mut cx).is_pending());
+        assert_eq!(pool.idle(), 0);
+    }
+
+    drop(read_ahead);
+    assert_eq!(pool.idle(), 1);
+}
+
+#[tokio::test]
+async fn stream_read_uninit() -> io::Result<()> {
+    use std::mem::MaybeUninit;
+
+    let data = (0..20 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+
+    let (server, mut client) = make_pair();
+    let mut server = Connection::from(server);
+    poll_fn(|cx| do_handshake(&mut client, &mut server, cx)).await?;
+
+    io::copy(&mut Cursor::new(&data), &mut server.writer())?;
+    server.send_close_notify();
+
+    let mut good = Good(&mut server);
+    let mut stream = Stream::new(&mut good, &mut client);
+    let mut storage = [MaybeUninit::<u8>::uninit(); 32 * 1024];
+    let mut buf = ReadBuf::uninit(&mut storage);
+    loop {
+        let filled = buf.filled().len();
+        poll_fn(|cx| stream.poll_read_plaintext_buf(cx, &mut buf)).await?;
+        if buf.filled().len() == filled {
+            break;
+        }
+    }
+    assert_eq!(buf.filled(), &data[..]);
+    // Only what was read has been written to.
+    assert_eq!(buf.initialized().len(), data.len());
+
+    Ok(()) as io::Result<()>
+}
+
+#[tokio::test]
+async fn stream_write_vectored() -> io::Result<()> {
+    let (server, mut client) = make_pair();
+    let mut server = Connection::from(server);
+    poll_fn(|cx| do_handshake(&mut client, &mut server, cx)).await?;
+
+    {
+        let mut good = Good(&mut server);
+        let mut stream = Stream::new(&mut good, &mut client);
+        let bufs = [
+            IoSlice::new(b"Hello"),
+            IoSlice::new(b""),
+            IoSlice::new(b" "),
+            IoSlice::new(b"World!"),
+        ];
+        let n = poll_fn(|cx| stream.as_mut_pin().poll_write_vectored(cx, &bufs)).await?;
+        assert_eq!(n, 12);
+        stream.flush().await?;
+    }
+
+    let mut buf = [0; 12];
+    server
+        .process_new_packets()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    server.reader().read_exact(&mut buf)?;
+    assert_eq!(&buf, b"Hello World!");
+
+    let bufs = [
+        IoSlice::new(b"abc"),
+        IoSlice::new(b"defg"),
+        IoSlice::new(b"h"),
+    ];
+    let mut out = [IoSlice::new(&[]); 2];
+    let count = super::slice_io(&bufs, 2, 6, &mut out);
+    assert_eq!(count, 2);
+    assert_eq!((&*out[0], &*out[1]), (&b"c"[..], &b"def"[..]));
+
+    Ok(()) as io::Result<()>
+}
+
+/// Counts the records in each vectored write.
+#[derive(Default)]
+struct Vectored(Vec<usize>);
+
+impl AsyncRead for Vectored {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Vectored {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().0.push(1);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().0.push(bufs.len());
+        Poll::Ready(Ok(bufs.iter().map(|buf| buf.len()).sum()))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn stream_flush_vectored() -> io::Result<()> {
+    let (server, mut client) = make_pair();
+    let mut server = Connection::from(server);
+    poll_fn(|cx| do_handshake(&mut client, &mut server, cx)).await?;
+
+    // Three records, and a fourth with what is left.
+    client.writer().write_all(&[0x42; 3 * 16384 + 1])?;
+
+    let mut vectored = Vectored::default();
+    let mut stream = Stream::new(&mut vectored, &mut client);
+    poll_fn(|cx| stream.write_io(cx)).await?;
+    assert!(!stream.session.wants_write());
+    assert_eq!(vectored.0, [4]);
+
+    Ok(()) as io::Result<()>
+}
+
 #[tokio::test]
 async fn stream_bad() -> io::Result<()> {
     let (server, mut client) = make_pair();
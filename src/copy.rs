@@ -0,0 +1,169 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::common::poll_fn;
+
+/// The largest plaintext a single TLS record carries.
+const RECORD_SIZE: usize = 16 * 1024;
+
+/// Copies data in both directions between `a` and `b` until both reach EOF,
+/// returning the number of bytes copied from `a` to `b` and from `b` to `a`.
+///
+/// This works like `tokio::io::copy_bidirectional`, but is tuned for TLS streams:
+///
+/// - Buffers hold a full TLS record, so each write can fill a record rather than
+///   split one read across two.
+/// - The writer is flushed whenever its reader has nothing more to give, since data
+///   written to a [`TlsStream`](crate::TlsStream) may sit in rustls until flushed.
+/// - Once one side reaches EOF, the other side's write half is shut down, which sends
+///   `close_notify` on a TLS stream, while copying in the other direction continues.
+pub async fn copy_bidirectional<A, B>(a: &mut A, b: &mut B) -> io::Result<(u64, u64)>
+where
+    A: AsyncRead + AsyncWrite + Unpin + ?Sized,
+    B: AsyncRead + AsyncWrite + Unpin + ?Sized,
+{
+    copy_bidirectional_with_sizes(a, b, RECORD_SIZE, RECORD_SIZE).await
+}
+
+/// Like [`copy_bidirectional`], with the buffer sizes for each direction chosen
+/// by the caller.
+///
+/// `a_to_b` is the size of the buffer used to copy from `a` to `b`, and `b_to_a`
+/// the size of the one used to copy from `b` to `a`.
+pub async fn copy_bidirectional_with_sizes<A, B>(
+    a: &mut A,
+    b: &mut B,
+    a_to_b: usize,
+    b_to_a: usize,
+) -> io::Result<(u64, u64)>
+where
+    A: AsyncRead + AsyncWrite + Unpin + ?Sized,
+    B: AsyncRead + AsyncWrite + Unpin + ?Sized,
+{
+    let mut a_to_b = Transfer::Running(CopyBuffer::new(a_to_b));
+    let mut b_to_a = Transfer::Running(CopyBuffer::new(b_to_a));
+
+    poll_fn(|cx| {
+        let a_to_b = a_to_b.poll(cx, a, b)?;
+        let b_to_a = b_to_a.poll(cx, b, a)?;
+
+        match (a_to_b, b_to_a) {
+            (Poll::Ready(a_to_b), Poll::Ready(b_to_a)) => Poll::Ready(Ok((a_to_b, b_to_a))),
+            _ => Poll::Pending,
+        }
+    })
+    .await
+}
+
+enum Transfer {
+    Running(CopyBuffer),
+    ShuttingDown(u64),
+    Done(u64),
+}
+
+impl Transfer {
+    fn poll<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Poll<io::Result<u64>>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        loop {
+            match self {
+                Transfer::Running(buf) => {
+                    let count = ready!(buf.poll_copy(cx, reader, writer))?;
+                    *self = Transfer::ShuttingDown(count);
+                }
+                Transfer::ShuttingDown(count) => {
+                    ready!(Pin::new(&mut *writer).poll_shutdown(cx))?;
+                    *self = Transfer::Done(*count);
+                }
+                Transfer::Done(count) => return Poll::Ready(Ok(*count)),
+            }
+        }
+    }
+}
+
+struct CopyBuffer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    amt: u64,
+    read_done: bool,
+    need_flush: bool,
+}
+
+impl CopyBuffer {
+    fn new(size: usize) -> Self {
+        CopyBuffer {
+            buf: vec![0; size.max(1)].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            amt: 0,
+            read_done: false,
+            need_flush: false,
+        }
+    }
+
+    fn poll_copy<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Poll<io::Result<u64>>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        loop {
+            if self.pos == self.cap && !self.read_done {
+                let mut buf = ReadBuf::new(&mut self.buf);
+                match Pin::new(&mut *reader).poll_read(cx, &mut buf) {
+                    Poll::Ready(result) => result?,
+                    Poll::Pending => {
+                        // Nothing more to read for now: push out what has been written,
+                        // rather than leave it buffered in the writer.
+                        if self.need_flush {
+                            ready!(Pin::new(&mut *writer).poll_flush(cx))?;
+                            self.need_flush = false;
+                        }
+
+                        return Poll::Pending;
+                    }
+                }
+
+                let n = buf.filled().len();
+                if n == 0 {
+                    self.read_done = true;
+                } else {
+                    self.pos = 0;
+                    self.cap = n;
+                }
+            }
+
+            while self.pos < self.cap {
+                let n =
+                    ready!(Pin::new(&mut *writer).poll_write(cx, &self.buf[self.pos..self.cap]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+
+                self.pos += n;
+                self.amt += n as u64;
+                self.need_flush = true;
+            }
+
+            if self.pos == self.cap && self.read_done {
+                ready!(Pin::new(&mut *writer).poll_flush(cx))?;
+                return Poll::Ready(Ok(self.amt));
+            }
+        }
+    }
+}
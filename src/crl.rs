@@ -0,0 +1,341 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use pki_types::{CertificateDer, CertificateRevocationListDer, UnixTime};
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::CryptoProvider;
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::server::{VerifierBuilderError, WebPkiClientVerifier};
+use rustls::{
+    CertificateError, DigitallySignedStruct, DistinguishedName, Error, RootCertStore,
+    SignatureScheme,
+};
+use tokio::sync::Notify;
+use x509_parser::extensions::{DistributionPointName, GeneralName, ParsedExtension};
+
+/// The future returned by [`CrlFetcher::fetch`].
+pub type CrlFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<u8>>> + Send + 'a>>;
+
+/// How a [`CrlVerifier`] downloads CRLs, so it works with any HTTP client.
+pub trait CrlFetcher: Send + Sync {
+    /// GETs `url`, and returns the body of the response, a DER-encoded CRL.
+    fn fetch<'a>(&'a self, url: &'a str) -> CrlFuture<'a>;
+}
+
+/// A client certificate verifier that checks revocation against CRLs it downloads
+/// and keeps fresh, for mTLS servers.
+///
+/// Install it as the client certificate verifier of a `ServerConfig`, and start
+/// [`spawn_refresh`](Self::spawn_refresh). CRLs are downloaded from those added with
+/// [`add_url`](Self::add_url), and from the distribution points named in client
+/// certificates and their intermediates, once the chain is seen to lead to one of the
+/// roots. Certificates are otherwise verified like `WebPkiClientVerifier` does.
+///
+/// Certificates are rejected as long as no CRL of their issuer has been fetched, so
+/// add the distribution points of the CAs up front and [`refresh`](Self::refresh)
+/// before serving, or [`allow_unknown_revocation_status`] to accept them meanwhile.
+///
+/// [`allow_unknown_revocation_status`]: Self::allow_unknown_revocation_status
+pub struct CrlVerifier {
+    roots: Arc<RootCertStore>,
+    provider: Arc<CryptoProvider>,
+    subjects: Vec<DistinguishedName>,
+    fetcher: Arc<dyn CrlFetcher>,
+    allow_unknown: bool,
+    state: RwLock<State>,
+    new_urls: Notify,
+}
+
+struct State {
+    verifier: Arc<dyn ClientCertVerifier>,
+    /// The distribution points, with their CRL once fetched.
+    crls: Vec<(String, Option<Crl>)>,
+    /// How many of `crls` were learned from client certificates.
+    learned: usize,
+}
+
+/// A CRL, with its `nextUpdate`.
+type Crl = (CertificateRevocationListDer<'static>, Option<SystemTime>);
+
+impl CrlVerifier {
+    /// How long to wait before retrying a failed fetch.
+    const RETRY: Duration = Duration::from_secs(5 * 60);
+    /// How often to refresh CRLs without a `nextUpdate`.
+    const DEFAULT_REFRESH: Duration = Duration::from_secs(60 * 60);
+    /// How many distribution points are learned from client certificates at most.
+    const MAX_LEARNED: usize = 32;
+
+    /// Verifies client certificates issued by `roots`, with the algorithms of
+    /// `provider`, downloading CRLs with `fetcher`.
+    pub fn new(
+        roots: Arc<RootCertStore>,
+        provider: Arc<CryptoProvider>,
+        fetcher: Arc<dyn CrlFetcher>,
+    ) -> Result<Self, VerifierBuilderError> {
+        let verifier = build(&roots, &provider, &[], false)?;
+        Ok(Self {
+            subjects: roots.subjects(),
+            roots,
+            provider,
+            fetcher,
+            allow_unknown: false,
+            state: RwLock::new(State {
+                verifier,
+                crls: Vec::new(),
+                learned: 0,
+            }),
+            new_urls: Notify::new(),
+        })
+    }
+
+    /// Accept certificates whose issuer has no CRL fetched yet, instead of rejecting
+    /// them.
+    ///
+    /// This fails open: until the CRLs are downloaded, revoked certificates get
+    /// through too.
+    pub fn allow_unknown_revocation_status(mut self) -> Self {
+        self.allow_unknown = true;
+        let state = self.state.get_mut().unwrap_or_else(|err| err.into_inner());
+        // The same roots and CRLs as the current verifier, so this can't fail.
+        if let Ok(verifier) = build(&self.roots, &self.provider, &state.crls, true) {
+            state.verifier = verifier;
+        }
+        self
+    }
+
+    /// Downloads the CRL at `url` from now on.
+    pub fn add_url(&self, url: impl Into<String>) {
+        self.insert_url(url.into(), false);
+    }
+
+    fn insert_url(&self, url: String, learned: bool) {
+        let mut state = self.state.write().unwrap_or_else(|err| err.into_inner());
+        if state.crls.iter().any(|(known, _)| *known == url) {
+            return;
+        }
+        if learned {
+            if state.learned >= Self::MAX_LEARNED {
+                return;
+            }
+            state.learned += 1;
+        }
+        state.crls.push((url, None));
+        self.new_urls.notify_one();
+    }
+
+    /// Returns the URLs CRLs are downloaded from.
+    pub fn urls(&self) -> Vec<String> {
+        let state = self.state.read().unwrap_or_else(|err| err.into_inner());
+        state.crls.iter().map(|(url, _)| url.clone()).collect()
+    }
+
+    /// Downloads all CRLs again, and checks revocation against them from now on.
+    ///
+    /// CRLs that fail to download or parse keep their previous version; the first
+    /// such error is returned once the others are in use. CRLs that can't be parsed
+    /// fail with an error of kind `InvalidData`.
+    pub async fn refresh(&self) -> io::Result<()> {
+        let mut first_err = None;
+        let mut fetched = Vec::new();
+        for url in self.urls() {
+            let crl = match self.fetcher.fetch(&url).await {
+                Ok(der) => next_update(&der).map(|expiry| (der.into(), expiry)),
+                Err(err) => Err(err),
+            };
+            match crl {
+                Ok(crl) => fetched.push((url, crl)),
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+
+        let mut state = self.state.write().unwrap_or_else(|err| err.into_inner());
+        let mut crls = state.crls.clone();
+        for (url, crl) in fetched {
+            if let Some((_, slot)) = crls.iter_mut().find(|(known, _)| *known == url) {
+                *slot = Some(crl);
+            }
+        }
+        state.verifier = build(&self.roots, &self.provider, &crls, self.allow_unknown)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        state.crls = crls;
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Refresh the CRLs in a task on the current runtime, halfway to their earliest
+    /// `nextUpdate`, and as soon as new distribution points are seen.
+    ///
+    /// Failed downloads are retried every five minutes; CRLs past their `nextUpdate`
+    /// are still checked against until a new version is fetched. Abort the returned
+    /// handle to stop refreshing.
+    pub fn spawn_refresh(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let wait = match self.refresh().await {
+                    Ok(()) => self.next_refresh(),
+                    Err(_) => Self::RETRY,
+                };
+                let _ = tokio::time::timeout(wait, self.new_urls.notified()).await;
+            }
+        })
+    }
+
+    fn next_refresh(&self) -> Duration {
+        let state = self.state.read().unwrap_or_else(|err| err.into_inner());
+        state
+            .crls
+            .iter()
+            .filter_map(|(_, crl)| crl.as_ref().and_then(|(_, expiry)| *expiry))
+            .map(|expiry| {
+                expiry
+                    .duration_since(SystemTime::now())
+                    .map_or(Self::RETRY, |left| (left / 2).max(Self::RETRY))
+            })
+            .min()
+            .unwrap_or(Self::DEFAULT_REFRESH)
+    }
+
+    fn current(&self) -> Arc<dyn ClientCertVerifier> {
+        self.state
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .verifier
+            .clone()
+    }
+}
+
+impl ClientCertVerifier for CrlVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &self.subjects
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, Error> {
+        let (verifier, unchecked) = {
+            let state = self.state.read().unwrap_or_else(|err| err.into_inner());
+            let unchecked = state.crls.iter().all(|(_, crl)| crl.is_none());
+            (state.verifier.clone(), unchecked)
+        };
+        let mut verified = verifier.verify_client_cert(end_entity, intermediates, now);
+
+        // Revocation is only checked once the chain is known to lead to a root, so
+        // only then are its distribution points worth fetching.
+        if matches!(
+            verified,
+            Ok(_)
+                | Err(Error::InvalidCertificate(
+                    CertificateError::UnknownRevocationStatus
+                ))
+        ) {
+            for cert in std::iter::once(end_entity).chain(intermediates) {
+                for url in distribution_points(cert) {
+                    self.insert_url(url, true);
+                }
+            }
+        }
+
+        // Without any CRL, rustls skips revocation checks rather than failing them.
+        if unchecked && !self.allow_unknown && verified.is_ok() {
+            verified = Err(Error::InvalidCertificate(
+                CertificateError::UnknownRevocationStatus,
+            ));
+        }
+        verified
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.current().verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.current().verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.current().supported_verify_schemes()
+    }
+}
+
+impl fmt::Debug for CrlVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CrlVerifier")
+            .field("urls", &self.urls())
+            .finish_non_exhaustive()
+    }
+}
+
+fn build(
+    roots: &Arc<RootCertStore>,
+    provider: &Arc<CryptoProvider>,
+    crls: &[(String, Option<Crl>)],
+    allow_unknown: bool,
+) -> Result<Arc<dyn ClientCertVerifier>, VerifierBuilderError> {
+    let crls = crls
+        .iter()
+        .filter_map(|(_, crl)| crl.as_ref().map(|(der, _)| der.clone()));
+    let builder = WebPkiClientVerifier::builder_with_provider(roots.clone(), provider.clone())
+        .with_crls(crls);
+    match allow_unknown {
+        true => builder.allow_unknown_revocation_status().build(),
+        false => builder.build(),
+    }
+}
+
+/// Returns the HTTP URLs of the CRL distribution points of `cert`.
+fn distribution_points(cert: &[u8]) -> Vec<String> {
+    let cert = match x509_parser::parse_x509_certificate(cert) {
+        Ok((_, cert)) => cert,
+        Err(_) => return Vec::new(),
+    };
+    cert.extensions()
+        .iter()
+        .filter_map(|ext| match ext.parsed_extension() {
+            ParsedExtension::CRLDistributionPoints(points) => Some(points),
+            _ => None,
+        })
+        .flat_map(|points| points.iter())
+        .filter_map(|point| match &point.distribution_point {
+            Some(DistributionPointName::FullName(names)) => Some(names),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|name| match name {
+            GeneralName::URI(url) if url.starts_with("http") => Some(url.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the `nextUpdate` of a DER-encoded CRL, if it has one.
+fn next_update(der: &[u8]) -> io::Result<Option<SystemTime>> {
+    let (_, crl) = x509_parser::parse_x509_crl(der)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(crl
+        .next_update()
+        .map(|time| match u64::try_from(time.timestamp()) {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_) => UNIX_EPOCH,
+        }))
+}
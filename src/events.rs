@@ -0,0 +1,77 @@
+use std::io;
+use std::task::Poll;
+
+use rustls::AlertDescription;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::common::EofReason;
+
+/// TLS-level activity on an established stream, see
+/// [`client::TlsStream::subscribe`](crate::client::TlsStream::subscribe) and
+/// [`server::TlsStream::subscribe`](crate::server::TlsStream::subscribe).
+///
+/// Events are observed as the stream is read from, so they are delivered no earlier
+/// than the application data around them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StreamEvent {
+    /// A client received a TLS 1.3 session ticket.
+    ///
+    /// Servers issue their tickets as part of the handshake, before a stream can be
+    /// subscribed to.
+    TicketReceived,
+    /// The traffic keys were updated with `refresh_traffic_keys`.
+    KeyUpdate,
+    /// The peer closed its side of the connection with `close_notify`.
+    CloseNotify,
+    /// The peer sent a fatal alert.
+    AlertReceived(AlertDescription),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Events {
+    sender: Option<UnboundedSender<StreamEvent>>,
+    tickets: u32,
+}
+
+impl Events {
+    pub(crate) fn subscribe(&mut self) -> UnboundedReceiver<StreamEvent> {
+        let (sender, receiver) = unbounded_channel();
+        self.sender = Some(sender);
+        receiver
+    }
+
+    pub(crate) fn emit(&self, event: StreamEvent) {
+        if let Some(sender) = &self.sender {
+            // The subscriber may have gone away; that's fine.
+            let _ = sender.send(event);
+        }
+    }
+
+    pub(crate) fn tickets_received(&mut self, tickets: u32) {
+        for _ in self.tickets..tickets {
+            self.emit(StreamEvent::TicketReceived);
+        }
+        self.tickets = tickets;
+    }
+
+    pub(crate) fn after_read<T>(
+        &self,
+        output: &Poll<io::Result<T>>,
+        eof_before: Option<EofReason>,
+        eof_after: Option<EofReason>,
+    ) {
+        if eof_before.is_none() && eof_after == Some(EofReason::CloseNotify) {
+            self.emit(StreamEvent::CloseNotify);
+        }
+
+        if let Poll::Ready(Err(err)) = output {
+            let alert = err
+                .get_ref()
+                .and_then(|err| err.downcast_ref::<rustls::Error>());
+            if let Some(rustls::Error::AlertReceived(description)) = alert {
+                self.emit(StreamEvent::AlertReceived(*description));
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use pki_types::CertificateDer;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+use crate::PeerCertificate;
+
+/// The validity of a certificate watched by a [`CertExpiryMonitor`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CertExpiry {
+    /// The subject distinguished name.
+    pub subject: String,
+    /// The subject alternative names, as in `DNS:example.com`.
+    pub sans: Vec<String>,
+    /// The SHA-256 fingerprint of the DER certificate, as lowercase hex.
+    pub fingerprint: String,
+    /// End of the validity period, in seconds since the Unix epoch.
+    pub not_after: i64,
+}
+
+/// Watches the end-entity certificates an acceptor serves, and reports those that
+/// expire within a warning window.
+///
+/// rustls certificate resolvers can't be enumerated, so certificates are picked up
+/// the first time an acceptor created with [`TlsAcceptor::monitor_expiry`](crate::TlsAcceptor::monitor_expiry)
+/// serves them. Register certificates up front with [`CertExpiryMonitor::watch`] to
+/// have them checked before any client connects.
+pub struct CertExpiryMonitor {
+    window: Duration,
+    on_expiring: Box<dyn Fn(&CertExpiry) + Send + Sync>,
+    certs: Mutex<Vec<(CertificateDer<'static>, CertExpiry)>>,
+}
+
+impl CertExpiryMonitor {
+    /// Report certificates that expire within `window`, or have expired, to `on_expiring`.
+    ///
+    /// `on_expiring` is called when such a certificate is first seen, and on every
+    /// [`CertExpiryMonitor::check`].
+    pub fn new(
+        window: Duration,
+        on_expiring: impl Fn(&CertExpiry) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            window,
+            on_expiring: Box::new(on_expiring),
+            certs: Mutex::default(),
+        }
+    }
+
+    /// Start watching `cert`.
+    pub fn watch(&self, cert: &CertificateDer<'_>) -> io::Result<()> {
+        let mut certs = self.lock();
+        if certs.iter().any(|(der, _)| der == cert) {
+            return Ok(());
+        }
+
+        let info = PeerCertificate::parse(cert, false)?;
+        let expiry = CertExpiry {
+            subject: info.subject,
+            sans: info.sans,
+            fingerprint: info.fingerprint,
+            not_after: info.not_after,
+        };
+        let expiring = self.is_expiring(&expiry);
+        certs.push((cert.clone().into_owned(), expiry.clone()));
+        drop(certs);
+
+        if expiring {
+            (self.on_expiring)(&expiry);
+        }
+        Ok(())
+    }
+
+    /// Returns the watched certificates.
+    pub fn certificates(&self) -> Vec<CertExpiry> {
+        self.lock()
+            .iter()
+            .map(|(_, expiry)| expiry.clone())
+            .collect()
+    }
+
+    /// Report the watched certificates that now expire within the window, and return them.
+    ///
+    /// Call this periodically to catch certificates that were fine when first seen.
+    pub fn check(&self) -> Vec<CertExpiry> {
+        let expiring = self
+            .lock()
+            .iter()
+            .filter(|(_, expiry)| self.is_expiring(expiry))
+            .map(|(_, expiry)| expiry.clone())
+            .collect::<Vec<_>>();
+
+        for expiry in &expiring {
+            (self.on_expiring)(expiry);
+        }
+        expiring
+    }
+
+    fn is_expiring(&self, expiry: &CertExpiry) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let deadline = now.saturating_add(self.window).as_secs();
+        expiry.not_after <= i64::try_from(deadline).unwrap_or(i64::MAX)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(CertificateDer<'static>, CertExpiry)>> {
+        self.certs.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Registers the certificates a resolver hands out with a [`CertExpiryMonitor`].
+#[derive(Debug)]
+pub(crate) struct MonitoredResolver {
+    pub(crate) inner: Arc<dyn ResolvesServerCert>,
+    pub(crate) monitor: Arc<CertExpiryMonitor>,
+}
+
+impl ResolvesServerCert for MonitoredResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let key = self.inner.resolve(client_hello)?;
+        if let Ok(cert) = key.end_entity_cert() {
+            // A certificate that fails to parse is served regardless; the handshake
+            // is not the place to report it.
+            let _ = self.monitor.watch(cert);
+        }
+        Some(key)
+    }
+
+    fn only_raw_public_keys(&self) -> bool {
+        self.inner.only_raw_public_keys()
+    }
+}
+
+impl std::fmt::Debug for CertExpiryMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertExpiryMonitor")
+            .field("window", &self.window)
+            .field("certs", &self.certificates())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,149 @@
+//! Deterministic entry points for fuzzing the stream state machines.
+//!
+//! Each function drives a handshake and the following reads and writes over an
+//! in-memory IO fed from `data`, without sockets, timers or a runtime. Whatever the
+//! input, they must return without panicking or stalling; a future that returns
+//! `Pending` although its IO never blocks is reported as a panic, since in a real
+//! program it would never be woken again.
+//!
+//! The first byte of `data` sets the largest read the IO serves at once (1 to 256
+//! bytes), so the fuzzer also explores how records are split across reads.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use rustls::server::Acceptor;
+use rustls::{ClientConfig, ServerConfig};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::common::poll_fn;
+use crate::{LazyConfigAcceptor, TlsAcceptor, TlsConnector};
+
+/// Accept a connection whose client sends `data`, then read from it until EOF.
+pub fn server(config: Arc<ServerConfig>, data: &[u8]) {
+    let acceptor = TlsAcceptor::from(config);
+    run(async move {
+        if let Ok(stream) = acceptor.accept(FuzzIo::new(data)).await {
+            exercise(stream).await;
+        }
+    });
+}
+
+/// Read a ClientHello from `data` with a [`LazyConfigAcceptor`], then complete the
+/// handshake with `config` and read until EOF.
+pub fn lazy_server(config: Arc<ServerConfig>, data: &[u8]) {
+    run(async move {
+        let start = match LazyConfigAcceptor::new(Acceptor::default(), FuzzIo::new(data)).await {
+            Ok(start) => start,
+            Err(_) => return,
+        };
+        let _ = start.client_hello().server_name();
+        if let Ok(stream) = start.into_stream(config).await {
+            exercise(stream).await;
+        }
+    });
+}
+
+/// Connect to `example.com` over a connection whose server sends `data`, then read
+/// from it until EOF.
+pub fn client(config: Arc<ClientConfig>, data: &[u8]) {
+    let connector = TlsConnector::from(config);
+    let domain = pki_types::ServerName::try_from("example.com").unwrap();
+    run(async move {
+        if let Ok(stream) = connector.connect(domain, FuzzIo::new(data)).await {
+            exercise(stream).await;
+        }
+    });
+}
+
+async fn exercise<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) {
+    let _ = poll_fn(|cx| Pin::new(&mut stream).poll_write(cx, b"fuzz")).await;
+    let _ = poll_fn(|cx| Pin::new(&mut stream).poll_flush(cx)).await;
+
+    let mut buf = [0; 1024];
+    loop {
+        let mut buf = ReadBuf::new(&mut buf);
+        match poll_fn(|cx| Pin::new(&mut stream).poll_read(cx, &mut buf)).await {
+            Ok(()) if !buf.filled().is_empty() => continue,
+            _ => break,
+        }
+    }
+
+    let _ = poll_fn(|cx| Pin::new(&mut stream).poll_shutdown(cx)).await;
+}
+
+fn run(future: impl Future<Output = ()>) {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    if future.as_mut().poll(&mut cx).is_pending() {
+        panic!("stalled although the IO never blocks");
+    }
+}
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    // Safety: the vtable functions ignore the data pointer.
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+/// Serves reads from the input in chunks, and discards writes.
+struct FuzzIo<'a> {
+    input: &'a [u8],
+    chunk: usize,
+}
+
+impl<'a> FuzzIo<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        match data.split_first() {
+            Some((chunk, input)) => FuzzIo {
+                input,
+                chunk: usize::from(*chunk) + 1,
+            },
+            None => FuzzIo {
+                input: data,
+                chunk: 1,
+            },
+        }
+    }
+}
+
+impl AsyncRead for FuzzIo<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let len = self.chunk.min(buf.remaining()).min(self.input.len());
+        let (chunk, rest) = self.input.split_at(len);
+        buf.put_slice(chunk);
+        self.input = rest;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for FuzzIo<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use pki_types::CertificateDer;
+use tonic::transport::server::Connected;
+
+use crate::{server, AlpnProtocol};
+
+/// The TLS details of a connection served by tonic, in the extensions of its
+/// requests.
+///
+/// Servers serving [`server::TlsStream`]s, with `Server::serve_with_incoming`, find
+/// it with `request.extensions().get::<TlsConnectInfo<TcpConnectInfo>>()` for TCP
+/// connections. tonic's `Request::peer_certs` only knows its own TLS streams; use
+/// [`peer_certificates`](Self::peer_certificates) instead.
+#[derive(Clone, Debug)]
+pub struct TlsConnectInfo<T> {
+    inner: T,
+    certs: Option<Arc<[CertificateDer<'static>]>>,
+    server_name: Option<Arc<str>>,
+    alpn_protocol: Option<AlpnProtocol>,
+}
+
+impl<T> TlsConnectInfo<T> {
+    /// Returns the connect info of the IO, like the addresses of a TCP connection.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the certificate chain the client presented, end-entity certificate
+    /// first.
+    pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        self.certs.as_deref()
+    }
+
+    /// Returns the server name the client sent with SNI.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Returns the protocol negotiated with ALPN.
+    pub fn alpn_protocol(&self) -> Option<&AlpnProtocol> {
+        self.alpn_protocol.as_ref()
+    }
+}
+
+impl<IO: Connected> Connected for server::TlsStream<IO> {
+    type ConnectInfo = TlsConnectInfo<IO::ConnectInfo>;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        let (io, session) = self.get_ref();
+        TlsConnectInfo {
+            inner: io.connect_info(),
+            certs: session.peer_certificates().map(Arc::from),
+            server_name: session.server_name().map(Arc::from),
+            alpn_protocol: self.alpn_protocol(),
+        }
+    }
+}
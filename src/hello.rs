@@ -0,0 +1,188 @@
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{CipherSuite, NamedGroup, SignatureScheme};
+
+/// What a client offered in its ClientHello, see
+/// [`TlsAcceptor::capture_client_hello`](crate::TlsAcceptor::capture_client_hello).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientHelloSummary {
+    /// The server name sent with SNI, if any.
+    pub server_name: Option<String>,
+    /// The ALPN protocols offered, in the client's order of preference.
+    pub alpn_protocols: Vec<Vec<u8>>,
+    /// The cipher suites offered, in the client's order of preference.
+    pub cipher_suites: Vec<CipherSuite>,
+    /// The signature schemes the client can verify.
+    pub signature_schemes: Vec<SignatureScheme>,
+    /// The key exchange groups offered, if the client sent the extension.
+    pub named_groups: Option<Vec<NamedGroup>>,
+    /// The offered cipher suites, signature schemes, groups and ALPN protocols as
+    /// one string, to tell client implementations apart.
+    ///
+    /// Lists are separated by `-`, and their entries by `,`; cipher suites, schemes
+    /// and groups are written as decimal code points, without GREASE values.
+    pub fingerprint: String,
+}
+
+impl From<&ClientHello<'_>> for ClientHelloSummary {
+    fn from(client_hello: &ClientHello<'_>) -> Self {
+        let alpn_protocols: Vec<Vec<u8>> = client_hello
+            .alpn()
+            .map(|protocols| protocols.map(<[u8]>::to_vec).collect())
+            .unwrap_or_default();
+        let cipher_suites = client_hello.cipher_suites().to_vec();
+        let signature_schemes = client_hello.signature_schemes().to_vec();
+        let named_groups = client_hello.named_groups().map(<[NamedGroup]>::to_vec);
+
+        let mut fingerprint = String::new();
+        write_codes(
+            &mut fingerprint,
+            cipher_suites.iter().map(|&s| u16::from(s)),
+        );
+        fingerprint.push('-');
+        write_codes(
+            &mut fingerprint,
+            signature_schemes.iter().map(|&s| u16::from(s)),
+        );
+        fingerprint.push('-');
+        let groups = named_groups.iter().flatten().map(|&g| u16::from(g));
+        write_codes(&mut fingerprint, groups);
+        fingerprint.push('-');
+        for (i, protocol) in alpn_protocols.iter().enumerate() {
+            if i > 0 {
+                fingerprint.push(',');
+            }
+            fingerprint.push_str(&String::from_utf8_lossy(protocol));
+        }
+
+        Self {
+            server_name: client_hello.server_name().map(str::to_owned),
+            alpn_protocols,
+            cipher_suites,
+            signature_schemes,
+            named_groups,
+            fingerprint,
+        }
+    }
+}
+
+fn write_codes(out: &mut String, codes: impl Iterator<Item = u16>) {
+    // GREASE values (RFC 8701) are random per connection.
+    let codes = codes.filter(|code| code & 0x0f0f != 0x0a0a || code >> 8 != code & 0xff);
+    for (i, code) in codes.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{}", code);
+    }
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Option<ClientHelloSummary>>> = const { RefCell::new(None) };
+}
+
+/// The ClientHello summary of one handshake, filled in while the handshake is polled.
+///
+/// rustls only shows the ClientHello to the certificate resolver, which has no way to
+/// tell connections apart. The resolver runs synchronously while a handshake
+/// processes the ClientHello, so [`CapturingResolver`] hands the summary over to the
+/// handshake being polled on the same thread.
+#[derive(Debug, Default)]
+pub(crate) struct HelloCapture {
+    enabled: bool,
+    summary: Option<ClientHelloSummary>,
+}
+
+impl HelloCapture {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            summary: None,
+        }
+    }
+
+    pub(crate) fn poll<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return f();
+        }
+
+        CAPTURED.with(|captured| *captured.borrow_mut() = Some(None));
+        let output = f();
+        // After a HelloRetryRequest the resolver sees the second ClientHello.
+        if let Some(summary) = CAPTURED.with(|captured| captured.borrow_mut().take().flatten()) {
+            self.summary = Some(summary);
+        }
+        output
+    }
+
+    pub(crate) fn take(&mut self) -> Option<ClientHelloSummary> {
+        self.summary.take()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CapturingResolver {
+    pub(crate) inner: Arc<dyn ResolvesServerCert>,
+}
+
+impl ResolvesServerCert for CapturingResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        CAPTURED.with(|captured| {
+            if let Some(slot) = captured.borrow_mut().as_mut() {
+                *slot = Some(ClientHelloSummary::from(&client_hello));
+            }
+        });
+        self.inner.resolve(client_hello)
+    }
+
+    fn only_raw_public_keys(&self) -> bool {
+        self.inner.only_raw_public_keys()
+    }
+}
+
+/// Reads through `inner`, keeping a copy of everything read in `copy`.
+pub(crate) struct TeeReader<'a, R> {
+    pub(crate) inner: R,
+    pub(crate) copy: &'a mut Vec<u8>,
+}
+
+impl<R: Read> Read for TeeReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.copy.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Extracts the first handshake message, header included, from the TLS records in
+/// `records`.
+///
+/// Returns as much of the message as `records` holds, which is all of it for records
+/// rustls accepted a ClientHello from.
+pub(crate) fn first_handshake_message(mut records: &[u8]) -> Vec<u8> {
+    const HANDSHAKE: u8 = 22;
+
+    let mut message = Vec::new();
+    while records.len() >= 5 {
+        let len = usize::from(u16::from_be_bytes([records[3], records[4]]));
+        let fragment = &records[5..records.len().min(5 + len)];
+        if records[0] == HANDSHAKE {
+            message.extend_from_slice(fragment);
+        }
+        records = &records[5 + fragment.len()..];
+
+        if message.len() >= 4 {
+            let len = u32::from_be_bytes([0, message[1], message[2], message[3]]) as usize;
+            if message.len() >= 4 + len {
+                message.truncate(4 + len);
+                break;
+            }
+        }
+    }
+    message
+}
@@ -0,0 +1,216 @@
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use http::Uri;
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+use hyper_util::rt::TokioIo;
+use tower_service::Service;
+
+use crate::{client, AlpnProtocol, TlsConnector};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// A connector for the hyper 1.x client in `hyper-util`, that speaks TLS to `https`
+/// URIs.
+///
+/// The connection itself is made by another connector, [`HttpConnector`] by default,
+/// and the TLS handshake is done with a [`TlsConnector`] on top of it, using the host
+/// of the URI as the server name.
+///
+/// If the client config offers no ALPN protocols, the connector offers HTTP/1.1;
+/// call [`HttpsConnector::enable_http2`] to offer HTTP/2 as well. Connections that
+/// negotiated `h2` are reported to hyper as such.
+#[derive(Clone)]
+pub struct HttpsConnector<C = HttpConnector> {
+    http: C,
+    tls: TlsConnector,
+    https_only: bool,
+}
+
+impl HttpsConnector {
+    /// Connects over TCP with a default [`HttpConnector`].
+    pub fn new(tls: TlsConnector) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        Self::with_http(http, tls)
+    }
+}
+
+impl<C> HttpsConnector<C> {
+    /// Connects with `http`, which has to accept `https` URIs too.
+    ///
+    /// For an [`HttpConnector`], that means calling `enforce_http(false)`.
+    pub fn with_http(http: C, tls: TlsConnector) -> Self {
+        let tls = match tls.config().alpn_protocols.is_empty() {
+            true => tls.alpn_protocols(&[AlpnProtocol::Http11]),
+            false => tls,
+        };
+        Self {
+            http,
+            tls,
+            https_only: false,
+        }
+    }
+
+    /// Offer HTTP/2 with ALPN, falling back to HTTP/1.1.
+    ///
+    /// The hyper client has to support HTTP/2 for servers that pick it.
+    pub fn enable_http2(mut self) -> Self {
+        self.tls = self.tls.alpn_protocols(AlpnProtocol::HTTP);
+        self
+    }
+
+    /// Refuse to connect to `http` URIs.
+    pub fn https_only(mut self, enable: bool) -> Self {
+        self.https_only = enable;
+        self
+    }
+}
+
+impl<C> Service<Uri> for HttpsConnector<C>
+where
+    C: Service<Uri>,
+    C::Response: Read + Write + Connection + Unpin + Send + 'static,
+    C::Future: Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = MaybeHttpsStream<C::Response>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let domain = match uri.scheme_str() {
+            Some("https") => match server_name(&uri) {
+                Ok(domain) => Some(domain),
+                Err(err) => return Box::pin(async move { Err(err.into()) }),
+            },
+            Some("http") if !self.https_only => None,
+            _ => {
+                let err = io::Error::new(io::ErrorKind::InvalidInput, "unsupported URI scheme");
+                return Box::pin(async move { Err(err.into()) });
+            }
+        };
+
+        let connecting = self.http.call(uri);
+        let tls = self.tls.clone();
+        Box::pin(async move {
+            let io = connecting.await.map_err(Into::into)?;
+            match domain {
+                Some(domain) => {
+                    let stream = tls.connect(domain, TokioIo::new(io)).await?;
+                    Ok(MaybeHttpsStream::Https(TokioIo::new(stream)))
+                }
+                None => Ok(MaybeHttpsStream::Http(io)),
+            }
+        })
+    }
+}
+
+fn server_name(uri: &Uri) -> io::Result<pki_types::ServerName<'static>> {
+    let host = uri
+        .host()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URI has no host"))?;
+    // IPv6 addresses are bracketed in URIs.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    pki_types::ServerName::try_from(host.to_owned())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+/// A connection made by [`HttpsConnector`]: TLS for `https` URIs, plain for `http`.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum MaybeHttpsStream<T> {
+    Http(T),
+    Https(TokioIo<client::TlsStream<TokioIo<T>>>),
+}
+
+impl<T: Connection + Read + Write + Unpin> Connection for MaybeHttpsStream<T> {
+    fn connected(&self) -> Connected {
+        match self {
+            MaybeHttpsStream::Http(io) => io.connected(),
+            MaybeHttpsStream::Https(stream) => stream.connected(),
+        }
+    }
+}
+
+/// Reports the connection of the IO, as negotiating HTTP/2 if the server picked
+/// `h2` with ALPN.
+///
+/// With this, `TokioIo<client::TlsStream<TcpStream>>` can be returned by a connector
+/// for the `hyper-util` client.
+impl<IO: Connection> Connection for client::TlsStream<IO> {
+    fn connected(&self) -> Connected {
+        let (io, session) = self.get_ref();
+        let connected = io.connected();
+        match session.alpn_protocol() {
+            Some(b"h2") => connected.negotiated_h2(),
+            _ => connected,
+        }
+    }
+}
+
+impl<T: Read + Write + Unpin> Read for MaybeHttpsStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeHttpsStream::Http(io) => Pin::new(io).poll_read(cx, buf),
+            MaybeHttpsStream::Https(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<T: Read + Write + Unpin> Write for MaybeHttpsStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeHttpsStream::Http(io) => Pin::new(io).poll_write(cx, buf),
+            MaybeHttpsStream::Https(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeHttpsStream::Http(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+            MaybeHttpsStream::Https(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            MaybeHttpsStream::Http(io) => io.is_write_vectored(),
+            MaybeHttpsStream::Https(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeHttpsStream::Http(io) => Pin::new(io).poll_flush(cx),
+            MaybeHttpsStream::Https(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeHttpsStream::Http(io) => Pin::new(io).poll_shutdown(cx),
+            MaybeHttpsStream::Https(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
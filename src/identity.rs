@@ -0,0 +1,119 @@
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use pki_types::pem::PemObject;
+use pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::client::ResolvesClientCert;
+use rustls::crypto::CryptoProvider;
+use rustls::sign::CertifiedKey;
+use rustls::SignatureScheme;
+
+/// A client certificate that can be replaced while in use.
+///
+/// Install it with [`TlsConnector::client_identity`](crate::TlsConnector::client_identity);
+/// every new connection then presents whatever identity is current at the time, so
+/// short-lived certificates can rotate without rebuilding connectors. Updates are
+/// pushed with [`set`](Self::set) or [`load_pem_files`](Self::load_pem_files), or
+/// picked up from disk by [`watch_files`](Self::watch_files).
+#[derive(Debug, Default)]
+pub struct ReloadingClientCert {
+    identity: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl ReloadingClientCert {
+    pub fn new(identity: Arc<CertifiedKey>) -> Self {
+        ReloadingClientCert {
+            identity: RwLock::new(Some(identity)),
+        }
+    }
+
+    /// Returns the identity presented to servers from now on.
+    pub fn current(&self) -> Option<Arc<CertifiedKey>> {
+        self.identity
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// Present `identity` on new connections.
+    pub fn set(&self, identity: Arc<CertifiedKey>) {
+        *self.identity.write().unwrap_or_else(|err| err.into_inner()) = Some(identity);
+    }
+
+    /// Read a PEM certificate chain and private key, and present them on new connections.
+    ///
+    /// The current identity is kept if either file cannot be loaded.
+    pub fn load_pem_files(
+        &self,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+        provider: &CryptoProvider,
+    ) -> io::Result<()> {
+        fn invalid(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        }
+
+        let certs = CertificateDer::pem_file_iter(cert)
+            .map_err(invalid)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let key = PrivateKeyDer::from_pem_file(key).map_err(invalid)?;
+        let key = provider
+            .key_provider
+            .load_private_key(key)
+            .map_err(invalid)?;
+
+        self.set(Arc::new(CertifiedKey::new(certs, key)));
+        Ok(())
+    }
+
+    /// Reload the identity from `cert` and `key` whenever either file changes,
+    /// checking every `interval` in a task on the current runtime.
+    ///
+    /// Files that fail to load, for example while only one of them has been
+    /// replaced, are retried on the next change. Abort the returned handle to stop
+    /// watching.
+    #[cfg(feature = "reload")]
+    pub fn watch_files(
+        self: Arc<Self>,
+        cert: std::path::PathBuf,
+        key: std::path::PathBuf,
+        provider: Arc<CryptoProvider>,
+        interval: std::time::Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        use std::time::SystemTime;
+
+        fn modified(path: &Path) -> Option<SystemTime> {
+            std::fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .ok()
+        }
+
+        tokio::spawn(async move {
+            let mut seen = None;
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let current = (modified(&cert), modified(&key));
+                if seen != Some(current) && self.load_pem_files(&cert, &key, &provider).is_ok() {
+                    seen = Some(current);
+                }
+            }
+        })
+    }
+}
+
+impl ResolvesClientCert for ReloadingClientCert {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        self.current()
+    }
+
+    fn has_certs(&self) -> bool {
+        self.current().is_some()
+    }
+}
@@ -36,19 +36,44 @@
 //!
 //! see <https://github.com/tokio-rs/tls/issues/41>
 
+use std::collections::HashMap;
 use std::future::Future;
 use std::io;
+use std::mem;
 #[cfg(unix)]
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+// `std::os::wasi::io` is still unstable on wasip2; `std::os::fd` is not.
+#[cfg(target_os = "wasi")]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 #[cfg(windows)]
-use std::os::windows::io::{AsRawSocket, RawSocket};
+use std::os::windows::io::{AsRawSocket, AsSocket, BorrowedSocket, RawSocket};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
+#[cfg(feature = "timeout")]
+use std::time::Duration;
 
+#[cfg(feature = "bytes")]
+use bytes::{Buf, BufMut};
 pub use rustls;
-use rustls::{ClientConfig, ClientConnection, CommonState, ServerConfig, ServerConnection};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use rustls::client::danger::ServerCertVerifier;
+use rustls::server::ProducesTickets;
+use rustls::{
+    AlertDescription, ClientConfig, ClientConnection, CommonState, ServerConfig, ServerConnection,
+    Side,
+};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+#[cfg(feature = "cancel")]
+use tokio_util::sync::CancellationToken;
+
+/// Emits a `tracing` event at `$level` with the `tracing` feature, and nothing
+/// without it.
+macro_rules! event {
+    ( $level:ident, $($arg:tt)+ ) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
 
 macro_rules! ready {
     ( $e:expr ) => {
@@ -59,38 +84,244 @@ macro_rules! ready {
     };
 }
 
+mod acme;
+pub use acme::{AcmeAccept, AcmeAcceptor, AcmeChallenges, ChallengeStore};
+mod alpn;
+pub use alpn::{AlpnAccept, AlpnAcceptor, AlpnProtocol};
+mod audit;
+use audit::Audit;
+pub use audit::{AuditEvent, AuditKind};
+#[cfg(feature = "blocking")]
+mod blocking;
+#[cfg(feature = "cancel")]
+mod cancel;
+#[cfg(feature = "blocking")]
+pub use blocking::SyncTlsStream;
+#[cfg(feature = "cancel")]
+use cancel::Cancel;
 pub mod client;
 mod common;
-use common::{MidHandshake, TlsState};
+mod copy;
+#[cfg(feature = "crl")]
+mod crl;
+#[cfg(feature = "crl")]
+pub use crl::{CrlFetcher, CrlFuture, CrlVerifier};
+#[cfg(feature = "events")]
+mod events;
+#[cfg(feature = "events")]
+pub use events::StreamEvent;
+#[cfg(feature = "x509")]
+mod expiry;
+#[cfg(feature = "x509")]
+pub use expiry::{CertExpiry, CertExpiryMonitor};
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+#[cfg(feature = "tonic")]
+mod grpc;
+#[cfg(feature = "tonic")]
+pub use grpc::TlsConnectInfo;
+mod hello;
+#[cfg(feature = "hyper")]
+mod https;
+pub use hello::ClientHelloSummary;
+use hello::HelloCapture;
+#[cfg(feature = "hyper")]
+pub use https::{HttpsConnector, MaybeHttpsStream};
+mod identity;
+pub use identity::ReloadingClientCert;
+#[cfg(feature = "listener")]
+mod listener;
+use common::poll_fn;
+pub use common::{ConnectionState, EofReason};
+use common::{IoSession, MidHandshake, ReadAhead, TlsState, WriteWatermarks, DEFAULT_BUFFER_LIMIT};
+pub use copy::{copy_bidirectional, copy_bidirectional_with_sizes};
+#[cfg(feature = "listener")]
+pub use listener::{ErrorPolicy, LazyTlsListener, TlsConnection, TlsListener};
+mod limit;
+use limit::RateLimit;
+pub use limit::RateLimited;
+mod maybe;
+pub use maybe::MaybeTlsStream;
+mod metrics;
+mod observer;
+use observer::Observe;
+pub use observer::{CompletedHandshake, FailedHandshake, HandshakeObserver};
+#[cfg(feature = "x509")]
+mod peer_cert;
+pub use metrics::{AcceptorStats, ResumptionMetrics, SniMetrics};
+use metrics::{Metrics, SniTable, SniTracker, Stats, StatsTracker};
+#[cfg(feature = "x509")]
+pub use peer_cert::PeerCertificate;
+#[cfg(feature = "ocsp")]
+mod ocsp;
+#[cfg(feature = "ocsp")]
+pub use ocsp::{OcspFetcher, OcspFuture, OcspStapler};
+mod options;
+use options::DerivedConfigs;
+pub use options::{AcceptOptions, ConnectOptions};
+mod owned;
+pub use owned::{OwnedBufFuture, OwnedIo, OwnedTlsStream};
+mod pool;
+pub use pool::BufferPool;
+mod proxy;
+pub use proxy::{ProxyHeader, ProxyProtocolAcceptor};
+mod replay;
+use replay::ReplayCheck;
+pub use replay::{AntiReplay, ReplayCache};
+mod resolver;
+pub use resolver::DualCertResolver;
+mod roots;
+pub use roots::ReloadingRoots;
+mod select;
 pub mod server;
+#[cfg(feature = "session-store")]
+mod session;
+#[cfg(feature = "session-store")]
+pub use session::{LruSessionStore, SessionFuture, SessionStore};
+#[cfg(all(unix, feature = "sighup"))]
+mod sighup;
+#[cfg(all(unix, feature = "sighup"))]
+pub use sighup::ReloadOnSighup;
+mod sni;
+pub use sni::{SniAccept, SniAcceptor, SniError};
+mod split;
+pub use split::{ReadHalf, ReuniteError, WriteHalf};
+#[cfg(feature = "tcp")]
+mod tcp;
+mod ticketer;
+pub use ticketer::{RotatingTicketer, TicketerGenerator};
+#[cfg(feature = "timeout")]
+mod timeout;
+#[cfg(feature = "timeout")]
+use timeout::Deadline;
+#[cfg(feature = "timeout")]
+pub use timeout::{StreamTimeout, TimeoutStream};
+#[cfg(feature = "transcript")]
+pub mod transcript;
+pub mod verify;
+use verify::{AsyncVerifier, PendingVerify};
 
 /// A wrapper around a `rustls::ClientConfig`, providing an async `connect` method.
 #[derive(Clone)]
 pub struct TlsConnector {
     inner: Arc<ClientConfig>,
+    derived: Arc<DerivedConfigs<ConnectOptions>>,
+    metrics: Arc<Metrics>,
+    audit: Audit,
+    read_ahead: usize,
+    buffer_pool: Option<Arc<BufferPool>>,
+    write_watermarks: WriteWatermarks,
+    buffer_limit: Option<usize>,
+    lenient_eof: bool,
+    observer: Option<Arc<dyn HandshakeObserver>>,
+    async_verifier: Option<Arc<dyn AsyncVerifier>>,
     #[cfg(feature = "early-data")]
     early_data: bool,
+    #[cfg(feature = "session-store")]
+    session_store: Option<Arc<session::StoreBridge>>,
 }
 
 /// A wrapper around a `rustls::ServerConfig`, providing an async `accept` method.
 #[derive(Clone)]
 pub struct TlsAcceptor {
-    inner: Arc<ServerConfig>,
+    inner: Arc<RwLock<Arc<ServerConfig>>>,
+    derived: Arc<DerivedConfigs<AcceptOptions>>,
+    metrics: Arc<Metrics>,
+    audit: Audit,
+    sni: Option<Arc<SniTable>>,
+    stats: Arc<Stats>,
+    rate_limit: Option<Arc<RateLimit>>,
+    capture_hello: bool,
+    read_ahead: usize,
+    buffer_pool: Option<Arc<BufferPool>>,
+    write_watermarks: WriteWatermarks,
+    buffer_limit: Option<usize>,
+    lenient_eof: bool,
+    observer: Option<Arc<dyn HandshakeObserver>>,
+    async_verifier: Option<Arc<dyn AsyncVerifier>>,
+    anti_replay: Option<Arc<dyn AntiReplay>>,
+    wrappers: Vec<ConfigWrapper>,
+}
+
+/// A wrapper a builder method put around part of the server config of an acceptor,
+/// which [`TlsAcceptor::swap_config`] puts around the new config too.
+#[derive(Clone)]
+enum ConfigWrapper {
+    Resumption,
+    #[cfg(feature = "x509")]
+    Expiry(Arc<CertExpiryMonitor>),
+    Hello,
+    Replay,
+}
+
+impl ConfigWrapper {
+    fn apply(&self, config: &mut ServerConfig, metrics: &Arc<Metrics>) {
+        match self {
+            ConfigWrapper::Resumption => metrics::track_resumption(config, metrics),
+            #[cfg(feature = "x509")]
+            ConfigWrapper::Expiry(monitor) => {
+                config.cert_resolver = Arc::new(expiry::MonitoredResolver {
+                    inner: config.cert_resolver.clone(),
+                    monitor: monitor.clone(),
+                })
+            }
+            ConfigWrapper::Hello => {
+                config.cert_resolver = Arc::new(hello::CapturingResolver {
+                    inner: config.cert_resolver.clone(),
+                })
+            }
+            ConfigWrapper::Replay => {
+                config.key_log = Arc::new(replay::ReplayKeyLog {
+                    inner: config.key_log.clone(),
+                })
+            }
+        }
+    }
 }
 
 impl From<Arc<ClientConfig>> for TlsConnector {
     fn from(inner: Arc<ClientConfig>) -> TlsConnector {
         TlsConnector {
             inner,
+            derived: Arc::default(),
+            metrics: Arc::default(),
+            audit: Audit::default(),
+            read_ahead: 0,
+            buffer_pool: None,
+            write_watermarks: WriteWatermarks::default(),
+            buffer_limit: Some(DEFAULT_BUFFER_LIMIT),
+            lenient_eof: false,
+            observer: None,
+            async_verifier: None,
             #[cfg(feature = "early-data")]
             early_data: false,
+            #[cfg(feature = "session-store")]
+            session_store: None,
         }
     }
 }
 
 impl From<Arc<ServerConfig>> for TlsAcceptor {
     fn from(inner: Arc<ServerConfig>) -> TlsAcceptor {
-        TlsAcceptor { inner }
+        TlsAcceptor {
+            inner: Arc::new(RwLock::new(inner)),
+            derived: Arc::default(),
+            metrics: Arc::default(),
+            audit: Audit::default(),
+            sni: None,
+            stats: Arc::default(),
+            rate_limit: None,
+            capture_hello: false,
+            read_ahead: 0,
+            buffer_pool: None,
+            write_watermarks: WriteWatermarks::default(),
+            buffer_limit: Some(DEFAULT_BUFFER_LIMIT),
+            lenient_eof: false,
+            observer: None,
+            async_verifier: None,
+            anti_replay: None,
+            wrappers: Vec::new(),
+        }
     }
 }
 
@@ -105,14 +336,235 @@ impl TlsConnector {
         self
     }
 
+    /// Verify server certificates with `verifier` instead of the one in the client config.
+    ///
+    /// The [`verify`] module has combinators for layering verifiers.
+    /// This replaces the verifier in a copy of the client config, so the connector
+    /// uses that copy from then on.
+    pub fn verifier(mut self, verifier: Arc<dyn ServerCertVerifier>) -> TlsConnector {
+        let mut config = ClientConfig::clone(&self.inner);
+        config.dangerous().set_certificate_verifier(verifier);
+        self.set_config(config);
+        self
+    }
+
+    /// Check server certificates with `verifier` too, once the handshake is done.
+    ///
+    /// See [`AsyncVerifier`].
+    pub fn async_verifier(mut self, verifier: Arc<dyn AsyncVerifier>) -> TlsConnector {
+        self.async_verifier = Some(verifier);
+        self
+    }
+
+    /// Authenticate new connections with the current identity of `identity`.
+    ///
+    /// This replaces the client certificate resolver in a copy of the client config,
+    /// so the connector uses that copy from then on.
+    pub fn client_identity(mut self, identity: Arc<ReloadingClientCert>) -> TlsConnector {
+        let mut config = ClientConfig::clone(&self.inner);
+        config.client_auth_cert_resolver = identity;
+        self.set_config(config);
+        self
+    }
+
+    /// Verify servers on new connections against the current roots of `roots`.
+    ///
+    /// This replaces the certificate verifier in a copy of the client config, so the
+    /// connector uses that copy from then on.
+    pub fn trust_anchors(self, roots: Arc<ReloadingRoots>) -> TlsConnector {
+        self.verifier(roots)
+    }
+
+    /// Offer Encrypted Client Hello on new connections, so the server name and other
+    /// sensitive parts of the ClientHello are hidden from observers.
+    ///
+    /// `mode` is usually an [`EchConfig`](rustls::client::EchConfig) made from the ECH
+    /// configs in the DNS `HTTPS` record of the server. rustls only takes ECH settings
+    /// while building a client config, so this builds a new one with the crypto
+    /// provider and time provider of the current config, verifying servers against
+    /// `roots`. The other public settings of the current config are kept, except
+    /// `resumption`: sessions are not shared with a config that may verify servers
+    /// differently. Set a custom verifier, or a session store, after this.
+    ///
+    /// Whether the server accepted ECH is reported by
+    /// [`client::TlsStream::ech_status`]; when it rejects it, the handshake fails with
+    /// [`rustls::PeerIncompatible::ServerRejectedEncryptedClientHello`], which carries
+    /// the configs the server sent for a retry.
+    pub fn ech(
+        mut self,
+        mode: impl Into<rustls::client::EchMode>,
+        roots: impl Into<Arc<rustls::RootCertStore>>,
+    ) -> Result<TlsConnector, rustls::Error> {
+        let current = &self.inner;
+        let mut config = ClientConfig::builder_with_details(
+            current.crypto_provider().clone(),
+            current.time_provider.clone(),
+        )
+        .with_ech(mode.into())?
+        .with_root_certificates(roots)
+        .with_client_cert_resolver(current.client_auth_cert_resolver.clone());
+
+        config.alpn_protocols = current.alpn_protocols.clone();
+        config.check_selected_alpn = current.check_selected_alpn;
+        config.max_fragment_size = current.max_fragment_size;
+        config.enable_sni = current.enable_sni;
+        config.key_log = current.key_log.clone();
+        config.enable_secret_extraction = current.enable_secret_extraction;
+        config.enable_early_data = current.enable_early_data;
+        config.cert_decompressors = current.cert_decompressors.clone();
+        config.cert_compressors = current.cert_compressors.clone();
+        config.cert_compression_cache = current.cert_compression_cache.clone();
+        config.send_ticket_request = current.send_ticket_request;
+        self.set_config(config);
+        Ok(self)
+    }
+
+    fn set_config(&mut self, config: ClientConfig) {
+        self.inner = Arc::new(config);
+        self.derived = Arc::default();
+    }
+
+    /// Returns the client config new connections are made with.
+    pub fn config(&self) -> &Arc<ClientConfig> {
+        &self.inner
+    }
+
+    /// Modify a copy of the client config with `f`, and use that copy from then on.
+    ///
+    /// The connector keeps its other settings, and shares its counters with the
+    /// connector it was derived from.
+    pub fn with_config(mut self, f: impl FnOnce(&mut ClientConfig)) -> TlsConnector {
+        let mut config = ClientConfig::clone(&self.inner);
+        f(&mut config);
+        self.set_config(config);
+        self
+    }
+
+    /// Offer `protocols` for ALPN, in order of preference.
+    ///
+    /// This replaces `alpn_protocols` in a copy of the client config, so the connector
+    /// uses that copy from then on.
+    pub fn alpn_protocols<'a>(
+        self,
+        protocols: impl IntoIterator<Item = &'a AlpnProtocol>,
+    ) -> TlsConnector {
+        let protocols = AlpnProtocol::to_ids(protocols);
+        self.with_config(|config| config.alpn_protocols = protocols)
+    }
+
+    /// Log the TLS secrets of new connections to the file named by the `SSLKEYLOGFILE`
+    /// environment variable, if `enabled` and the variable is set.
+    ///
+    /// Tools like Wireshark decrypt captured traffic with these secrets, so only
+    /// enable this where debugging is wanted, like behind a command line flag. This
+    /// sets `key_log` in a copy of the client config to rustls's `KeyLogFile`, so the
+    /// connector uses that copy from then on.
+    pub fn key_log_from_env(self, enabled: bool) -> TlsConnector {
+        match enabled && std::env::var_os("SSLKEYLOGFILE").is_some() {
+            true => self.with_config(|config| config.key_log = Arc::new(rustls::KeyLogFile::new())),
+            false => self,
+        }
+    }
+
+    /// Read up to `size` bytes of ciphertext at a time on new connections.
+    ///
+    /// See [`client::TlsStream::set_read_ahead`].
+    pub fn read_ahead(mut self, size: usize) -> TlsConnector {
+        self.read_ahead = size;
+        self
+    }
+
+    /// Take the read-ahead buffers of new connections from `pool`, and put them back
+    /// once they are drained.
+    ///
+    /// Pools can be shared between connectors and acceptors. This has no effect
+    /// unless [`read_ahead`](TlsConnector::read_ahead) is set.
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> TlsConnector {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Bound the ciphertext new connections buffer for writing.
+    ///
+    /// See [`client::TlsStream::set_write_watermarks`].
+    pub fn write_watermarks(mut self, high: usize, low: usize) -> TlsConnector {
+        self.write_watermarks = WriteWatermarks::new(high, low);
+        self
+    }
+
+    /// Bound the data new connections buffer for writing, or lift the bound with
+    /// `None`.
+    ///
+    /// See [`client::TlsStream::set_buffer_limit`].
+    pub fn buffer_limit(mut self, limit: Option<usize>) -> TlsConnector {
+        self.buffer_limit = limit;
+        self
+    }
+
+    /// Report the IO ending without `close_notify` as the end of the stream on new
+    /// connections.
+    ///
+    /// See [`client::TlsStream::set_lenient_eof`].
+    pub fn lenient_eof(mut self, lenient: bool) -> TlsConnector {
+        self.lenient_eof = lenient;
+        self
+    }
+
+    /// Report security-relevant handshake events on new connections to `hook`.
+    ///
+    /// See [`AuditKind`] for the events that are reported.
+    pub fn audit(mut self, hook: impl Fn(&AuditEvent<'_>) + Send + Sync + 'static) -> TlsConnector {
+        self.audit = Audit::new(hook);
+        self
+    }
+
+    /// Report the start, completion and failure of handshakes on new connections to
+    /// `observer`, for metrics.
+    ///
+    /// See [`HandshakeObserver`].
+    pub fn observer(mut self, observer: Arc<dyn HandshakeObserver>) -> TlsConnector {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Resume sessions with TLS 1.3 tickets from `store`, and keep the tickets new
+    /// connections receive there.
+    ///
+    /// [`TlsConnector::connect`] and [`TlsConnector::connect_with_options`] wait for
+    /// the store before starting the handshake, and tickets are put into the store on
+    /// a task of their own. [`TlsConnector::connect_with`] starts the handshake right
+    /// away, so it doesn't resume from the store.
+    ///
+    /// This replaces the resumption settings in a copy of the client config, so the
+    /// connector uses that copy from then on.
+    #[cfg(feature = "session-store")]
+    pub fn session_store(mut self, store: Arc<dyn SessionStore>) -> TlsConnector {
+        let mut config = ClientConfig::clone(&self.inner);
+        self.session_store = Some(session::StoreBridge::install(&mut config, store));
+        self.set_config(config);
+        self
+    }
+
+    /// Returns a snapshot of the session resumption counters of this connector.
+    ///
+    /// Handshakes that resolve before completing (0-RTT) are not counted.
+    pub fn resumption_metrics(&self) -> ResumptionMetrics {
+        self.metrics.resumption()
+    }
+
     #[inline]
     pub fn connect<IO>(&self, domain: pki_types::ServerName<'static>, stream: IO) -> Connect<IO>
     where
         IO: AsyncRead + AsyncWrite + Unpin,
     {
-        self.connect_with(domain, stream, |_| ())
+        self.connect_resuming(self.inner.clone(), domain, stream)
     }
 
+    /// Like [`TlsConnector::connect`], but calls `f` with the new connection before the
+    /// handshake starts.
+    ///
+    /// This is the place for per-connection settings, like buffer limits, that would
+    /// otherwise need a config of their own.
     pub fn connect_with<IO, F>(
         &self,
         domain: pki_types::ServerName<'static>,
@@ -123,20 +575,158 @@ impl TlsConnector {
         IO: AsyncRead + AsyncWrite + Unpin,
         F: FnOnce(&mut ClientConnection),
     {
-        let mut session = match ClientConnection::new(self.inner.clone(), domain) {
+        self.connect_inner(self.inner.clone(), domain, stream, f)
+    }
+
+    /// Connect with `options` applied on top of the client config.
+    ///
+    /// The connector keeps a copy of its client config for each distinct set of
+    /// options it was called with, so the config is not cloned per connection.
+    pub fn connect_with_options<IO>(
+        &self,
+        domain: pki_types::ServerName<'static>,
+        stream: IO,
+        options: &ConnectOptions,
+    ) -> Connect<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let config = self.derived.get(&self.inner, options);
+        match options.resumption {
+            true => self.connect_resuming(config, domain, stream),
+            false => self.connect_inner(config, domain, stream, |_| ()),
+        }
+    }
+
+    /// Connect with early data ("0-RTT data") chosen up front, rather than written
+    /// through the stream while the handshake is in progress.
+    ///
+    /// Write the early data to the returned [`client::EarlyDataWriter`] before polling
+    /// the [`Connect`] future; it is sent with the ClientHello. The future completes
+    /// the full handshake, whatever [`TlsConnector::early_data`] is set to, and early
+    /// data the server rejects is not sent again.
+    ///
+    /// You must also set `ClientConfig.enable_early_data` to `true`.
+    #[cfg(feature = "early-data")]
+    pub fn connect_early<IO>(
+        &self,
+        domain: pki_types::ServerName<'static>,
+        stream: IO,
+    ) -> (client::EarlyDataWriter, Connect<IO>)
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut connect = self.connect_inner(self.inner.clone(), domain, stream, |_| ());
+        let mut limit = 0;
+        if let MidHandshake::Handshaking(stream) = &mut connect.inner {
+            stream.state = TlsState::Stream;
+            limit = stream
+                .session
+                .early_data()
+                .map_or(0, |data| data.bytes_left());
+        }
+        let (writer, queue) = client::EarlyDataWriter::new(limit);
+        connect.early = Some(queue);
+        (writer, connect)
+    }
+
+    /// Upgrade `stream`, a connection that has carried plaintext so far, to TLS, as
+    /// protocols with a STARTTLS command (SMTP, IMAP, LDAP, ...) do.
+    ///
+    /// The handshake starts with the next bytes exchanged on `stream`, so call this
+    /// once the server has agreed to the upgrade. `buffered` is what was read from
+    /// `stream` but not consumed yet, like the buffer of a `BufReader` around it. It
+    /// has to be empty: the server sends nothing between agreeing and the handshake,
+    /// and plaintext that arrived in between may have been injected by an attacker,
+    /// so the upgrade fails with an error of kind `InvalidData` instead of losing it
+    /// or letting it pass for part of the TLS session.
+    pub fn upgrade<IO>(
+        &self,
+        domain: pki_types::ServerName<'static>,
+        stream: IO,
+        buffered: &[u8],
+    ) -> Connect<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        match buffered.is_empty() {
+            true => self.connect(domain, stream),
+            false => self.failed(stream, plaintext_before_upgrade()),
+        }
+    }
+
+    /// Connects once a ticket is fetched from the session store, if there is one.
+    fn connect_resuming<IO>(
+        &self,
+        config: Arc<ClientConfig>,
+        domain: pki_types::ServerName<'static>,
+        stream: IO,
+    ) -> Connect<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        #[cfg(feature = "session-store")]
+        if let Some(bridge) = &self.session_store {
+            let observe = Observe::client(&self.observer, &domain);
+            let verify = PendingVerify::new(self.async_verifier.clone(), Some(domain.clone()));
+            let fetch = session::Fetch::new(bridge, self.clone(), config, domain, stream);
+            return Connect {
+                inner: MidHandshake::End,
+                verify,
+                fetch: Some(fetch),
+                metrics: self.metrics.clone(),
+                audit: self.audit.clone(),
+                observe,
+                #[cfg(feature = "cancel")]
+                cancel: Cancel::default(),
+                #[cfg(feature = "early-data")]
+                early: None,
+            };
+        }
+        self.connect_inner(config, domain, stream, |_| ())
+    }
+
+    fn failed<IO>(&self, stream: IO, error: io::Error) -> Connect<IO> {
+        Connect {
+            inner: MidHandshake::Error { io: stream, error },
+            verify: PendingVerify::default(),
+            #[cfg(feature = "session-store")]
+            fetch: None,
+            metrics: self.metrics.clone(),
+            audit: self.audit.clone(),
+            observe: Observe::default(),
+            #[cfg(feature = "cancel")]
+            cancel: Cancel::default(),
+            #[cfg(feature = "early-data")]
+            early: None,
+        }
+    }
+
+    fn connect_inner<IO, F>(
+        &self,
+        config: Arc<ClientConfig>,
+        domain: pki_types::ServerName<'static>,
+        stream: IO,
+        f: F,
+    ) -> Connect<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+        F: FnOnce(&mut ClientConnection),
+    {
+        let observe = Observe::client(&self.observer, &domain);
+        let verify = PendingVerify::new(self.async_verifier.clone(), Some(domain.clone()));
+        let mut session = match ClientConnection::new(config, domain) {
             Ok(session) => session,
             Err(error) => {
-                return Connect(MidHandshake::Error {
-                    io: stream,
-                    // TODO(eliza): should this really return an `io::Error`?
-                    // Probably not...
-                    error: io::Error::new(io::ErrorKind::Other, error),
-                });
+                // TODO(eliza): should this really return an `io::Error`?
+                // Probably not...
+                return self.failed(stream, io::Error::new(io::ErrorKind::Other, error));
             }
         };
+        session.set_buffer_limit(self.buffer_limit);
         f(&mut session);
 
-        Connect(MidHandshake::Handshaking(client::TlsStream {
+        let stream = client::TlsStream {
             io: stream,
 
             #[cfg(not(feature = "early-data"))]
@@ -151,13 +741,321 @@ impl TlsConnector {
 
             #[cfg(feature = "early-data")]
             early_waker: None,
+            #[cfg(feature = "early-data")]
+            early_data_len: 0,
 
+            read_ahead: ReadAhead::new(self.read_ahead).with_pool(self.buffer_pool.clone()),
+            write_watermarks: self.write_watermarks,
+            read_eof: None,
+            lenient_eof: self.lenient_eof,
+            #[cfg(feature = "events")]
+            events: Default::default(),
             session,
-        }))
+        };
+
+        Connect {
+            inner: MidHandshake::Handshaking(stream),
+            verify,
+            #[cfg(feature = "session-store")]
+            fetch: None,
+            metrics: self.metrics.clone(),
+            audit: self.audit.clone(),
+            observe,
+            #[cfg(feature = "cancel")]
+            cancel: Cancel::default(),
+            #[cfg(feature = "early-data")]
+            early: None,
+        }
+    }
+}
+
+/// Lets a connector sit in a tower stack, so layers like timeouts and retries wrap
+/// the handshake.
+#[cfg(feature = "tower")]
+impl<IO> tower_service::Service<(pki_types::ServerName<'static>, IO)> for TlsConnector
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    type Response = client::TlsStream<IO>;
+    type Error = io::Error;
+    type Future = Connect<IO>;
+
+    #[inline]
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, (domain, stream): (pki_types::ServerName<'static>, IO)) -> Connect<IO> {
+        self.connect(domain, stream)
     }
 }
 
 impl TlsAcceptor {
+    /// Count resumption attempts and issued tickets as well as completed handshakes.
+    ///
+    /// This wraps the session storage and ticketer of the server config, so the
+    /// acceptor uses a copy of the config from then on.
+    pub fn track_resumption(self) -> TlsAcceptor {
+        self.wrap(ConfigWrapper::Resumption)
+    }
+
+    /// Register the certificates this acceptor serves with `monitor`, to be warned
+    /// before they expire.
+    ///
+    /// This wraps the certificate resolver of the server config, so the acceptor uses
+    /// a copy of the config from then on. A certificate is registered the first time
+    /// it is served; see [`CertExpiryMonitor`] for registering certificates up front.
+    #[cfg(feature = "x509")]
+    pub fn monitor_expiry(self, monitor: Arc<CertExpiryMonitor>) -> TlsAcceptor {
+        self.wrap(ConfigWrapper::Expiry(monitor))
+    }
+
+    /// Record what clients offer in their ClientHello, see
+    /// [`server::TlsStream::client_hello`].
+    ///
+    /// The summary is taken from the certificate resolver of the server config, so
+    /// this wraps the resolver, and the acceptor uses a copy of the config from then
+    /// on. Configs set later with [`TlsAcceptor::with_config`] or
+    /// [`TlsAcceptor::swap_config`] keep the wrapper.
+    pub fn capture_client_hello(mut self) -> TlsAcceptor {
+        self.capture_hello = true;
+        self.wrap(ConfigWrapper::Hello)
+    }
+
+    fn wrap(mut self, wrapper: ConfigWrapper) -> TlsAcceptor {
+        let mut config = ServerConfig::clone(&self.config());
+        wrapper.apply(&mut config, &self.metrics);
+        self.set_config(config);
+        self.wrappers.push(wrapper);
+        self
+    }
+
+    fn set_config(&mut self, config: ServerConfig) {
+        self.inner = Arc::new(RwLock::new(Arc::new(config)));
+        self.derived = Arc::default();
+    }
+
+    /// Returns the server config new connections are accepted with.
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.inner
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// Accept new connections with `config`, on this acceptor and all its clones.
+    ///
+    /// Handshakes already started finish with the config they started with. This is
+    /// the way to roll out renewed certificates without handing a new acceptor to
+    /// every task that accepts connections.
+    ///
+    /// The wrappers that [`TlsAcceptor::track_resumption`],
+    /// [`TlsAcceptor::capture_client_hello`] and the like added to the config of the
+    /// acceptor are added to a copy of `config` too; other settings made with builder
+    /// methods are not. Acceptors derived with builder methods after `swap_config` are
+    /// not shared with the acceptor they were derived from.
+    pub fn swap_config(&self, config: Arc<ServerConfig>) {
+        let config = match self.wrappers.is_empty() {
+            true => config,
+            false => {
+                let mut config = ServerConfig::clone(&config);
+                for wrapper in &self.wrappers {
+                    wrapper.apply(&mut config, &self.metrics);
+                }
+                Arc::new(config)
+            }
+        };
+        *self.inner.write().unwrap_or_else(|err| err.into_inner()) = config;
+    }
+
+    /// Modify a copy of the server config with `f`, and use that copy from then on.
+    ///
+    /// The acceptor keeps its other settings, and shares its counters with the
+    /// acceptor it was derived from.
+    pub fn with_config(mut self, f: impl FnOnce(&mut ServerConfig)) -> TlsAcceptor {
+        let mut config = ServerConfig::clone(&self.config());
+        f(&mut config);
+        self.set_config(config);
+        self
+    }
+
+    /// Accept `protocols` for ALPN, in order of preference.
+    ///
+    /// This replaces `alpn_protocols` in a copy of the server config, so the acceptor
+    /// uses that copy from then on.
+    pub fn alpn_protocols<'a>(
+        self,
+        protocols: impl IntoIterator<Item = &'a AlpnProtocol>,
+    ) -> TlsAcceptor {
+        let protocols = AlpnProtocol::to_ids(protocols);
+        self.with_config(|config| config.alpn_protocols = protocols)
+    }
+
+    /// Log the TLS secrets of new connections to the file named by the `SSLKEYLOGFILE`
+    /// environment variable, if `enabled` and the variable is set.
+    ///
+    /// Tools like Wireshark decrypt captured traffic with these secrets, so only
+    /// enable this where debugging is wanted, like behind a command line flag. This
+    /// sets `key_log` in a copy of the server config to rustls's `KeyLogFile`, so the
+    /// acceptor uses that copy from then on.
+    pub fn key_log_from_env(self, enabled: bool) -> TlsAcceptor {
+        match enabled && std::env::var_os("SSLKEYLOGFILE").is_some() {
+            true => self.with_config(|config| config.key_log = Arc::new(rustls::KeyLogFile::new())),
+            false => self,
+        }
+    }
+
+    /// Issue stateless session tickets encrypted by `ticketer`, like a
+    /// [`RotatingTicketer`].
+    ///
+    /// This sets `ticketer` in a copy of the server config, so the acceptor uses that
+    /// copy from then on. Call it before [`TlsAcceptor::track_resumption`] for tickets
+    /// to be counted.
+    pub fn ticketer(self, ticketer: Arc<dyn ProducesTickets>) -> TlsAcceptor {
+        self.with_config(|config| config.ticketer = ticketer)
+    }
+
+    /// Accept up to `size` bytes of early (0-RTT) data from clients resuming a
+    /// session.
+    ///
+    /// This sets `max_early_data_size` in a copy of the server config, so the
+    /// acceptor uses that copy from then on. rustls only accepts early data for
+    /// sessions resumed from its session store, not with stateless tickets. Early
+    /// data can be replayed by an attacker, see [`TlsAcceptor::anti_replay`].
+    pub fn max_early_data_size(self, size: u32) -> TlsAcceptor {
+        self.with_config(|config| config.max_early_data_size = size)
+    }
+
+    /// Check early data with `guard` before it is read, and fail handshakes whose
+    /// early data was replayed with an error of kind `InvalidData`.
+    ///
+    /// The guard sees the random of the ClientHello, which rustls only hands to the
+    /// key log, so this wraps the key log of the server config, and the acceptor
+    /// uses a copy of the config from then on. Call it after
+    /// [`TlsAcceptor::key_log_from_env`]. Connections accepted through
+    /// [`LazyConfigAcceptor`] are not checked.
+    pub fn anti_replay(mut self, guard: Arc<dyn AntiReplay>) -> TlsAcceptor {
+        self.anti_replay = Some(guard);
+        self.wrap(ConfigWrapper::Replay)
+    }
+
+    /// Read up to `size` bytes of ciphertext at a time on new connections.
+    ///
+    /// See [`server::TlsStream::set_read_ahead`].
+    pub fn read_ahead(mut self, size: usize) -> TlsAcceptor {
+        self.read_ahead = size;
+        self
+    }
+
+    /// Take the read-ahead buffers of new connections from `pool`, and put them back
+    /// once they are drained.
+    ///
+    /// Pools can be shared between connectors and acceptors. This has no effect
+    /// unless [`read_ahead`](TlsAcceptor::read_ahead) is set.
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> TlsAcceptor {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Bound the ciphertext new connections buffer for writing.
+    ///
+    /// See [`server::TlsStream::set_write_watermarks`].
+    pub fn write_watermarks(mut self, high: usize, low: usize) -> TlsAcceptor {
+        self.write_watermarks = WriteWatermarks::new(high, low);
+        self
+    }
+
+    /// Bound the data new connections buffer for writing, or lift the bound with
+    /// `None`.
+    ///
+    /// See [`server::TlsStream::set_buffer_limit`].
+    pub fn buffer_limit(mut self, limit: Option<usize>) -> TlsAcceptor {
+        self.buffer_limit = limit;
+        self
+    }
+
+    /// Report the IO ending without `close_notify` as the end of the stream on new
+    /// connections.
+    ///
+    /// See [`server::TlsStream::set_lenient_eof`].
+    pub fn lenient_eof(mut self, lenient: bool) -> TlsAcceptor {
+        self.lenient_eof = lenient;
+        self
+    }
+
+    /// Report security-relevant handshake events on new connections to `hook`.
+    ///
+    /// See [`AuditKind`] for the events that are reported. Connections accepted through
+    /// [`LazyConfigAcceptor`] are not audited.
+    pub fn audit(mut self, hook: impl Fn(&AuditEvent<'_>) + Send + Sync + 'static) -> TlsAcceptor {
+        self.audit = Audit::new(hook);
+        self
+    }
+
+    /// Report the start, completion and failure of handshakes on new connections to
+    /// `observer`, for metrics.
+    ///
+    /// See [`HandshakeObserver`]. Connections accepted through [`LazyConfigAcceptor`]
+    /// are not observed, and neither are those refused before their handshake started,
+    /// like the ones over the [`rate_limit`](TlsAcceptor::rate_limit).
+    pub fn observer(mut self, observer: Arc<dyn HandshakeObserver>) -> TlsAcceptor {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Check client certificates with `verifier` too, once the handshake is done.
+    ///
+    /// See [`AsyncVerifier`]. Connections accepted through [`LazyConfigAcceptor`] are
+    /// not checked.
+    pub fn async_verifier(mut self, verifier: Arc<dyn AsyncVerifier>) -> TlsAcceptor {
+        self.async_verifier = Some(verifier);
+        self
+    }
+
+    /// Returns a snapshot of the session resumption counters of this acceptor.
+    pub fn resumption_metrics(&self) -> ResumptionMetrics {
+        self.metrics.resumption()
+    }
+
+    /// Count handshakes, failures and active connections per server name (SNI).
+    ///
+    /// Server names are chosen by clients, so at most `max_names` distinct names are
+    /// tracked; connections for further names are counted under `None`, like those
+    /// that did not send SNI at all.
+    pub fn track_sni(mut self, max_names: usize) -> TlsAcceptor {
+        self.sni = Some(Arc::new(SniTable::new(max_names)));
+        self
+    }
+
+    /// Returns a snapshot of the per-SNI counters of this acceptor.
+    ///
+    /// This is empty unless the acceptor was created with [`TlsAcceptor::track_sni`].
+    pub fn sni_metrics(&self) -> HashMap<Option<String>, SniMetrics> {
+        self.sni
+            .as_ref()
+            .map(|table| table.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Start at most `per_second` handshakes a second, after an initial burst of up to
+    /// `burst`.
+    ///
+    /// Handshakes are what costs a server CPU time, so a flood of new connections can
+    /// starve established ones. Connections over the limit fail right away with a
+    /// [`RateLimited`] error inside the `io::Error`, without starting a handshake.
+    /// Clones of the acceptor share the limit; this includes a `TlsListener` made
+    /// with it.
+    pub fn rate_limit(mut self, per_second: u32, burst: u32) -> TlsAcceptor {
+        self.rate_limit = Some(Arc::new(RateLimit::new(per_second, burst)));
+        self
+    }
+
+    /// Returns a snapshot of the connection counters of this acceptor, for health
+    /// endpoints and load signals.
+    pub fn stats(&self) -> AcceptorStats {
+        self.stats.snapshot()
+    }
+
     #[inline]
     pub fn accept<IO>(&self, stream: IO) -> Accept<IO>
     where
@@ -166,35 +1064,178 @@ impl TlsAcceptor {
         self.accept_with(stream, |_| ())
     }
 
+    /// Like [`TlsAcceptor::accept`], but gives up on handshakes that take longer than
+    /// `timeout` from when the future is first polled.
+    ///
+    /// A handshake that times out is sent `close_notify`, and fails with an error of
+    /// kind `TimedOut`. [`Accept::into_fallible`] hands back the IO of such a handshake;
+    /// otherwise it is dropped, closing the connection.
+    #[cfg(feature = "timeout")]
+    pub fn accept_with_timeout<IO>(&self, stream: IO, timeout: Duration) -> Accept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut accept = self.accept(stream);
+        accept.deadline = Deadline::new(timeout);
+        accept
+    }
+
+    /// Like [`TlsAcceptor::accept`], but calls `f` with the new connection before the
+    /// handshake starts.
+    ///
+    /// This is the place for per-connection settings, like buffer limits, that would
+    /// otherwise need a config of their own.
     pub fn accept_with<IO, F>(&self, stream: IO, f: F) -> Accept<IO>
     where
         IO: AsyncRead + AsyncWrite + Unpin,
         F: FnOnce(&mut ServerConnection),
     {
-        let mut session = match ServerConnection::new(self.inner.clone()) {
+        self.accept_inner(self.config(), stream, f)
+    }
+
+    /// Accept with `options` applied on top of the server config.
+    ///
+    /// The acceptor keeps a copy of its server config for each distinct set of
+    /// options it was called with, so the config is not cloned per connection.
+    pub fn accept_with_options<IO>(&self, stream: IO, options: &AcceptOptions) -> Accept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let config = self.derived.get(&self.config(), options);
+        self.accept_inner(config, stream, |_| ())
+    }
+
+    /// Accept with early (0-RTT) data read as it arrives, before the handshake
+    /// completes.
+    ///
+    /// Read the early data from the returned [`server::EarlyDataReader`] while
+    /// polling the [`Accept`] future, as with `tokio::join!`; the stream only reads
+    /// the data sent after it. Early data is only checked against replays if
+    /// [`TlsAcceptor::anti_replay`] is set, so it should only carry requests that are
+    /// safe to process more than once otherwise.
+    #[cfg(feature = "early-data")]
+    pub fn accept_early<IO>(&self, stream: IO) -> (server::EarlyDataReader, Accept<IO>)
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut accept = self.accept(stream);
+        let (reader, sender) = server::EarlyDataReader::new();
+        accept.early = Some(sender);
+        (reader, accept)
+    }
+
+    /// Upgrade `stream`, a connection that has carried plaintext so far, to TLS, as
+    /// protocols with a STARTTLS command (SMTP, IMAP, LDAP, ...) do.
+    ///
+    /// The handshake starts with the next bytes exchanged on `stream`, so call this
+    /// once the client has been told to go ahead. `buffered` is what was read from
+    /// `stream` but not consumed yet, like the buffer of a `BufReader` around it. It
+    /// has to be empty: plaintext sent after the STARTTLS command may have been
+    /// injected by an attacker, so the upgrade fails with an error of kind
+    /// `InvalidData` instead of losing it or letting it pass for part of the TLS
+    /// session.
+    pub fn upgrade<IO>(&self, stream: IO, buffered: &[u8]) -> Accept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        match buffered.is_empty() {
+            true => self.accept(stream),
+            false => {
+                self.stats.failed();
+                self.failed(stream, plaintext_before_upgrade())
+            }
+        }
+    }
+
+    fn failed<IO>(&self, stream: IO, error: io::Error) -> Accept<IO> {
+        Accept {
+            inner: MidHandshake::Error { io: stream, error },
+            verify: PendingVerify::default(),
+            metrics: self.metrics.clone(),
+            audit: self.audit.clone(),
+            observe: Observe::default(),
+            hello: HelloCapture::default(),
+            replay: ReplayCheck::default(),
+            #[cfg(feature = "early-data")]
+            early: None,
+            #[cfg(feature = "cancel")]
+            cancel: Cancel::default(),
+            #[cfg(feature = "timeout")]
+            deadline: Deadline::default(),
+        }
+    }
+
+    fn accept_inner<IO, F>(&self, config: Arc<ServerConfig>, stream: IO, f: F) -> Accept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+        F: FnOnce(&mut ServerConnection),
+    {
+        if let Some(limit) = &self.rate_limit {
+            if !limit.try_acquire() {
+                return self.failed(stream, io::Error::new(io::ErrorKind::Other, RateLimited));
+            }
+        }
+
+        let mut session = match ServerConnection::new(config) {
             Ok(session) => session,
             Err(error) => {
-                return Accept(MidHandshake::Error {
-                    io: stream,
-                    // TODO(eliza): should this really return an `io::Error`?
-                    // Probably not...
-                    error: io::Error::new(io::ErrorKind::Other, error),
-                });
+                self.stats.failed();
+                // TODO(eliza): should this really return an `io::Error`?
+                // Probably not...
+                return self.failed(stream, io::Error::new(io::ErrorKind::Other, error));
             }
         };
+        session.set_buffer_limit(self.buffer_limit);
         f(&mut session);
 
-        Accept(MidHandshake::Handshaking(server::TlsStream {
-            session,
-            io: stream,
-            state: TlsState::Stream,
-        }))
+        Accept {
+            inner: MidHandshake::Handshaking(server::TlsStream {
+                session,
+                io: stream,
+                state: TlsState::Stream,
+                read_ahead: ReadAhead::new(self.read_ahead).with_pool(self.buffer_pool.clone()),
+                write_watermarks: self.write_watermarks,
+                read_eof: None,
+                lenient_eof: self.lenient_eof,
+                #[cfg(feature = "events")]
+                events: Default::default(),
+                tracker: self.sni.clone().map(SniTracker::new),
+                stats: Some(StatsTracker::new(self.stats.clone())),
+                client_hello: None,
+                early_data_status: server::EarlyDataStatus::Handshaking,
+                early_data: Vec::new(),
+            }),
+            verify: PendingVerify::new(self.async_verifier.clone(), None),
+            metrics: self.metrics.clone(),
+            audit: self.audit.clone(),
+            observe: Observe::server(&self.observer),
+            hello: HelloCapture::new(self.capture_hello),
+            replay: ReplayCheck::new(self.anti_replay.clone()),
+            #[cfg(feature = "early-data")]
+            early: None,
+            #[cfg(feature = "cancel")]
+            cancel: Cancel::default(),
+            #[cfg(feature = "timeout")]
+            deadline: Deadline::default(),
+        }
     }
 }
 
+fn plaintext_before_upgrade() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "received plaintext ahead of the TLS upgrade",
+    )
+}
+
 pub struct LazyConfigAcceptor<IO> {
     acceptor: rustls::server::Acceptor,
     io: Option<IO>,
+    records: Vec<u8>,
+    plaintext_fallback: bool,
+    plaintext: bool,
+    #[cfg(feature = "timeout")]
+    deadline: Deadline,
 }
 
 impl<IO> LazyConfigAcceptor<IO>
@@ -206,6 +1247,51 @@ where
         Self {
             acceptor,
             io: Some(io),
+            records: Vec::new(),
+            plaintext_fallback: false,
+            plaintext: false,
+            #[cfg(feature = "timeout")]
+            deadline: Deadline::default(),
+        }
+    }
+
+    /// Like [`LazyConfigAcceptor::new`], but gives up on handshakes that take longer
+    /// than `timeout` from when the acceptor is first polled.
+    ///
+    /// The timeout covers the whole handshake: the [`Accept`] future returned by
+    /// [`StartHandshake::into_stream`] fails once the remaining time runs out. Waiting
+    /// for the ClientHello times out with an error of kind `TimedOut`, and drops the
+    /// IO to close the connection.
+    #[cfg(feature = "timeout")]
+    pub fn with_timeout(acceptor: rustls::server::Acceptor, io: IO, timeout: Duration) -> Self {
+        Self {
+            acceptor,
+            io: Some(io),
+            records: Vec::new(),
+            plaintext_fallback: false,
+            plaintext: false,
+            deadline: Deadline::new(timeout),
+        }
+    }
+
+    /// Keep the connection of clients that send plaintext instead of a ClientHello,
+    /// like plain HTTP requests sent to an HTTPS port.
+    ///
+    /// Connections that start with an ASCII letter fail with an error of kind
+    /// `InvalidData` without being sent an alert, and
+    /// [`LazyConfigAcceptor::take_plaintext`] hands back the IO together with the
+    /// bytes read from it, to answer the request, for example with a redirect.
+    pub fn plaintext_fallback(mut self, enable: bool) -> Self {
+        self.plaintext_fallback = enable;
+        self
+    }
+
+    /// Takes back the client connection and the bytes read from it, if the client sent
+    /// plaintext, see [`LazyConfigAcceptor::plaintext_fallback`].
+    pub fn take_plaintext(&mut self) -> Option<(IO, Vec<u8>)> {
+        match self.plaintext {
+            true => Some((self.io.take()?, mem::take(&mut self.records))),
+            false => None,
         }
     }
 
@@ -263,6 +1349,11 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
+        #[cfg(feature = "timeout")]
+        if this.io.is_some() && this.deadline.poll_expired(cx) {
+            this.io = None;
+            return Poll::Ready(Err(timeout::timed_out()));
+        }
         loop {
             let io = match this.io.as_mut() {
                 Some(io) => io,
@@ -274,7 +1365,10 @@ where
                 }
             };
 
-            let mut reader = common::SyncReadAdapter { io, cx };
+            let mut reader = hello::TeeReader {
+                inner: common::SyncReadAdapter { io, cx },
+                copy: &mut this.records,
+            };
             match this.acceptor.read_tls(&mut reader) {
                 Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()).into(),
                 Ok(_) => {}
@@ -285,9 +1379,25 @@ where
             match this.acceptor.accept() {
                 Ok(Some(accepted)) => {
                     let io = this.io.take().unwrap();
-                    return Poll::Ready(Ok(StartHandshake { accepted, io }));
+                    let client_hello = hello::first_handshake_message(&this.records);
+                    this.records = Vec::new();
+                    return Poll::Ready(Ok(StartHandshake {
+                        accepted,
+                        io,
+                        client_hello,
+                        #[cfg(feature = "timeout")]
+                        deadline: mem::take(&mut this.deadline),
+                    }));
                 }
                 Ok(None) => continue,
+                // TLS records start with their content type, which is never a letter.
+                Err(_) if this.plaintext_fallback && this.records[0].is_ascii_alphabetic() => {
+                    this.plaintext = true;
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "client sent plaintext instead of TLS",
+                    )));
+                }
                 Err((err, mut alert)) => {
                     let mut writer = common::SyncWriteAdapter { io, cx };
                     let _ = alert.write(&mut writer); // best effort
@@ -301,6 +1411,9 @@ where
 pub struct StartHandshake<IO> {
     accepted: rustls::server::Accepted,
     io: IO,
+    client_hello: Vec<u8>,
+    #[cfg(feature = "timeout")]
+    deadline: Deadline,
 }
 
 impl<IO> StartHandshake<IO>
@@ -311,10 +1424,42 @@ where
         self.accepted.client_hello()
     }
 
+    /// Returns the ClientHello message as the client sent it, for fingerprinting and
+    /// logging.
+    ///
+    /// This is the handshake message with its four-byte header, reassembled from the
+    /// TLS records it arrived in.
+    pub fn client_hello_bytes(&self) -> &[u8] {
+        &self.client_hello
+    }
+
     pub fn into_stream(self, config: Arc<ServerConfig>) -> Accept<IO> {
         self.into_stream_with(config, |_| ())
     }
 
+    /// Refuse the connection, telling the client why with a fatal `alert`, like
+    /// `UnrecognisedName` for a server name that isn't served here.
+    ///
+    /// No keys have been agreed yet, so the alert is sent in the clear. The IO is
+    /// shut down once it has been written.
+    pub async fn reject(mut self, alert: AlertDescription) -> io::Result<()> {
+        // An alert record: level `fatal`, then the description.
+        let record = [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, u8::from(alert)];
+        let mut written = 0;
+        while written < record.len() {
+            let io = &mut self.io;
+            match poll_fn(|cx| Pin::new(&mut *io).poll_write(cx, &record[written..])).await? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => written += n,
+            }
+        }
+        poll_fn(|cx| Pin::new(&mut self.io).poll_shutdown(cx)).await
+    }
+
+    /// Like [`StartHandshake::into_stream`], but calls `f` with the new connection
+    /// before the handshake resumes.
+    ///
+    /// The ClientHello is known by then, so per-connection settings can depend on it.
     pub fn into_stream_with<F>(self, config: Arc<ServerConfig>, f: F) -> Accept<IO>
     where
         F: FnOnce(&mut ServerConnection),
@@ -322,47 +1467,176 @@ where
         let mut conn = match self.accepted.into_connection(config) {
             Ok(conn) => conn,
             Err((error, alert)) => {
-                return Accept(MidHandshake::SendAlert {
-                    io: self.io,
-                    alert,
-                    // TODO(eliza): should this really return an `io::Error`?
-                    // Probably not...
-                    error: io::Error::new(io::ErrorKind::Other, error),
-                });
+                return Accept {
+                    inner: MidHandshake::SendAlert {
+                        io: self.io,
+                        alert,
+                        // TODO(eliza): should this really return an `io::Error`?
+                        // Probably not...
+                        error: io::Error::new(io::ErrorKind::Other, error),
+                    },
+                    verify: PendingVerify::default(),
+                    metrics: Arc::default(),
+                    audit: Audit::default(),
+                    observe: Observe::server(&None),
+                    hello: HelloCapture::default(),
+                    replay: ReplayCheck::default(),
+                    #[cfg(feature = "early-data")]
+                    early: None,
+                    #[cfg(feature = "cancel")]
+                    cancel: Cancel::default(),
+                    #[cfg(feature = "timeout")]
+                    deadline: self.deadline,
+                };
             }
         };
         f(&mut conn);
 
-        Accept(MidHandshake::Handshaking(server::TlsStream {
-            session: conn,
-            io: self.io,
-            state: TlsState::Stream,
-        }))
+        Accept {
+            inner: MidHandshake::Handshaking(server::TlsStream {
+                session: conn,
+                io: self.io,
+                state: TlsState::Stream,
+                read_ahead: ReadAhead::default(),
+                write_watermarks: WriteWatermarks::default(),
+                read_eof: None,
+                lenient_eof: false,
+                #[cfg(feature = "events")]
+                events: Default::default(),
+                tracker: None,
+                stats: None,
+                client_hello: None,
+                early_data_status: server::EarlyDataStatus::Handshaking,
+                early_data: Vec::new(),
+            }),
+            verify: PendingVerify::default(),
+            metrics: Arc::default(),
+            audit: Audit::default(),
+            observe: Observe::server(&None),
+            hello: HelloCapture::default(),
+            replay: ReplayCheck::default(),
+            #[cfg(feature = "early-data")]
+            early: None,
+            #[cfg(feature = "cancel")]
+            cancel: Cancel::default(),
+            #[cfg(feature = "timeout")]
+            deadline: self.deadline,
+        }
     }
 }
 
 /// Future returned from `TlsConnector::connect` which will resolve
 /// once the connection handshake has finished.
-pub struct Connect<IO>(MidHandshake<client::TlsStream<IO>>);
+///
+/// The handshake state lives in the future, so it can be polled by reference, as in
+/// `tokio::select!` on `&mut fut`, and resumed after another branch won. Dropping it
+/// drops the IO along with the half-done handshake, which can't be restarted.
+pub struct Connect<IO> {
+    inner: MidHandshake<client::TlsStream<IO>>,
+    verify: PendingVerify<client::TlsStream<IO>>,
+    #[cfg(feature = "session-store")]
+    fetch: Option<session::Fetch<IO>>,
+    metrics: Arc<Metrics>,
+    audit: Audit,
+    observe: Observe,
+    #[cfg(feature = "cancel")]
+    cancel: Cancel,
+    #[cfg(feature = "early-data")]
+    early: Option<Arc<std::sync::Mutex<client::EarlyDataQueue>>>,
+}
 
 /// Future returned from `TlsAcceptor::accept` which will resolve
 /// once the accept handshake has finished.
-pub struct Accept<IO>(MidHandshake<server::TlsStream<IO>>);
+///
+/// The handshake state lives in the future, so it can be polled by reference, as in
+/// `tokio::select!` on `&mut fut`, and resumed after another branch won. Dropping it
+/// drops the IO along with the half-done handshake, which can't be restarted.
+pub struct Accept<IO> {
+    inner: MidHandshake<server::TlsStream<IO>>,
+    verify: PendingVerify<server::TlsStream<IO>>,
+    metrics: Arc<Metrics>,
+    audit: Audit,
+    observe: Observe,
+    hello: HelloCapture,
+    replay: ReplayCheck,
+    #[cfg(feature = "early-data")]
+    early: Option<server::EarlyDataSender>,
+    #[cfg(feature = "cancel")]
+    cancel: Cancel,
+    #[cfg(feature = "timeout")]
+    deadline: Deadline,
+}
 
 /// Like [Connect], but returns `IO` on failure.
-pub struct FallibleConnect<IO>(MidHandshake<client::TlsStream<IO>>);
+pub struct FallibleConnect<IO> {
+    inner: MidHandshake<client::TlsStream<IO>>,
+    verify: PendingVerify<client::TlsStream<IO>>,
+    #[cfg(feature = "session-store")]
+    fetch: Option<session::Fetch<IO>>,
+    metrics: Arc<Metrics>,
+    audit: Audit,
+    observe: Observe,
+    #[cfg(feature = "cancel")]
+    cancel: Cancel,
+    #[cfg(feature = "early-data")]
+    early: Option<Arc<std::sync::Mutex<client::EarlyDataQueue>>>,
+}
 
 /// Like [Accept], but returns `IO` on failure.
-pub struct FallibleAccept<IO>(MidHandshake<server::TlsStream<IO>>);
+pub struct FallibleAccept<IO> {
+    inner: MidHandshake<server::TlsStream<IO>>,
+    verify: PendingVerify<server::TlsStream<IO>>,
+    metrics: Arc<Metrics>,
+    audit: Audit,
+    observe: Observe,
+    hello: HelloCapture,
+    replay: ReplayCheck,
+    #[cfg(feature = "early-data")]
+    early: Option<server::EarlyDataSender>,
+    #[cfg(feature = "cancel")]
+    cancel: Cancel,
+    #[cfg(feature = "timeout")]
+    deadline: Deadline,
+}
 
 impl<IO> Connect<IO> {
+    /// Abandon the handshake once `token` is cancelled.
+    ///
+    /// The peer is sent a `close_notify` alert if the IO accepts it without waiting,
+    /// and the future fails with an error of kind `ConnectionAborted`.
+    #[cfg(feature = "cancel")]
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Cancel::new(token);
+        self
+    }
+
     #[inline]
     pub fn into_fallible(self) -> FallibleConnect<IO> {
-        FallibleConnect(self.0)
+        FallibleConnect {
+            inner: self.inner,
+            verify: self.verify,
+            #[cfg(feature = "session-store")]
+            fetch: self.fetch,
+            metrics: self.metrics,
+            audit: self.audit,
+            observe: self.observe,
+            #[cfg(feature = "cancel")]
+            cancel: self.cancel,
+            #[cfg(feature = "early-data")]
+            early: self.early,
+        }
     }
 
+    /// Returns the IO while the handshake is pending, for instance to set socket
+    /// options on it.
+    ///
+    /// Returns `None` once the future has completed.
     pub fn get_ref(&self) -> Option<&IO> {
-        match &self.0 {
+        #[cfg(feature = "session-store")]
+        if let Some(fetch) = &self.fetch {
+            return Some(&fetch.io);
+        }
+        match &self.inner {
             MidHandshake::Handshaking(sess) => Some(sess.get_ref().0),
             MidHandshake::SendAlert { io, .. } => Some(io),
             MidHandshake::Error { io, .. } => Some(io),
@@ -370,24 +1644,82 @@ impl<IO> Connect<IO> {
         }
     }
 
+    /// Returns the IO while the handshake is pending.
+    ///
+    /// Returns `None` once the future has completed.
     pub fn get_mut(&mut self) -> Option<&mut IO> {
-        match &mut self.0 {
+        #[cfg(feature = "session-store")]
+        if let Some(fetch) = &mut self.fetch {
+            return Some(&mut fetch.io);
+        }
+        match &mut self.inner {
             MidHandshake::Handshaking(sess) => Some(sess.get_mut().0),
             MidHandshake::SendAlert { io, .. } => Some(io),
             MidHandshake::Error { io, .. } => Some(io),
             MidHandshake::End => None,
         }
     }
+
+    /// Returns the connection while the handshake is in progress, to see how far it
+    /// got.
+    ///
+    /// Returns `None` before the handshake has started, after it failed, and once
+    /// the future has completed.
+    pub fn session(&self) -> Option<&ClientConnection> {
+        match &self.inner {
+            MidHandshake::Handshaking(sess) => Some(sess.get_ref().1),
+            _ => None,
+        }
+    }
+
+    /// Returns the connection while the handshake is in progress.
+    ///
+    /// Returns `None` before the handshake has started, after it failed, and once
+    /// the future has completed.
+    pub fn session_mut(&mut self) -> Option<&mut ClientConnection> {
+        match &mut self.inner {
+            MidHandshake::Handshaking(sess) => Some(sess.get_mut().1),
+            _ => None,
+        }
+    }
 }
 
 impl<IO> Accept<IO> {
+    /// Abandon the handshake once `token` is cancelled.
+    ///
+    /// The peer is sent a `close_notify` alert if the IO accepts it without waiting,
+    /// and the future fails with an error of kind `ConnectionAborted`.
+    #[cfg(feature = "cancel")]
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Cancel::new(token);
+        self
+    }
+
     #[inline]
     pub fn into_fallible(self) -> FallibleAccept<IO> {
-        FallibleAccept(self.0)
+        FallibleAccept {
+            inner: self.inner,
+            verify: self.verify,
+            metrics: self.metrics,
+            audit: self.audit,
+            observe: self.observe,
+            hello: self.hello,
+            replay: self.replay,
+            #[cfg(feature = "early-data")]
+            early: self.early,
+            #[cfg(feature = "cancel")]
+            cancel: self.cancel,
+            #[cfg(feature = "timeout")]
+            deadline: self.deadline,
+        }
     }
 
+    /// Returns the IO while the handshake is pending, for instance to set socket
+    /// options on it.
+    ///
+    /// Returns `None` once the future has completed.
     pub fn get_ref(&self) -> Option<&IO> {
-        match &self.0 {
+        match &self.inner {
             MidHandshake::Handshaking(sess) => Some(sess.get_ref().0),
             MidHandshake::SendAlert { io, .. } => Some(io),
             MidHandshake::Error { io, .. } => Some(io),
@@ -395,14 +1727,75 @@ impl<IO> Accept<IO> {
         }
     }
 
+    /// Returns the IO while the handshake is pending.
+    ///
+    /// Returns `None` once the future has completed.
     pub fn get_mut(&mut self) -> Option<&mut IO> {
-        match &mut self.0 {
+        match &mut self.inner {
             MidHandshake::Handshaking(sess) => Some(sess.get_mut().0),
             MidHandshake::SendAlert { io, .. } => Some(io),
             MidHandshake::Error { io, .. } => Some(io),
             MidHandshake::End => None,
         }
     }
+
+    /// Returns the connection while the handshake is in progress, to see how far it
+    /// got.
+    ///
+    /// Returns `None` before the handshake has started, after it failed, and once
+    /// the future has completed.
+    pub fn session(&self) -> Option<&ServerConnection> {
+        match &self.inner {
+            MidHandshake::Handshaking(sess) => Some(sess.get_ref().1),
+            _ => None,
+        }
+    }
+
+    /// Returns the connection while the handshake is in progress.
+    ///
+    /// Returns `None` before the handshake has started, after it failed, and once
+    /// the future has completed.
+    pub fn session_mut(&mut self) -> Option<&mut ServerConnection> {
+        match &mut self.inner {
+            MidHandshake::Handshaking(sess) => Some(sess.get_mut().1),
+            _ => None,
+        }
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Connect<IO> {
+    fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<client::TlsStream<IO>>> {
+        let this = self;
+        #[cfg(feature = "cancel")]
+        if this.cancel.poll_cancelled(cx) {
+            #[cfg(feature = "session-store")]
+            if this.fetch.take().is_some() {
+                return Poll::Ready(Err(cancel::cancelled()));
+            }
+            if this.verify.abort(cx).is_some() {
+                return Poll::Ready(Err(cancel::cancelled()));
+            }
+            return Poll::Ready(Err(this.inner.abort(cx, cancel::cancelled()).0));
+        }
+        #[cfg(feature = "session-store")]
+        ready!(session::poll_fetch(&mut this.fetch, &mut this.inner, cx));
+        #[cfg(feature = "early-data")]
+        if let Some(queue) = this.early.take() {
+            if let MidHandshake::Handshaking(stream) = &mut this.inner {
+                stream.send_queued_early_data(&queue);
+            }
+        }
+        let inner = &mut this.inner;
+        let stream =
+            ready!(this.verify.poll(cx, |cx| Pin::new(inner).poll(cx))).map_err(|(err, _)| {
+                this.audit.handshake_failed(Side::Client, &err);
+                err
+            })?;
+        this.metrics.handshake_completed(&stream.session);
+        this.audit
+            .handshake_completed(Side::Client, &stream.session);
+        Poll::Ready(Ok(stream))
+    }
 }
 
 impl<IO: AsyncRead + AsyncWrite + Unpin> Future for Connect<IO> {
@@ -410,7 +1803,70 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> Future for Connect<IO> {
 
     #[inline]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        Pin::new(&mut self.0).poll(cx).map_err(|(err, _)| err)
+        let this = &mut *self;
+        #[cfg(feature = "tracing")]
+        let _span = this.observe.span.clone().entered();
+        this.observe.poll_started();
+        let output = ready!(this.poll_handshake(cx));
+        match &output {
+            Ok(stream) => this.observe.completed(&stream.session, None),
+            Err(err) => this.observe.failed(err),
+        }
+        Poll::Ready(output)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Accept<IO> {
+    fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<server::TlsStream<IO>>> {
+        let this = self;
+        #[cfg(feature = "cancel")]
+        if this.cancel.poll_cancelled(cx) {
+            if this.verify.abort(cx).is_some() {
+                return Poll::Ready(Err(cancel::cancelled()));
+            }
+            return Poll::Ready(Err(this.inner.abort(cx, cancel::cancelled()).0));
+        }
+        #[cfg(feature = "timeout")]
+        if this.deadline.poll_expired(cx) {
+            if this.verify.abort(cx).is_some() {
+                return Poll::Ready(Err(timeout::timed_out()));
+            }
+            return Poll::Ready(Err(this.inner.abort(cx, timeout::timed_out()).0));
+        }
+        let (inner, hello, replay) = (&mut this.inner, &mut this.hello, &mut this.replay);
+        let handshake =
+            |cx: &mut Context<'_>| hello.poll(|| replay.poll(|| Pin::new(inner).poll(cx)));
+        let polled = this.verify.poll(cx, handshake);
+        // Early data arrives ahead of the end of the handshake.
+        if let MidHandshake::Handshaking(stream) = &mut this.inner {
+            if let Err(err) = this.replay.check(&mut stream.session) {
+                this.audit.handshake_failed(Side::Server, &err);
+                return Poll::Ready(Err(this.inner.abort(cx, err).0));
+            }
+            #[cfg(feature = "early-data")]
+            if let Some(early) = &this.early {
+                early.send(&mut stream.session);
+            }
+        }
+        let mut stream = ready!(polled).map_err(|(err, _)| {
+            this.audit.handshake_failed(Side::Server, &err);
+            err
+        })?;
+        if let Err(err) = this.replay.check(&mut stream.session) {
+            this.audit.handshake_failed(Side::Server, &err);
+            stream.handshake_failed();
+            return Poll::Ready(Err(err));
+        }
+        this.metrics.handshake_completed(&stream.session);
+        this.audit
+            .handshake_completed(Side::Server, &stream.session);
+        stream.client_hello = this.hello.take();
+        stream.handshake_completed();
+        #[cfg(feature = "early-data")]
+        if let Some(early) = this.early.take() {
+            early.finish(&mut stream);
+        }
+        Poll::Ready(Ok(stream))
     }
 }
 
@@ -419,7 +1875,56 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> Future for Accept<IO> {
 
     #[inline]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        Pin::new(&mut self.0).poll(cx).map_err(|(err, _)| err)
+        let this = &mut *self;
+        #[cfg(feature = "tracing")]
+        let _span = this.observe.span.clone().entered();
+        this.observe.poll_started();
+        let output = ready!(this.poll_handshake(cx));
+        match &output {
+            Ok(stream) => this
+                .observe
+                .completed(&stream.session, stream.session.server_name()),
+            Err(err) => this.observe.failed(err),
+        }
+        Poll::Ready(output)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> FallibleConnect<IO> {
+    fn poll_handshake(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<client::TlsStream<IO>, (io::Error, IO)>> {
+        let this = self;
+        #[cfg(feature = "cancel")]
+        if this.cancel.poll_cancelled(cx) {
+            #[cfg(feature = "session-store")]
+            if let Some(fetch) = this.fetch.take() {
+                return Poll::Ready(Err((cancel::cancelled(), fetch.io)));
+            }
+            if let Some(io) = this.verify.abort(cx) {
+                return Poll::Ready(Err((cancel::cancelled(), io)));
+            }
+            return Poll::Ready(Err(this.inner.abort(cx, cancel::cancelled())));
+        }
+        #[cfg(feature = "session-store")]
+        ready!(session::poll_fetch(&mut this.fetch, &mut this.inner, cx));
+        #[cfg(feature = "early-data")]
+        if let Some(queue) = this.early.take() {
+            if let MidHandshake::Handshaking(stream) = &mut this.inner {
+                stream.send_queued_early_data(&queue);
+            }
+        }
+        let inner = &mut this.inner;
+        let stream =
+            ready!(this.verify.poll(cx, |cx| Pin::new(inner).poll(cx))).map_err(|(err, io)| {
+                this.audit.handshake_failed(Side::Client, &err);
+                (err, io)
+            })?;
+        this.metrics.handshake_completed(&stream.session);
+        this.audit
+            .handshake_completed(Side::Client, &stream.session);
+        Poll::Ready(Ok(stream))
     }
 }
 
@@ -428,7 +1933,72 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> Future for FallibleConnect<IO> {
 
     #[inline]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        Pin::new(&mut self.0).poll(cx)
+        let this = &mut *self;
+        #[cfg(feature = "tracing")]
+        let _span = this.observe.span.clone().entered();
+        this.observe.poll_started();
+        let output = ready!(this.poll_handshake(cx));
+        match &output {
+            Ok(stream) => this.observe.completed(&stream.session, None),
+            Err((err, _)) => this.observe.failed(err),
+        }
+        Poll::Ready(output)
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> FallibleAccept<IO> {
+    fn poll_handshake(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<server::TlsStream<IO>, (io::Error, IO)>> {
+        let this = self;
+        #[cfg(feature = "cancel")]
+        if this.cancel.poll_cancelled(cx) {
+            if let Some(io) = this.verify.abort(cx) {
+                return Poll::Ready(Err((cancel::cancelled(), io)));
+            }
+            return Poll::Ready(Err(this.inner.abort(cx, cancel::cancelled())));
+        }
+        #[cfg(feature = "timeout")]
+        if this.deadline.poll_expired(cx) {
+            if let Some(io) = this.verify.abort(cx) {
+                return Poll::Ready(Err((timeout::timed_out(), io)));
+            }
+            return Poll::Ready(Err(this.inner.abort(cx, timeout::timed_out())));
+        }
+        let (inner, hello, replay) = (&mut this.inner, &mut this.hello, &mut this.replay);
+        let handshake =
+            |cx: &mut Context<'_>| hello.poll(|| replay.poll(|| Pin::new(inner).poll(cx)));
+        let polled = this.verify.poll(cx, handshake);
+        if let MidHandshake::Handshaking(stream) = &mut this.inner {
+            if let Err(err) = this.replay.check(&mut stream.session) {
+                this.audit.handshake_failed(Side::Server, &err);
+                return Poll::Ready(Err(this.inner.abort(cx, err)));
+            }
+            #[cfg(feature = "early-data")]
+            if let Some(early) = &this.early {
+                early.send(&mut stream.session);
+            }
+        }
+        let mut stream = ready!(polled).map_err(|(err, io)| {
+            this.audit.handshake_failed(Side::Server, &err);
+            (err, io)
+        })?;
+        if let Err(err) = this.replay.check(&mut stream.session) {
+            this.audit.handshake_failed(Side::Server, &err);
+            stream.handshake_failed();
+            return Poll::Ready(Err((err, stream.into_io())));
+        }
+        this.metrics.handshake_completed(&stream.session);
+        this.audit
+            .handshake_completed(Side::Server, &stream.session);
+        stream.client_hello = this.hello.take();
+        stream.handshake_completed();
+        #[cfg(feature = "early-data")]
+        if let Some(early) = this.early.take() {
+            early.finish(&mut stream);
+        }
+        Poll::Ready(Ok(stream))
     }
 }
 
@@ -437,7 +2007,18 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> Future for FallibleAccept<IO> {
 
     #[inline]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        Pin::new(&mut self.0).poll(cx)
+        let this = &mut *self;
+        #[cfg(feature = "tracing")]
+        let _span = this.observe.span.clone().entered();
+        this.observe.poll_started();
+        let output = ready!(this.poll_handshake(cx));
+        match &output {
+            Ok(stream) => this
+                .observe
+                .completed(&stream.session, stream.session.server_name()),
+            Err((err, _)) => this.observe.failed(err),
+        }
+        Poll::Ready(output)
     }
 }
 
@@ -480,6 +2061,128 @@ impl<T> TlsStream<T> {
             }
         }
     }
+
+    /// Splits the stream into halves that can be read from and written to by
+    /// separate tasks, see [`client::TlsStream::into_split`].
+    pub fn into_split(self) -> (ReadHalf<Self>, WriteHalf<Self>) {
+        split::split(self)
+    }
+
+    /// Replaces the underlying IO, returning the old one.
+    ///
+    /// See [`client::TlsStream::replace_io`].
+    pub fn replace_io(&mut self, io: T) -> T {
+        match self {
+            TlsStream::Client(stream) => stream.replace_io(io),
+            TlsStream::Server(stream) => stream.replace_io(io),
+        }
+    }
+
+    /// Returns the state of the connection, without having to attempt a read or write.
+    pub fn connection_state(&self) -> ConnectionState {
+        match self {
+            TlsStream::Client(io) => io.connection_state(),
+            TlsStream::Server(io) => io.connection_state(),
+        }
+    }
+
+    /// Returns whether the handshake has yet to complete, as while early data is
+    /// being sent.
+    pub fn is_handshaking(&self) -> bool {
+        match self {
+            TlsStream::Client(io) => io.is_handshaking(),
+            TlsStream::Server(io) => io.is_handshaking(),
+        }
+    }
+
+    /// Returns whether rustls holds ciphertext that hasn't been written to the IO
+    /// yet; flushing the stream writes it out.
+    pub fn wants_write(&self) -> bool {
+        match self {
+            TlsStream::Client(io) => io.wants_write(),
+            TlsStream::Server(io) => io.wants_write(),
+        }
+    }
+
+    /// Returns why the read side of the stream ended, or `None` while it is open.
+    pub fn read_eof_reason(&self) -> Option<EofReason> {
+        match self {
+            TlsStream::Client(io) => io.read_eof_reason(),
+            TlsStream::Server(io) => io.read_eof_reason(),
+        }
+    }
+
+    /// Returns the negotiated ALPN protocol, if any.
+    pub fn alpn_protocol(&self) -> Option<AlpnProtocol> {
+        match self {
+            TlsStream::Client(io) => io.alpn_protocol(),
+            TlsStream::Server(io) => io.alpn_protocol(),
+        }
+    }
+
+    /// Returns the raw identifier of the negotiated ALPN protocol, without copying it.
+    pub fn alpn_protocol_bytes(&self) -> Option<&[u8]> {
+        match self {
+            TlsStream::Client(io) => io.alpn_protocol_bytes(),
+            TlsStream::Server(io) => io.alpn_protocol_bytes(),
+        }
+    }
+
+    /// Returns the certificate chain the peer presented, end-entity certificate first.
+    pub fn peer_certificates(&self) -> Option<&[pki_types::CertificateDer<'static>]> {
+        self.get_ref().1.peer_certificates()
+    }
+
+    /// Returns the negotiated TLS version, once the handshake has got that far.
+    pub fn protocol_version(&self) -> Option<rustls::ProtocolVersion> {
+        self.get_ref().1.protocol_version()
+    }
+
+    /// Returns the negotiated cipher suite, once the handshake has got that far.
+    pub fn negotiated_cipher_suite(&self) -> Option<rustls::SupportedCipherSuite> {
+        self.get_ref().1.negotiated_cipher_suite()
+    }
+
+    /// Returns the key exchange group used for the handshake, see
+    /// [`client::TlsStream::negotiated_key_exchange_group`].
+    pub fn negotiated_key_exchange_group(
+        &self,
+    ) -> Option<&'static dyn rustls::crypto::SupportedKxGroup> {
+        self.get_ref().1.negotiated_key_exchange_group()
+    }
+
+    /// Derives `output_len` bytes of keying material from the session, see
+    /// [`client::TlsStream::export_keying_material`].
+    pub fn export_keying_material(
+        &self,
+        label: &[u8],
+        context: Option<&[u8]>,
+        output_len: usize,
+    ) -> io::Result<Vec<u8>> {
+        match self {
+            TlsStream::Client(io) => io.export_keying_material(label, context, output_len),
+            TlsStream::Server(io) => io.export_keying_material(label, context, output_len),
+        }
+    }
+
+    /// Update the traffic keys, and ask the peer to update theirs.
+    ///
+    /// See [`client::TlsStream::refresh_traffic_keys`].
+    pub fn refresh_traffic_keys(&mut self) -> io::Result<()> {
+        match self {
+            TlsStream::Client(io) => io.refresh_traffic_keys(),
+            TlsStream::Server(io) => io.refresh_traffic_keys(),
+        }
+    }
+
+    /// Returns a receiver for the TLS-level events of this stream.
+    #[cfg(feature = "events")]
+    pub fn subscribe(&mut self) -> tokio::sync::mpsc::UnboundedReceiver<StreamEvent> {
+        match self {
+            TlsStream::Client(io) => io.subscribe(),
+            TlsStream::Server(io) => io.subscribe(),
+        }
+    }
 }
 
 impl<T> From<client::TlsStream<T>> for TlsStream<T> {
@@ -494,7 +2197,7 @@ impl<T> From<server::TlsStream<T>> for TlsStream<T> {
     }
 }
 
-#[cfg(unix)]
+#[cfg(any(unix, target_os = "wasi"))]
 impl<S> AsRawFd for TlsStream<S>
 where
     S: AsRawFd,
@@ -504,6 +2207,16 @@ where
     }
 }
 
+#[cfg(any(unix, target_os = "wasi"))]
+impl<S> AsFd for TlsStream<S>
+where
+    S: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.get_ref().0.as_fd()
+    }
+}
+
 #[cfg(windows)]
 impl<S> AsRawSocket for TlsStream<S>
 where
@@ -514,6 +2227,111 @@ where
     }
 }
 
+#[cfg(windows)]
+impl<S> AsSocket for TlsStream<S>
+where
+    S: AsSocket,
+{
+    fn as_socket(&self) -> BorrowedSocket<'_> {
+        self.get_ref().0.as_socket()
+    }
+}
+
+impl<T> TlsStream<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Write the contents of `buf`, advancing it past the bytes written.
+    ///
+    /// Unlike `poll_write`, this hands rustls all chunks of a non-contiguous buffer
+    /// (such as a chain of [`Bytes`](bytes::Bytes)) at once, so they can share TLS
+    /// records without being copied into one slice first.
+    ///
+    /// Like `poll_write`, this does not guarantee the data has been sent; call `flush`.
+    #[cfg(feature = "bytes")]
+    pub fn poll_write_buf<B: Buf>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            TlsStream::Client(x) => Pin::new(x).poll_write_buf(cx, buf),
+            TlsStream::Server(x) => Pin::new(x).poll_write_buf(cx, buf),
+        }
+    }
+
+    /// Write all of `buf`, see [`TlsStream::poll_write_buf`].
+    #[cfg(feature = "bytes")]
+    pub async fn write_all_buf<B: Buf>(&mut self, buf: &mut B) -> io::Result<()> {
+        while buf.has_remaining() {
+            let n = poll_fn(|cx| Pin::new(&mut *self).poll_write_buf(cx, buf)).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Read plaintext into the spare capacity of `buf`, advancing it past the bytes read.
+    ///
+    /// Plaintext is copied straight from rustls into `buf`, such as a
+    /// [`BytesMut`](bytes::BytesMut), without an intermediate buffer. Returns `Ok(0)` at
+    /// the end of the stream, or if `buf` has no room left.
+    #[cfg(feature = "bytes")]
+    pub fn poll_read_buf<B: BufMut>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<io::Result<usize>> {
+        common::poll_read_buf(self, cx, buf)
+    }
+
+    /// Read plaintext into `buf`, see [`TlsStream::poll_read_buf`].
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> io::Result<usize> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_read_buf(cx, buf)).await
+    }
+
+    /// Copy plaintext into `buf` without consuming it, so that the next read returns
+    /// it again, like `TcpStream::poll_peek`.
+    ///
+    /// Only the plaintext of the first TLS record rustls has buffered is copied, so
+    /// this may return fewer bytes than have arrived; reading makes way for the rest.
+    /// Returns `Ok(0)` at the end of the stream, or if `buf` has no room left.
+    pub fn poll_peek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<usize>> {
+        common::poll_peek(self, cx, buf)
+    }
+
+    /// Copy plaintext into `buf` without consuming it, see [`TlsStream::poll_peek`].
+    pub async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buf = ReadBuf::new(buf);
+        poll_fn(|cx| Pin::new(&mut *self).poll_peek(cx, &mut buf)).await
+    }
+
+    /// Ends the write side of the stream, and keeps the read side open, see
+    /// [`client::TlsStream::shutdown_write`].
+    pub async fn shutdown_write(&mut self) -> io::Result<()> {
+        match self {
+            TlsStream::Client(io) => io.shutdown_write().await,
+            TlsStream::Server(io) => io.shutdown_write().await,
+        }
+    }
+
+    /// Closes the connection in both directions, see
+    /// [`client::TlsStream::graceful_shutdown`].
+    #[cfg(feature = "timeout")]
+    pub async fn graceful_shutdown(&mut self, timeout: Duration) -> io::Result<EofReason> {
+        match self {
+            TlsStream::Client(io) => io.graceful_shutdown(timeout).await,
+            TlsStream::Server(io) => io.graceful_shutdown(timeout).await,
+        }
+    }
+}
+
 impl<T> AsyncRead for TlsStream<T>
 where
     T: AsyncRead + AsyncWrite + Unpin,
@@ -531,6 +2349,27 @@ where
     }
 }
 
+impl<T> AsyncBufRead for TlsStream<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    #[inline]
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        match self.get_mut() {
+            TlsStream::Client(x) => Pin::new(x).poll_fill_buf(cx),
+            TlsStream::Server(x) => Pin::new(x).poll_fill_buf(cx),
+        }
+    }
+
+    #[inline]
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        match self.get_mut() {
+            TlsStream::Client(x) => Pin::new(x).consume(amt),
+            TlsStream::Server(x) => Pin::new(x).consume(amt),
+        }
+    }
+}
+
 impl<T> AsyncWrite for TlsStream<T>
 where
     T: AsyncRead + AsyncWrite + Unpin,
@@ -547,6 +2386,23 @@ where
         }
     }
 
+    #[inline]
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            TlsStream::Client(x) => Pin::new(x).poll_write_vectored(cx, bufs),
+            TlsStream::Server(x) => Pin::new(x).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
     #[inline]
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.get_mut() {
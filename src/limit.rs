@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The error a handshake fails with when the acceptor is over its rate limit, as the
+/// inner error of an `io::Error`, see
+/// [`TlsAcceptor::rate_limit`](crate::TlsAcceptor::rate_limit).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimited;
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("too many TLS handshakes, try again later")
+    }
+}
+
+impl Error for RateLimited {}
+
+/// A token bucket, holding up to `burst` handshakes and refilled at `rate` handshakes
+/// per second.
+#[derive(Debug)]
+pub(crate) struct RateLimit {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimit {
+    pub(crate) fn new(per_second: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: f64::from(per_second),
+            burst,
+            bucket: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Takes a token for a handshake, if there is one.
+    pub(crate) fn try_acquire(&self) -> bool {
+        let now = Instant::now();
+        let mut bucket = self.bucket.lock().unwrap_or_else(|err| err.into_inner());
+        let (tokens, last) = *bucket;
+        let tokens = (tokens + now.duration_since(last).as_secs_f64() * self.rate).min(self.burst);
+        let acquired = tokens >= 1.0;
+        *bucket = (if acquired { tokens - 1.0 } else { tokens }, now);
+        acquired
+    }
+}
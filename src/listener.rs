@@ -0,0 +1,432 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use rustls::server::Acceptor;
+#[cfg(target_os = "linux")]
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::Sleep;
+#[cfg(feature = "cancel")]
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "cancel")]
+use crate::cancel::Cancel;
+
+use crate::common::poll_fn;
+use crate::{server, AlpnProtocol, LazyConfigAcceptor, StartHandshake, TlsAcceptor};
+
+/// A TCP listener that reads the ClientHello of each incoming connection.
+///
+/// Connections are yielded as [`StartHandshake`]s, together with the peer address,
+/// once their ClientHello has arrived, so the server config can be chosen per
+/// connection. ClientHellos are read concurrently in tasks on the current runtime,
+/// so a slow client does not hold up the others.
+///
+/// Besides errors from accepting TCP connections, errors reading the ClientHello of
+/// a connection are also yielded. Neither stops the listener: keep accepting after
+/// an error to serve further connections.
+pub struct LazyTlsListener {
+    listener: TcpListener,
+    pending: JoinSet<io::Result<(StartHandshake<TcpStream>, SocketAddr)>>,
+    timeout: Option<Duration>,
+    #[cfg(feature = "cancel")]
+    cancel: Cancel,
+}
+
+impl LazyTlsListener {
+    pub fn new(listener: TcpListener) -> Self {
+        LazyTlsListener {
+            listener,
+            pending: JoinSet::new(),
+            timeout: None,
+            #[cfg(feature = "cancel")]
+            cancel: Cancel::default(),
+        }
+    }
+
+    /// Binds `shards` listeners to `addr` with `SO_REUSEPORT`, so the kernel spreads
+    /// incoming connections across them.
+    ///
+    /// Accept from each listener in a task of its own, typically one per core, to
+    /// scale accepting connections and reading ClientHellos across a multi-threaded
+    /// runtime. If `addr` has port 0, all listeners share the port picked for the first.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[cfg(target_os = "linux")]
+    pub fn bind_sharded(addr: SocketAddr, shards: usize) -> io::Result<Vec<Self>> {
+        let mut listeners = Vec::with_capacity(shards);
+        let mut addr = addr;
+        for _ in 0..shards {
+            let listener = bind_reuse_port(addr)?;
+            addr = listener.local_addr()?;
+            listeners.push(Self::new(listener));
+        }
+        Ok(listeners)
+    }
+
+    /// Drop connections that have not sent their ClientHello within `timeout`.
+    ///
+    /// Such connections are yielded as errors of kind `TimedOut`.
+    pub fn client_hello_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop accepting connections once `token` is cancelled.
+    ///
+    /// Connections still waiting for their ClientHello are dropped, and `accept`
+    /// fails with an error of kind `ConnectionAborted` from then on; as a stream,
+    /// the listener ends. Pass the token to [`Accept::cancel_on`](crate::Accept::cancel_on)
+    /// to also cancel the handshakes of connections already yielded.
+    #[cfg(feature = "cancel")]
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Cancel::new(token);
+        self
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &TcpListener {
+        &self.listener
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for the next connection to send its ClientHello.
+    pub async fn accept(&mut self) -> io::Result<(StartHandshake<TcpStream>, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    pub fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(StartHandshake<TcpStream>, SocketAddr)>> {
+        #[cfg(feature = "cancel")]
+        if self.cancel.poll_cancelled(cx) {
+            self.pending.abort_all();
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "listener cancelled",
+            )));
+        }
+
+        while let Poll::Ready(accepted) = self.listener.poll_accept(cx) {
+            let (stream, addr) = accepted?;
+            let client_hello = LazyConfigAcceptor::new(Acceptor::default(), stream);
+            let timeout = self.timeout;
+            self.pending.spawn(async move {
+                let start = with_timeout(timeout, client_hello).await?;
+                Ok((start, addr))
+            });
+        }
+
+        poll_pending(&mut self.pending, cx)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn bind_reuse_port(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+impl futures_core::Stream for LazyTlsListener {
+    type Item = io::Result<(StartHandshake<TcpStream>, SocketAddr)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        #[cfg(feature = "cancel")]
+        if this.cancel.poll_cancelled(cx) {
+            this.pending.abort_all();
+            return Poll::Ready(None);
+        }
+        this.poll_accept(cx).map(Some)
+    }
+}
+
+/// A connection accepted by a [`TlsListener`], with what is known about it once the
+/// handshake has completed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TlsConnection {
+    /// The established stream.
+    pub stream: server::TlsStream<TcpStream>,
+    /// The address of the peer.
+    pub remote_addr: SocketAddr,
+    /// The local address the connection was accepted on.
+    pub local_addr: SocketAddr,
+    /// How long the handshake took, from accepting the TCP connection on.
+    pub handshake_duration: Duration,
+    /// The server name the client sent with SNI, if any.
+    pub server_name: Option<String>,
+    /// The negotiated ALPN protocol, if any.
+    pub alpn_protocol: Option<AlpnProtocol>,
+}
+
+/// How a [`TlsListener`] handles errors, see [`TlsListener::error_policy`].
+#[derive(Clone, Default)]
+pub enum ErrorPolicy {
+    /// Yield errors, and keep accepting.
+    #[default]
+    Yield,
+    /// Pass errors to the callback instead of yielding them, and keep accepting.
+    Callback(Arc<dyn Fn(io::Error) + Send + Sync>),
+    /// Yield the first failed connection, and end the listener.
+    Terminate,
+}
+
+impl fmt::Debug for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorPolicy::Yield => f.write_str("Yield"),
+            ErrorPolicy::Callback(_) => f.write_str("Callback(..)"),
+            ErrorPolicy::Terminate => f.write_str("Terminate"),
+        }
+    }
+}
+
+/// A TCP listener that completes the TLS handshake of each incoming connection.
+///
+/// Connections are yielded as [`TlsConnection`]s, holding the established
+/// [`server::TlsStream`] and the addresses, SNI and ALPN protocol of the connection.
+/// Handshakes run concurrently in tasks on the current runtime, so a
+/// slow client does not hold up accepting or the handshakes of others.
+///
+/// Besides errors from accepting TCP connections, failed handshakes are also
+/// yielded as errors. By default neither stops the listener: keep accepting after
+/// an error to serve further connections, or set an [`ErrorPolicy`].
+///
+/// Errors accepting TCP connections other than the peer aborting, such as running
+/// out of file descriptors, pause accepting for a while, from 5 milliseconds up to
+/// a second as they repeat, rather than failing again right away.
+pub struct TlsListener {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    pending: JoinSet<io::Result<TlsConnection>>,
+    timeout: Option<Duration>,
+    policy: ErrorPolicy,
+    backoff: Option<Pin<Box<Sleep>>>,
+    delay: Duration,
+    terminated: bool,
+    #[cfg(feature = "cancel")]
+    cancel: Cancel,
+}
+
+impl TlsListener {
+    const MIN_BACKOFF: Duration = Duration::from_millis(5);
+    const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+    pub fn new(listener: TcpListener, acceptor: TlsAcceptor) -> Self {
+        TlsListener {
+            listener,
+            acceptor,
+            pending: JoinSet::new(),
+            timeout: None,
+            policy: ErrorPolicy::default(),
+            backoff: None,
+            delay: Self::MIN_BACKOFF,
+            terminated: false,
+            #[cfg(feature = "cancel")]
+            cancel: Cancel::default(),
+        }
+    }
+
+    /// Drop connections that have not completed the handshake within `timeout`.
+    ///
+    /// Such connections are yielded as errors of kind `TimedOut`.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Handle errors as `policy` says.
+    ///
+    /// With [`ErrorPolicy::Terminate`], handshakes in progress are dropped once a
+    /// connection fails, and `accept` fails with an error of kind `ConnectionAborted`
+    /// after yielding that failure; as a stream, the listener ends. Errors accepting
+    /// TCP connections that pause accepting are yielded without ending the listener.
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Stop accepting connections once `token` is cancelled.
+    ///
+    /// Handshakes in progress are dropped, and `accept` fails with an error of kind
+    /// `ConnectionAborted` from then on; as a stream, the listener ends.
+    #[cfg(feature = "cancel")]
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Cancel::new(token);
+        self
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &TcpListener {
+        &self.listener
+    }
+
+    #[inline]
+    pub fn acceptor(&self) -> &TlsAcceptor {
+        &self.acceptor
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for the next connection to complete its handshake.
+    pub async fn accept(&mut self) -> io::Result<TlsConnection> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    pub fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<TlsConnection>> {
+        #[cfg(feature = "cancel")]
+        if self.cancel.poll_cancelled(cx) {
+            self.pending.abort_all();
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "listener cancelled",
+            )));
+        }
+
+        if self.terminated {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "listener terminated",
+            )));
+        }
+
+        loop {
+            let (err, paused) = match self.poll_listener(cx) {
+                Some(err) => (err, self.backoff.is_some()),
+                None => match ready!(poll_pending(&mut self.pending, cx)) {
+                    Ok(conn) => return Poll::Ready(Ok(conn)),
+                    Err(err) => (err, false),
+                },
+            };
+
+            match &self.policy {
+                ErrorPolicy::Yield => return Poll::Ready(Err(err)),
+                ErrorPolicy::Callback(callback) => callback(err),
+                ErrorPolicy::Terminate => {
+                    if !paused {
+                        self.terminated = true;
+                        self.pending.abort_all();
+                    }
+                    return Poll::Ready(Err(err));
+                }
+            }
+        }
+    }
+
+    /// Starts handshakes for the connections accepted so far, until accepting fails.
+    fn poll_listener(&mut self, cx: &mut Context<'_>) -> Option<io::Error> {
+        if let Some(backoff) = &mut self.backoff {
+            if backoff.as_mut().poll(cx).is_pending() {
+                return None;
+            }
+            self.backoff = None;
+        }
+
+        while let Poll::Ready(accepted) = self.listener.poll_accept(cx) {
+            let (stream, remote_addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(err) if is_connection_error(&err) => return Some(err),
+                Err(err) => {
+                    // Accepting again right away would most likely fail the same way.
+                    self.backoff = Some(Box::pin(tokio::time::sleep(self.delay)));
+                    self.delay = (self.delay * 2).min(Self::MAX_BACKOFF);
+                    return Some(err);
+                }
+            };
+            self.delay = Self::MIN_BACKOFF;
+            let started = Instant::now();
+            let local_addr = match stream.local_addr() {
+                Ok(addr) => addr,
+                Err(err) => return Some(err),
+            };
+            let handshake = self.acceptor.accept(stream);
+            let timeout = self.timeout;
+            self.pending.spawn(async move {
+                let stream = with_timeout(timeout, handshake).await?;
+                let session = stream.get_ref().1;
+                Ok(TlsConnection {
+                    server_name: session.server_name().map(str::to_owned),
+                    alpn_protocol: stream.alpn_protocol(),
+                    handshake_duration: started.elapsed(),
+                    stream,
+                    remote_addr,
+                    local_addr,
+                })
+            });
+        }
+        None
+    }
+}
+
+/// Returns whether `err` only concerns the connection being accepted.
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, future).await {
+            Ok(output) => output,
+            Err(_) => Err(io::ErrorKind::TimedOut.into()),
+        },
+        None => future.await,
+    }
+}
+
+fn poll_pending<T: 'static>(
+    pending: &mut JoinSet<io::Result<T>>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<T>> {
+    match ready!(pending.poll_join_next(cx)) {
+        Some(Ok(output)) => Poll::Ready(output),
+        Some(Err(err)) => Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, err))),
+        // Nothing in flight: the listener has registered for wakeups on accept.
+        None => Poll::Pending,
+    }
+}
+
+impl futures_core::Stream for TlsListener {
+    type Item = io::Result<TlsConnection>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        #[cfg(feature = "cancel")]
+        if this.cancel.poll_cancelled(cx) {
+            this.pending.abort_all();
+            return Poll::Ready(None);
+        }
+        if this.terminated {
+            return Poll::Ready(None);
+        }
+        this.poll_accept(cx).map(Some)
+    }
+}
@@ -0,0 +1,222 @@
+use std::io;
+// `std::os::wasi::io` is still unstable on wasip2; `std::os::fd` is not.
+#[cfg(target_os = "wasi")]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, AsSocket, BorrowedSocket, RawSocket};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{client, server, TlsAcceptor, TlsConnector, TlsStream};
+
+/// A connection that is encrypted with TLS or not, for protocols where TLS is
+/// optional, like Redis, SMTP or websockets.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum MaybeTlsStream<IO> {
+    Plain(IO),
+    Tls(TlsStream<IO>),
+}
+
+impl<IO> MaybeTlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Connects with TLS if a `connector` is given, and uses `stream` as is otherwise.
+    pub async fn connect(
+        connector: Option<&TlsConnector>,
+        domain: pki_types::ServerName<'static>,
+        stream: IO,
+    ) -> io::Result<Self> {
+        match connector {
+            Some(connector) => Ok(connector.connect(domain, stream).await?.into()),
+            None => Ok(MaybeTlsStream::Plain(stream)),
+        }
+    }
+
+    /// Accepts with TLS if an `acceptor` is given, and uses `stream` as is otherwise.
+    pub async fn accept(acceptor: Option<&TlsAcceptor>, stream: IO) -> io::Result<Self> {
+        match acceptor {
+            Some(acceptor) => Ok(acceptor.accept(stream).await?.into()),
+            None => Ok(MaybeTlsStream::Plain(stream)),
+        }
+    }
+}
+
+impl<IO> MaybeTlsStream<IO> {
+    /// Returns whether the connection is encrypted.
+    pub fn is_tls(&self) -> bool {
+        matches!(self, MaybeTlsStream::Tls(_))
+    }
+
+    /// Returns the underlying IO.
+    pub fn get_ref(&self) -> &IO {
+        match self {
+            MaybeTlsStream::Plain(io) => io,
+            MaybeTlsStream::Tls(stream) => stream.get_ref().0,
+        }
+    }
+
+    /// Returns the underlying IO.
+    ///
+    /// Reading from or writing to it directly corrupts a TLS connection.
+    pub fn get_mut(&mut self) -> &mut IO {
+        match self {
+            MaybeTlsStream::Plain(io) => io,
+            MaybeTlsStream::Tls(stream) => stream.get_mut().0,
+        }
+    }
+}
+
+impl<IO> From<TlsStream<IO>> for MaybeTlsStream<IO> {
+    fn from(stream: TlsStream<IO>) -> Self {
+        MaybeTlsStream::Tls(stream)
+    }
+}
+
+impl<IO> From<client::TlsStream<IO>> for MaybeTlsStream<IO> {
+    fn from(stream: client::TlsStream<IO>) -> Self {
+        MaybeTlsStream::Tls(stream.into())
+    }
+}
+
+impl<IO> From<server::TlsStream<IO>> for MaybeTlsStream<IO> {
+    fn from(stream: server::TlsStream<IO>) -> Self {
+        MaybeTlsStream::Tls(stream.into())
+    }
+}
+
+#[cfg(any(unix, target_os = "wasi"))]
+impl<IO> AsRawFd for MaybeTlsStream<IO>
+where
+    IO: AsRawFd,
+{
+    fn as_raw_fd(&self) -> RawFd {
+        self.get_ref().as_raw_fd()
+    }
+}
+
+#[cfg(any(unix, target_os = "wasi"))]
+impl<IO> AsFd for MaybeTlsStream<IO>
+where
+    IO: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.get_ref().as_fd()
+    }
+}
+
+#[cfg(windows)]
+impl<IO> AsRawSocket for MaybeTlsStream<IO>
+where
+    IO: AsRawSocket,
+{
+    fn as_raw_socket(&self) -> RawSocket {
+        self.get_ref().as_raw_socket()
+    }
+}
+
+#[cfg(windows)]
+impl<IO> AsSocket for MaybeTlsStream<IO>
+where
+    IO: AsSocket,
+{
+    fn as_socket(&self) -> BorrowedSocket<'_> {
+        self.get_ref().as_socket()
+    }
+}
+
+impl<IO> AsyncRead for MaybeTlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    #[inline]
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(io) => Pin::new(io).poll_read(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<IO> AsyncBufRead for MaybeTlsStream<IO>
+where
+    IO: AsyncBufRead + AsyncWrite + Unpin,
+{
+    #[inline]
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(io) => Pin::new(io).poll_fill_buf(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_fill_buf(cx),
+        }
+    }
+
+    #[inline]
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(io) => Pin::new(io).consume(amt),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).consume(amt),
+        }
+    }
+}
+
+impl<IO> AsyncWrite for MaybeTlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    #[inline]
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(io) => Pin::new(io).poll_write(cx, buf),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    #[inline]
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(io) => Pin::new(io).poll_write_vectored(cx, bufs),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            MaybeTlsStream::Plain(io) => io.is_write_vectored(),
+            MaybeTlsStream::Tls(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(io) => Pin::new(io).poll_flush(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(io) => Pin::new(io).poll_shutdown(cx),
+            MaybeTlsStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rustls::server::{ProducesTickets, StoresServerSessions};
+use rustls::{CommonState, HandshakeKind, ServerConfig};
+
+/// A snapshot of the session resumption counters of a [`TlsConnector`](crate::TlsConnector)
+/// or [`TlsAcceptor`](crate::TlsAcceptor).
+///
+/// Counters are shared between clones of the connector or acceptor they were taken from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResumptionMetrics {
+    /// Handshakes that completed, whether full or resumed.
+    pub handshakes: u64,
+    /// Handshakes that resumed a previous session.
+    pub resumed: u64,
+    /// Sessions the peer asked to resume, by presenting a ticket or session id.
+    ///
+    /// Only counted by acceptors created with [`TlsAcceptor::track_resumption`](crate::TlsAcceptor::track_resumption);
+    /// rustls does not let a client observe which of its handshakes offered a session.
+    pub attempted: u64,
+    /// Tickets or session ids issued to peers.
+    ///
+    /// Only counted by acceptors created with [`TlsAcceptor::track_resumption`](crate::TlsAcceptor::track_resumption).
+    pub issued: u64,
+}
+
+/// Handshake and connection counters for one server name, see
+/// [`TlsAcceptor::sni_metrics`](crate::TlsAcceptor::sni_metrics).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SniMetrics {
+    /// Handshakes that completed.
+    pub handshakes: u64,
+    /// Handshakes that failed.
+    pub failures: u64,
+    /// Connections that completed the handshake and have not been dropped yet.
+    pub active: u64,
+}
+
+/// A snapshot of the connection counters of a [`TlsAcceptor`](crate::TlsAcceptor), see
+/// [`TlsAcceptor::stats`](crate::TlsAcceptor::stats).
+///
+/// Counters are shared between clones of the acceptor, and count from its creation.
+/// Connections accepted through [`LazyConfigAcceptor`](crate::LazyConfigAcceptor) are not
+/// counted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AcceptorStats {
+    /// Connections that completed the handshake and have not been dropped yet.
+    pub active: u64,
+    /// Handshakes in progress.
+    pub handshaking: u64,
+    /// Handshakes that completed.
+    pub accepted: u64,
+    /// Handshakes that failed.
+    pub failed: u64,
+    /// Time since the acceptor was created.
+    pub uptime: Duration,
+}
+
+impl AcceptorStats {
+    /// Completed handshakes per second, averaged over the uptime.
+    ///
+    /// For the rate over a shorter window, compare `accepted` between two snapshots.
+    pub fn accept_rate(&self) -> f64 {
+        match self.uptime.as_secs_f64() {
+            secs if secs > 0.0 => self.accepted as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Stats {
+    started: Instant,
+    active: AtomicU64,
+    handshaking: AtomicU64,
+    accepted: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            active: AtomicU64::new(0),
+            handshaking: AtomicU64::new(0),
+            accepted: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Stats {
+    pub(crate) fn snapshot(&self) -> AcceptorStats {
+        AcceptorStats {
+            active: self.active.load(Ordering::Relaxed),
+            handshaking: self.handshaking.load(Ordering::Relaxed),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            uptime: self.started.elapsed(),
+        }
+    }
+
+    /// Counts a handshake that failed before it started.
+    pub(crate) fn failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Ties a server connection to the [`Stats`] of its acceptor.
+#[derive(Debug)]
+pub(crate) struct StatsTracker {
+    stats: Arc<Stats>,
+    counted_in: Option<Gauge>,
+}
+
+#[derive(Debug)]
+enum Gauge {
+    Handshaking,
+    Active,
+}
+
+impl StatsTracker {
+    pub(crate) fn new(stats: Arc<Stats>) -> Self {
+        stats.handshaking.fetch_add(1, Ordering::Relaxed);
+        Self {
+            stats,
+            counted_in: Some(Gauge::Handshaking),
+        }
+    }
+
+    pub(crate) fn completed(&mut self) {
+        self.leave();
+        self.stats.accepted.fetch_add(1, Ordering::Relaxed);
+        self.stats.active.fetch_add(1, Ordering::Relaxed);
+        self.counted_in = Some(Gauge::Active);
+    }
+
+    pub(crate) fn failed(&mut self) {
+        self.leave();
+        self.stats.failed();
+    }
+
+    fn leave(&mut self) {
+        let gauge = match self.counted_in.take() {
+            Some(Gauge::Handshaking) => &self.stats.handshaking,
+            Some(Gauge::Active) => &self.stats.active,
+            None => return,
+        };
+        gauge.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for StatsTracker {
+    fn drop(&mut self) {
+        self.leave();
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    handshakes: AtomicU64,
+    resumed: AtomicU64,
+    attempted: AtomicU64,
+    issued: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn handshake_completed(&self, session: &CommonState) {
+        if session.is_handshaking() {
+            // 0-RTT connections resolve before the handshake has finished.
+            return;
+        }
+
+        self.handshakes.fetch_add(1, Ordering::Relaxed);
+        if session.handshake_kind() == Some(HandshakeKind::Resumed) {
+            self.resumed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn resumption(&self) -> ResumptionMetrics {
+        ResumptionMetrics {
+            handshakes: self.handshakes.load(Ordering::Relaxed),
+            resumed: self.resumed.load(Ordering::Relaxed),
+            attempted: self.attempted.load(Ordering::Relaxed),
+            issued: self.issued.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Per-SNI counters, keyed by server name.
+///
+/// Names are client-controlled, so only the first `max_names` distinct names get an entry
+/// of their own; the rest are counted under `None`, together with handshakes without SNI.
+#[derive(Debug)]
+pub(crate) struct SniTable {
+    max_names: usize,
+    names: Mutex<HashMap<Option<String>, SniMetrics>>,
+}
+
+impl SniTable {
+    pub(crate) fn new(max_names: usize) -> Self {
+        Self {
+            max_names,
+            names: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> HashMap<Option<String>, SniMetrics> {
+        self.lock().clone()
+    }
+
+    fn record(&self, name: Option<&str>, f: impl FnOnce(&mut SniMetrics)) -> Option<String> {
+        let mut names = self.lock();
+        let named = names.len() - usize::from(names.contains_key(&None));
+        let key = name
+            .map(str::to_owned)
+            .filter(|name| named < self.max_names || names.contains_key(&Some(name.clone())));
+
+        f(names.entry(key.clone()).or_default());
+        key
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Option<String>, SniMetrics>> {
+        // Counters stay meaningful even if another thread panicked mid-update.
+        self.names.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Ties a server connection to its entry in an [`SniTable`].
+#[derive(Debug)]
+pub(crate) struct SniTracker {
+    table: Arc<SniTable>,
+    active: Option<Option<String>>,
+}
+
+impl SniTracker {
+    pub(crate) fn new(table: Arc<SniTable>) -> Self {
+        Self {
+            table,
+            active: None,
+        }
+    }
+
+    pub(crate) fn completed(&mut self, name: Option<&str>) {
+        let key = self.table.record(name, |metrics| {
+            metrics.handshakes += 1;
+            metrics.active += 1;
+        });
+        self.active = Some(key);
+    }
+
+    pub(crate) fn failed(&self, name: Option<&str>) {
+        self.table.record(name, |metrics| metrics.failures += 1);
+    }
+}
+
+impl Drop for SniTracker {
+    fn drop(&mut self) {
+        if let Some(key) = self.active.take() {
+            let mut names = self.table.lock();
+            if let Some(metrics) = names.get_mut(&key) {
+                metrics.active -= 1;
+            }
+        }
+    }
+}
+
+/// Wraps the session storage and ticketer of `config` to count into `metrics`.
+pub(crate) fn track_resumption(config: &mut ServerConfig, metrics: &Arc<Metrics>) {
+    config.session_storage = Arc::new(CountingStorage {
+        inner: config.session_storage.clone(),
+        metrics: metrics.clone(),
+    });
+    config.ticketer = Arc::new(CountingTicketer {
+        inner: config.ticketer.clone(),
+        metrics: metrics.clone(),
+    });
+}
+
+#[derive(Debug)]
+struct CountingStorage {
+    inner: Arc<dyn StoresServerSessions>,
+    metrics: Arc<Metrics>,
+}
+
+impl StoresServerSessions for CountingStorage {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        let stored = self.inner.put(key, value);
+        if stored {
+            self.metrics.issued.fetch_add(1, Ordering::Relaxed);
+        }
+        stored
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.metrics.attempted.fetch_add(1, Ordering::Relaxed);
+        self.inner.get(key)
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.metrics.attempted.fetch_add(1, Ordering::Relaxed);
+        self.inner.take(key)
+    }
+
+    fn can_cache(&self) -> bool {
+        self.inner.can_cache()
+    }
+}
+
+#[derive(Debug)]
+struct CountingTicketer {
+    inner: Arc<dyn ProducesTickets>,
+    metrics: Arc<Metrics>,
+}
+
+impl ProducesTickets for CountingTicketer {
+    fn enabled(&self) -> bool {
+        self.inner.enabled()
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.inner.lifetime()
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let ticket = self.inner.encrypt(plain);
+        if ticket.is_some() {
+            self.metrics.issued.fetch_add(1, Ordering::Relaxed);
+        }
+        ticket
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.metrics.attempted.fetch_add(1, Ordering::Relaxed);
+        self.inner.decrypt(cipher)
+    }
+}
@@ -0,0 +1,200 @@
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use pki_types::ServerName;
+use rustls::{CommonState, HandshakeKind, ProtocolVersion, Side, SupportedCipherSuite};
+
+/// Callbacks for the handshakes of a connector or acceptor, see
+/// [`TlsConnector::observer`](crate::TlsConnector::observer) and
+/// [`TlsAcceptor::observer`](crate::TlsAcceptor::observer).
+///
+/// Callbacks run on the task driving the handshake, so they should be quick, like
+/// updating a counter or a histogram. All methods do nothing by default.
+pub trait HandshakeObserver: Send + Sync {
+    /// A handshake was polled for the first time.
+    fn handshake_started(&self, side: Side) {
+        let _ = side;
+    }
+
+    /// A handshake completed.
+    fn handshake_completed(&self, handshake: &CompletedHandshake) {
+        let _ = handshake;
+    }
+
+    /// A handshake failed, was cancelled or timed out.
+    fn handshake_failed(&self, handshake: &FailedHandshake<'_>) {
+        let _ = handshake;
+    }
+}
+
+/// A handshake that completed, see [`HandshakeObserver::handshake_completed`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CompletedHandshake {
+    /// Which end of the connection completed the handshake.
+    pub side: Side,
+    /// Time from the first poll of the handshake to its completion.
+    pub duration: Duration,
+    /// The negotiated protocol version.
+    pub protocol_version: Option<ProtocolVersion>,
+    /// The negotiated cipher suite.
+    pub cipher_suite: Option<SupportedCipherSuite>,
+    /// Whether a previous session was resumed.
+    pub resumed: bool,
+}
+
+/// A handshake that failed, see [`HandshakeObserver::handshake_failed`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FailedHandshake<'a> {
+    /// Which end of the connection the handshake failed on.
+    pub side: Side,
+    /// Time from the first poll of the handshake to its failure.
+    pub duration: Duration,
+    /// The error the handshake future failed with.
+    pub error: &'a io::Error,
+    /// The TLS error inside `error`, if the handshake failed on one rather than on
+    /// the IO.
+    pub tls_error: Option<&'a rustls::Error>,
+}
+
+/// The observer of a handshake future, if any, and its span with the `tracing` feature.
+#[cfg_attr(not(feature = "tracing"), derive(Default))]
+pub(crate) struct Observe {
+    observing: Option<Observing>,
+    started: Option<Instant>,
+    #[cfg(feature = "tracing")]
+    pub(crate) span: tracing::Span,
+}
+
+// `Span` has no `Default`.
+#[cfg(feature = "tracing")]
+impl Default for Observe {
+    fn default() -> Self {
+        Self {
+            observing: None,
+            started: None,
+            span: tracing::Span::none(),
+        }
+    }
+}
+
+struct Observing {
+    observer: Arc<dyn HandshakeObserver>,
+    side: Side,
+}
+
+impl Observe {
+    pub(crate) fn client(
+        observer: &Option<Arc<dyn HandshakeObserver>>,
+        domain: &ServerName<'_>,
+    ) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = domain;
+        Self {
+            observing: Observing::new(observer, Side::Client),
+            started: None,
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "connect",
+                sni = %domain.to_str(),
+                alpn = tracing::field::Empty,
+                kind = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            ),
+        }
+    }
+
+    pub(crate) fn server(observer: &Option<Arc<dyn HandshakeObserver>>) -> Self {
+        Self {
+            observing: Observing::new(observer, Side::Server),
+            started: None,
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "accept",
+                sni = tracing::field::Empty,
+                alpn = tracing::field::Empty,
+                kind = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+            ),
+        }
+    }
+
+    /// Reports the start of the handshake on its first poll.
+    ///
+    /// Futures that are never polled, like those the session store replaces, are
+    /// not reported.
+    pub(crate) fn poll_started(&mut self) {
+        if self.started.is_some() {
+            return;
+        }
+        self.started = Some(Instant::now());
+        event!(debug, "handshake started");
+        if let Some(observing) = &self.observing {
+            observing.observer.handshake_started(observing.side);
+        }
+    }
+
+    /// Reports a completed handshake; `server_name` is the name a server received.
+    pub(crate) fn completed(&self, session: &CommonState, server_name: Option<&str>) {
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(name) = server_name {
+                self.span.record("sni", name);
+            }
+            if let Some(alpn) = session.alpn_protocol() {
+                self.span
+                    .record("alpn", String::from_utf8_lossy(alpn).as_ref());
+            }
+            if let Some(kind) = session.handshake_kind() {
+                self.span.record("kind", tracing::field::debug(kind));
+            }
+            self.span
+                .record("duration_ms", self.elapsed().as_millis() as u64);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = server_name;
+        event!(debug, "handshake completed");
+
+        if let Some(observing) = &self.observing {
+            observing.observer.handshake_completed(&CompletedHandshake {
+                side: observing.side,
+                duration: self.elapsed(),
+                protocol_version: session.protocol_version(),
+                cipher_suite: session.negotiated_cipher_suite(),
+                resumed: session.handshake_kind() == Some(HandshakeKind::Resumed),
+            });
+        }
+    }
+
+    pub(crate) fn failed(&self, error: &io::Error) {
+        #[cfg(feature = "tracing")]
+        self.span
+            .record("duration_ms", self.elapsed().as_millis() as u64);
+        event!(debug, %error, "handshake failed");
+
+        if let Some(observing) = &self.observing {
+            observing.observer.handshake_failed(&FailedHandshake {
+                side: observing.side,
+                duration: self.elapsed(),
+                error,
+                tls_error: error
+                    .get_ref()
+                    .and_then(|err| err.downcast_ref::<rustls::Error>()),
+            });
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.started
+            .map_or(Duration::ZERO, |started| started.elapsed())
+    }
+}
+
+impl Observing {
+    fn new(observer: &Option<Arc<dyn HandshakeObserver>>, side: Side) -> Option<Self> {
+        let observer = observer.clone()?;
+        Some(Self { observer, side })
+    }
+}
@@ -0,0 +1,349 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use sha2::{Digest, Sha256};
+use x509_parser::extensions::{GeneralName, ParsedExtension};
+use x509_parser::oid_registry::OID_PKIX_ACCESS_DESCRIPTOR_OCSP;
+
+/// The future returned by [`OcspFetcher::fetch`].
+pub type OcspFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<u8>>> + Send + 'a>>;
+
+/// How an [`OcspStapler`] talks to OCSP responders, so it works with any HTTP client.
+pub trait OcspFetcher: Send + Sync {
+    /// POSTs `request` to `url` with content type `application/ocsp-request`, and
+    /// returns the body of the response.
+    fn fetch<'a>(&'a self, url: &'a str, request: &'a [u8]) -> OcspFuture<'a>;
+}
+
+/// A server certificate whose OCSP response is fetched and kept fresh, for stapling
+/// it to handshakes.
+///
+/// Install it as the certificate resolver of a `ServerConfig`, and start
+/// [`spawn_refresh`](Self::spawn_refresh). The responder is the one named in the
+/// certificate, and is asked about it with a SHA-256 `CertID`. Responses are stapled
+/// as they come; clients verify them.
+pub struct OcspStapler {
+    /// The certificate with its current response, and when that response expires.
+    state: RwLock<(Arc<CertifiedKey>, Option<SystemTime>)>,
+    url: String,
+    request: Vec<u8>,
+    fetcher: Arc<dyn OcspFetcher>,
+}
+
+impl OcspStapler {
+    /// How long to wait before retrying a failed fetch.
+    const RETRY: Duration = Duration::from_secs(5 * 60);
+    /// How often to refresh responses without a `nextUpdate`.
+    const DEFAULT_REFRESH: Duration = Duration::from_secs(60 * 60);
+
+    /// Staples OCSP responses to `key`, fetched with `fetcher`.
+    ///
+    /// The chain of `key` has to include the issuer of the end-entity certificate,
+    /// and the end-entity certificate has to name an OCSP responder; otherwise this
+    /// fails with an error of kind `InvalidInput`.
+    pub fn new(key: Arc<CertifiedKey>, fetcher: Arc<dyn OcspFetcher>) -> io::Result<Self> {
+        let (cert, issuer) = match &key.cert[..] {
+            [cert, issuer, ..] => (parse(cert)?, parse(issuer)?),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "certificate chain does not include the issuer",
+                ))
+            }
+        };
+
+        let url = cert
+            .extensions()
+            .iter()
+            .filter_map(|ext| match ext.parsed_extension() {
+                ParsedExtension::AuthorityInfoAccess(aia) => Some(aia),
+                _ => None,
+            })
+            .flat_map(|aia| aia.iter())
+            .find_map(|desc| match &desc.access_location {
+                GeneralName::URI(url) if desc.access_method == OID_PKIX_ACCESS_DESCRIPTOR_OCSP => {
+                    Some(url.to_string())
+                }
+                _ => None,
+            })
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "certificate names no OCSP responder",
+                )
+            })?;
+
+        let request = ocsp_request(
+            &Sha256::digest(issuer.subject().as_raw()),
+            &Sha256::digest(&issuer.public_key().subject_public_key.data),
+            cert.raw_serial(),
+        );
+        Ok(Self {
+            state: RwLock::new((key, None)),
+            url,
+            request,
+            fetcher,
+        })
+    }
+
+    /// Returns the URL of the OCSP responder.
+    pub fn responder_url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the certificate served from now on, with the current response.
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.state
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .0
+            .clone()
+    }
+
+    /// Fetches a new response, and staples it from now on.
+    ///
+    /// Resolves to the `nextUpdate` of the response, if it has one. Responses that
+    /// can't be parsed, or that report an error, fail with an error of kind
+    /// `InvalidData`, and the current response is kept.
+    pub async fn refresh(&self) -> io::Result<Option<SystemTime>> {
+        let response = self.fetcher.fetch(&self.url, &self.request).await?;
+        let next_update = next_update(&response)?;
+
+        let mut state = self.state.write().unwrap_or_else(|err| err.into_inner());
+        let mut key = CertifiedKey::clone(&state.0);
+        key.ocsp = Some(response);
+        *state = (Arc::new(key), next_update);
+        Ok(next_update)
+    }
+
+    /// Refresh the response in a task on the current runtime, halfway to its
+    /// `nextUpdate`.
+    ///
+    /// The first response is fetched right away. Failed fetches are retried every
+    /// five minutes, and a response is no longer stapled once it expires. Abort the
+    /// returned handle to stop refreshing.
+    pub fn spawn_refresh(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let wait = match self.refresh().await {
+                    Ok(Some(next_update)) => next_update
+                        .duration_since(SystemTime::now())
+                        .map_or(Self::RETRY, |left| (left / 2).max(Self::RETRY)),
+                    Ok(None) => Self::DEFAULT_REFRESH,
+                    Err(_) => {
+                        self.drop_expired();
+                        Self::RETRY
+                    }
+                };
+                tokio::time::sleep(wait).await;
+            }
+        })
+    }
+
+    fn drop_expired(&self) {
+        let mut state = self.state.write().unwrap_or_else(|err| err.into_inner());
+        if state.1.map_or(false, |expiry| expiry <= SystemTime::now()) {
+            let mut key = CertifiedKey::clone(&state.0);
+            key.ocsp = None;
+            *state = (Arc::new(key), None);
+        }
+    }
+}
+
+impl ResolvesServerCert for OcspStapler {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+impl fmt::Debug for OcspStapler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OcspStapler")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+fn parse(der: &[u8]) -> io::Result<x509_parser::certificate::X509Certificate<'_>> {
+    x509_parser::parse_x509_certificate(der)
+        .map(|(_, cert)| cert)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+/// Encodes an `OCSPRequest` (RFC 6960) for one certificate, without extensions.
+fn ocsp_request(issuer_name_hash: &[u8], issuer_key_hash: &[u8], serial: &[u8]) -> Vec<u8> {
+    const SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
+    let algorithm = der(0x30, &[der(0x06, SHA256), der(0x05, &[])].concat());
+    let cert_id = der(
+        0x30,
+        &[
+            algorithm,
+            der(0x04, issuer_name_hash),
+            der(0x04, issuer_key_hash),
+            der(0x02, serial),
+        ]
+        .concat(),
+    );
+    let request_list = der(0x30, &der(0x30, &cert_id));
+    der(0x30, &der(0x30, &request_list))
+}
+
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match u8::try_from(contents.len()) {
+        Ok(len) if len < 0x80 => out.push(len),
+        _ => {
+            let len = contents.len().to_be_bytes();
+            let zeros = len.iter().take_while(|byte| **byte == 0).count();
+            out.push(0x80 | (len.len() - zeros) as u8);
+            out.extend_from_slice(&len[zeros..]);
+        }
+    }
+    out.extend_from_slice(contents);
+    out
+}
+
+/// Returns the `nextUpdate` of the first response in an `OCSPResponse`.
+fn next_update(response: &[u8]) -> io::Result<Option<SystemTime>> {
+    let response = expect(0x30, response)?.0;
+    let (status, rest) = expect(0x0a, response)?;
+    if status != [0] {
+        return Err(invalid("OCSP responder reported an error"));
+    }
+
+    let response_bytes = expect(0x30, expect(0xa0, rest)?.0)?.0;
+    let basic = expect(0x04, expect(0x06, response_bytes)?.1)?.0;
+    let mut data = expect(0x30, expect(0x30, basic)?.0)?.0;
+    // Skip the version, responder ID and `producedAt`.
+    if data.first() == Some(&0xa0) {
+        data = element(data)?.2;
+    }
+    let responses = expect(0x18, element(data)?.2)?.1;
+    let single = expect(0x30, expect(0x30, responses)?.0)?.0;
+    // Skip the `CertID`, the status and `thisUpdate`.
+    let rest = element(expect(0x30, single)?.1)?.2;
+    let rest = expect(0x18, rest)?.1;
+    match rest.first() {
+        Some(0xa0) => generalized_time(expect(0x18, expect(0xa0, rest)?.0)?.0).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Splits the DER element at the start of `input` into its tag, contents and the
+/// input after it.
+fn element(input: &[u8]) -> io::Result<(u8, &[u8], &[u8])> {
+    let truncated = || invalid("truncated OCSP response");
+    let (&tag, rest) = input.split_first().ok_or_else(truncated)?;
+    let (&len, rest) = rest.split_first().ok_or_else(truncated)?;
+    let (len, rest) = match len {
+        len if len < 0x80 => (usize::from(len), rest),
+        len => {
+            let count = usize::from(len & 0x7f);
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(invalid("invalid length in OCSP response"));
+            }
+            let (len, rest) = rest.split_at(count);
+            let len = len
+                .iter()
+                .fold(0, |len, byte| (len << 8) | usize::from(*byte));
+            (len, rest)
+        }
+    };
+    if rest.len() < len {
+        return Err(truncated());
+    }
+    let (contents, rest) = rest.split_at(len);
+    Ok((tag, contents, rest))
+}
+
+/// Like [`element`], but requires the element to have `tag`.
+fn expect(tag: u8, input: &[u8]) -> io::Result<(&[u8], &[u8])> {
+    match element(input)? {
+        (found, contents, rest) if found == tag => Ok((contents, rest)),
+        _ => Err(invalid("unexpected element in OCSP response")),
+    }
+}
+
+/// Parses a `GeneralizedTime` in UTC, as in `20300101000000Z`.
+fn generalized_time(time: &[u8]) -> io::Result<SystemTime> {
+    let bad_time = || invalid("invalid time in OCSP response");
+    if time.len() < 15 || time.last() != Some(&b'Z') || !time[..14].iter().all(u8::is_ascii_digit) {
+        return Err(bad_time());
+    }
+    let mut fields = [0; 6];
+    for (i, field) in fields.iter_mut().enumerate() {
+        let digits = if i == 0 {
+            &time[..4]
+        } else {
+            &time[2 + 2 * i..4 + 2 * i]
+        };
+        *field = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| digits.parse::<u64>().ok())
+            .ok_or_else(bad_time)?;
+    }
+    let [year, month, day, hour, minute, second] = fields;
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(bad_time());
+    }
+
+    // Days since the epoch of the civil date, by Howard Hinnant's algorithm.
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = (year % 400) * 365 + (year % 400) / 4 - (year % 400) / 100 + day_of_year;
+    let days = (year / 400) * 146_097 + day_of_era - 719_468;
+    let secs = ((days * 24 + hour) * 60 + minute) * 60 + second;
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(secs))
+        .ok_or_else(bad_time)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::generalized_time;
+
+    #[test]
+    fn generalized_times() {
+        let time = generalized_time(b"20300101000000Z").unwrap();
+        assert_eq!(time, UNIX_EPOCH + Duration::from_secs(1_893_456_000));
+        let time = generalized_time(b"20000229235960Z").unwrap();
+        assert_eq!(time, UNIX_EPOCH + Duration::from_secs(951_868_800));
+
+        for time in [
+            &b"20300300000000Z"[..],
+            b"20301301000000Z",
+            b"20300132000000Z",
+            b"20300101240000Z",
+            b"20300101006000Z",
+            b"20300101000061Z",
+            b"2030010100000+Z",
+            b"19690101000000Z",
+            b"20300101000000",
+        ] {
+            assert!(generalized_time(time).is_err(), "{:?}", time);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::{Arc, Mutex};
+
+use rustls::client::Resumption;
+use rustls::{ClientConfig, ServerConfig};
+
+/// Settings for a single [`TlsConnector::connect_with_options`](crate::TlsConnector::connect_with_options)
+/// call, layered on top of the connector's client config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectOptions {
+    sni: bool,
+    pub(crate) resumption: bool,
+}
+
+impl ConnectOptions {
+    /// Options that leave the client config as it is.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to send the server name in the SNI extension.
+    ///
+    /// The server certificate is verified against the name passed to `connect` either way.
+    /// Sending SNI also requires `enable_sni` in the client config.
+    pub fn sni(mut self, enable: bool) -> Self {
+        self.sni = enable;
+        self
+    }
+
+    /// Whether to resume a previous session, and to store the session for later resumption.
+    ///
+    /// Disabling this keeps the connection unlinkable to the connector's other
+    /// connections through session tickets or ids.
+    pub fn resumption(mut self, enable: bool) -> Self {
+        self.resumption = enable;
+        self
+    }
+}
+
+impl Options for ConnectOptions {
+    type Config = ClientConfig;
+
+    fn apply(&self, config: &mut ClientConfig) {
+        config.enable_sni &= self.sni;
+        if !self.resumption {
+            config.resumption = Resumption::disabled();
+        }
+    }
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            sni: true,
+            resumption: true,
+        }
+    }
+}
+
+/// Settings for a single [`TlsAcceptor::accept_with_options`](crate::TlsAcceptor::accept_with_options)
+/// call, layered on top of the acceptor's server config.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AcceptOptions {
+    alpn_protocols: Option<Vec<Vec<u8>>>,
+}
+
+impl AcceptOptions {
+    /// Options that leave the server config as it is.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advertise `protocols` for ALPN instead of the protocols in the server config.
+    ///
+    /// An empty list disables ALPN.
+    pub fn alpn_protocols(mut self, protocols: Vec<Vec<u8>>) -> Self {
+        self.alpn_protocols = Some(protocols);
+        self
+    }
+}
+
+impl Options for AcceptOptions {
+    type Config = ServerConfig;
+
+    fn apply(&self, config: &mut ServerConfig) {
+        if let Some(protocols) = &self.alpn_protocols {
+            config.alpn_protocols = protocols.clone();
+        }
+    }
+}
+
+pub(crate) trait Options: Clone + Default + PartialEq {
+    type Config: Clone;
+
+    fn apply(&self, config: &mut Self::Config);
+}
+
+/// Copies of a connector's or acceptor's config with options applied, made once per
+/// distinct set of options.
+///
+/// Only the `MAX` most recently used copies are kept, as options
+/// such as ALPN lists can differ per tenant or per request. Connectors and acceptors
+/// replace the cache whenever they replace their config.
+/// The copies are made afresh when the config is swapped, see
+/// [`TlsAcceptor::swap_config`](crate::TlsAcceptor::swap_config).
+pub(crate) struct DerivedConfigs<O: Options> {
+    configs: Mutex<Derived<O>>,
+}
+
+struct Derived<O: Options> {
+    base: Option<Arc<O::Config>>,
+    /// Least recently used first.
+    configs: Vec<(O, Arc<O::Config>)>,
+}
+
+impl<O: Options> Default for Derived<O> {
+    fn default() -> Self {
+        Self {
+            base: None,
+            configs: Vec::new(),
+        }
+    }
+}
+
+impl<O: Options> Default for DerivedConfigs<O> {
+    fn default() -> Self {
+        Self {
+            configs: Mutex::default(),
+        }
+    }
+}
+
+impl<O: Options> DerivedConfigs<O> {
+    const MAX: usize = 8;
+
+    pub(crate) fn get(&self, base: &Arc<O::Config>, options: &O) -> Arc<O::Config> {
+        if *options == O::default() {
+            return base.clone();
+        }
+
+        let mut derived = self.configs.lock().unwrap_or_else(|err| err.into_inner());
+        if !derived
+            .base
+            .as_ref()
+            .map_or(false, |old| Arc::ptr_eq(old, base))
+        {
+            derived.base = Some(base.clone());
+            derived.configs.clear();
+        }
+        if let Some(i) = derived.configs.iter().position(|(key, _)| key == options) {
+            let entry = derived.configs.remove(i);
+            let config = entry.1.clone();
+            derived.configs.push(entry);
+            return config;
+        }
+
+        let mut config = O::Config::clone(base);
+        options.apply(&mut config);
+        let config = Arc::new(config);
+        if derived.configs.len() >= Self::MAX {
+            derived.configs.remove(0);
+        }
+        derived.configs.push((options.clone(), config.clone()));
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{DerivedConfigs, Options};
+
+    /// Options that append a byte to the config, unless they are the default.
+    #[derive(Clone, Default, PartialEq)]
+    struct Append(u8);
+
+    impl Options for Append {
+        type Config = Vec<u8>;
+
+        fn apply(&self, config: &mut Vec<u8>) {
+            config.push(self.0);
+        }
+    }
+
+    #[test]
+    fn derived_configs_bounded() {
+        let base = Arc::new(Vec::new());
+        let derived = DerivedConfigs::<Append>::default();
+
+        let first = derived.get(&base, &Append(1));
+        assert_eq!(*first, [1]);
+        for i in 2..20 {
+            assert_eq!(*derived.get(&base, &Append(i)), [i]);
+            // Used all along, so never evicted.
+            assert!(Arc::ptr_eq(&first, &derived.get(&base, &Append(1))));
+        }
+
+        let configs = derived.configs.lock().unwrap();
+        assert_eq!(configs.configs.len(), DerivedConfigs::<Append>::MAX);
+    }
+}
@@ -0,0 +1,207 @@
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+use rustls::{ConnectionCommon, SideData};
+
+/// The future returned by the methods of [`OwnedIo`]: the result of the operation,
+/// and the buffer it was given.
+pub type OwnedBufFuture<'a> = Pin<Box<dyn Future<Output = (io::Result<usize>, Vec<u8>)> + 'a>>;
+
+/// A transport that takes ownership of its buffers while reading and writing, as
+/// completion-based IO like io_uring does.
+///
+/// For instance, for a `tokio_uring::net::TcpStream`:
+///
+/// ```ignore
+/// impl OwnedIo for UringIo {
+///     fn read(&mut self, buf: Vec<u8>) -> OwnedBufFuture<'_> {
+///         Box::pin(self.0.read(buf))
+///     }
+///
+///     fn write(&mut self, buf: Vec<u8>) -> OwnedBufFuture<'_> {
+///         Box::pin(self.0.write(buf).submit())
+///     }
+/// }
+/// ```
+pub trait OwnedIo {
+    /// Reads into the start of `buf`, at most `buf.len()` bytes, and returns how many
+    /// bytes were read, with `buf`; `Ok(0)` means EOF.
+    fn read(&mut self, buf: Vec<u8>) -> OwnedBufFuture<'_>;
+
+    /// Writes from the start of `buf`, and returns how many bytes were written, with
+    /// `buf`.
+    fn write(&mut self, buf: Vec<u8>) -> OwnedBufFuture<'_>;
+}
+
+/// A TLS stream over an [`OwnedIo`] transport.
+///
+/// The poll-based streams of this crate lend borrowed buffers to the IO, which
+/// completion-based IO can't take; this one hands the IO buffers of its own, and
+/// offers `async` methods instead of `AsyncRead` and `AsyncWrite`. `C` is a
+/// `rustls::ClientConnection` or `rustls::ServerConnection`.
+///
+/// Ciphertext is written out before each method returns, so there is nothing to
+/// flush.
+#[derive(Debug)]
+pub struct OwnedTlsStream<IO, C> {
+    io: IO,
+    session: C,
+    read_buf: Vec<u8>,
+    /// The ciphertext in `read_buf` rustls hasn't taken yet.
+    read_pos: usize,
+    read_len: usize,
+    write_buf: Vec<u8>,
+}
+
+impl<IO, C> OwnedTlsStream<IO, C> {
+    /// The size of the ciphertext buffers, enough for a full TLS record.
+    const BUF_SIZE: usize = 18 * 1024;
+
+    /// Runs `session` over `io`.
+    ///
+    /// The handshake is completed by [`handshake`](Self::handshake), or by the first
+    /// read or write.
+    pub fn new(io: IO, session: C) -> Self {
+        Self {
+            io,
+            session,
+            read_buf: Vec::new(),
+            read_pos: 0,
+            read_len: 0,
+            write_buf: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> (&IO, &C) {
+        (&self.io, &self.session)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> (&mut IO, &mut C) {
+        (&mut self.io, &mut self.session)
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> (IO, C) {
+        (self.io, self.session)
+    }
+}
+
+impl<IO, C, SD> OwnedTlsStream<IO, C>
+where
+    IO: OwnedIo,
+    C: DerefMut + Deref<Target = ConnectionCommon<SD>>,
+    SD: SideData,
+{
+    /// Completes the handshake, if it isn't yet.
+    pub async fn handshake(&mut self) -> io::Result<()> {
+        while self.session.is_handshaking() {
+            if self.session.wants_write() {
+                self.write_tls().await?;
+            } else if !self.read_tls().await? {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "tls handshake eof",
+                ));
+            }
+        }
+        self.write_tls().await
+    }
+
+    /// Reads plaintext into `buf`, returning how many bytes were read; `Ok(0)` means
+    /// the peer sent `close_notify`.
+    ///
+    /// An IO ending without `close_notify` fails with an error of kind
+    /// `UnexpectedEof`.
+    pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.handshake().await?;
+        loop {
+            match self.session.reader().read(buf) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+            // Key updates are answered right away.
+            self.write_tls().await?;
+            if !self.read_tls().await? {
+                self.session.read_tls(&mut &[][..])?;
+            }
+        }
+    }
+
+    /// Encrypts all of `buf`, and writes it out.
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        self.handshake().await?;
+        while !buf.is_empty() {
+            let n = self.session.writer().write(buf)?;
+            buf = &buf[n..];
+            if n == 0 && !self.session.wants_write() {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.write_tls().await?;
+        }
+        Ok(())
+    }
+
+    /// Sends `close_notify` to the peer.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.session.send_close_notify();
+        self.write_tls().await
+    }
+
+    /// Hands ciphertext to rustls, reading more from the IO once it has taken all;
+    /// returns `false` at EOF.
+    async fn read_tls(&mut self) -> io::Result<bool> {
+        if self.read_pos == self.read_len {
+            let mut buf = mem::take(&mut self.read_buf);
+            buf.resize(Self::BUF_SIZE, 0);
+            let (result, buf) = self.io.read(buf).await;
+            self.read_buf = buf;
+            self.read_pos = 0;
+            self.read_len = result?.min(self.read_buf.len());
+            if self.read_len == 0 {
+                return Ok(false);
+            }
+        }
+
+        while self.read_pos < self.read_len {
+            let n = self
+                .session
+                .read_tls(&mut &self.read_buf[self.read_pos..self.read_len])?;
+            self.read_pos += n;
+            if let Err(err) = self.session.process_new_packets() {
+                // Send the alert rustls queued, if we can.
+                let _ = self.write_tls().await;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            if n == 0 {
+                break;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Writes out all ciphertext rustls has queued.
+    async fn write_tls(&mut self) -> io::Result<()> {
+        while self.session.wants_write() {
+            let mut buf = mem::take(&mut self.write_buf);
+            buf.clear();
+            self.session.write_tls(&mut buf)?;
+            while !buf.is_empty() {
+                let (result, written) = self.io.write(buf).await;
+                buf = written;
+                match result? {
+                    0 => return Err(io::ErrorKind::WriteZero.into()),
+                    n => {
+                        buf.drain(..n.min(buf.len()));
+                    }
+                }
+            }
+            self.write_buf = buf;
+        }
+        Ok(())
+    }
+}
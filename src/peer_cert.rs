@@ -0,0 +1,109 @@
+use std::fmt::Write;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use rustls::CommonState;
+use sha2::{Digest, Sha256};
+use x509_parser::extensions::GeneralName;
+
+/// The peer's certificate rendered for forwarding to backends, like the `X-SSL-*`
+/// headers of TLS-terminating proxies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCertificate {
+    /// Whether the certificate chain was verified.
+    ///
+    /// rustls only completes handshakes whose peer certificates pass the configured
+    /// verifier, so this is `true` for certificates taken from a connection.
+    pub verified: bool,
+    /// The subject distinguished name, as in `CN=client, O=Example`.
+    pub subject: String,
+    /// The issuer distinguished name.
+    pub issuer: String,
+    /// The subject alternative names, as in `DNS:example.com` or `IP:192.0.2.1`.
+    pub sans: Vec<String>,
+    /// The serial number, as colon-separated hex bytes.
+    pub serial: String,
+    /// The SHA-256 fingerprint of the DER certificate, as lowercase hex.
+    pub fingerprint: String,
+    /// The SHA-256 fingerprint of the DER `SubjectPublicKeyInfo`, as lowercase hex.
+    ///
+    /// Unlike [`fingerprint`](Self::fingerprint), this stays the same when a
+    /// certificate is renewed with the same key, which makes it suited for pinning.
+    pub spki_fingerprint: String,
+    /// Start of the validity period, in seconds since the Unix epoch.
+    pub not_before: i64,
+    /// End of the validity period, in seconds since the Unix epoch.
+    pub not_after: i64,
+}
+
+impl PeerCertificate {
+    /// Renders the end-entity certificate of the peer of `conn`, if it sent one.
+    pub fn from_connection(conn: &CommonState) -> io::Result<Option<Self>> {
+        match conn.peer_certificates().and_then(|certs| certs.first()) {
+            Some(cert) => Self::parse(cert, true).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Renders a DER-encoded certificate.
+    pub fn parse(der: &[u8], verified: bool) -> io::Result<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let mut sans = Vec::new();
+        let extension = cert
+            .subject_alternative_name()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        for name in extension.iter().flat_map(|ext| &ext.value.general_names) {
+            sans.push(match name {
+                GeneralName::DNSName(name) => format!("DNS:{}", name),
+                GeneralName::RFC822Name(email) => format!("email:{}", email),
+                GeneralName::URI(uri) => format!("URI:{}", uri),
+                GeneralName::IPAddress(ip) => match ip.len() {
+                    4 => format!("IP:{}", Ipv4Addr::from(<[u8; 4]>::try_from(*ip).unwrap())),
+                    16 => format!("IP:{}", Ipv6Addr::from(<[u8; 16]>::try_from(*ip).unwrap())),
+                    _ => continue,
+                },
+                _ => continue,
+            });
+        }
+
+        Ok(PeerCertificate {
+            verified,
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            sans,
+            serial: cert.raw_serial_as_string(),
+            fingerprint: hex_sha256(der),
+            spki_fingerprint: hex_sha256(cert.public_key().raw),
+            not_before: cert.validity().not_before.timestamp(),
+            not_after: cert.validity().not_after.timestamp(),
+        })
+    }
+
+    /// Returns the certificate as `X-SSL-Client-*` header names and values.
+    ///
+    /// Values are plain strings; the caller is responsible for encoding them for
+    /// their transport, as DNs may contain characters not valid in HTTP headers.
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let verify = if self.verified { "SUCCESS" } else { "FAILED" };
+        vec![
+            ("X-SSL-Client-Verify", verify.to_owned()),
+            ("X-SSL-Client-S-DN", self.subject.clone()),
+            ("X-SSL-Client-I-DN", self.issuer.clone()),
+            ("X-SSL-Client-SAN", self.sans.join(", ")),
+            ("X-SSL-Client-Serial", self.serial.clone()),
+            ("X-SSL-Client-Fingerprint", self.fingerprint.clone()),
+            ("X-SSL-Client-Not-Before", self.not_before.to_string()),
+            ("X-SSL-Client-Not-After", self.not_after.to_string()),
+        ]
+    }
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    let mut hex = String::with_capacity(64);
+    for byte in Sha256::digest(data) {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
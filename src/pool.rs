@@ -0,0 +1,58 @@
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+
+/// Read-ahead buffers shared between connections, see
+/// [`TlsConnector::buffer_pool`](crate::TlsConnector::buffer_pool) and
+/// [`TlsAcceptor::buffer_pool`](crate::TlsAcceptor::buffer_pool).
+///
+/// A connection takes a buffer from the pool when it reads ahead from its IO, and
+/// puts it back once rustls has consumed what was read, so buffers are recycled
+/// instead of allocated for every new connection. A connection waiting for its IO to
+/// become readable keeps its buffer. rustls allocates its own buffers, which are not
+/// pooled.
+pub struct BufferPool {
+    max_idle: usize,
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    /// Keeps up to `max_idle` buffers that no connection is using; more are freed.
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            max_idle,
+            buffers: Mutex::default(),
+        }
+    }
+
+    /// Returns the number of buffers in the pool that no connection is using.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub(crate) fn take(&self, size: usize) -> Vec<u8> {
+        self.lock()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(size))
+    }
+
+    pub(crate) fn put(&self, mut buf: Vec<u8>) {
+        let mut buffers = self.lock();
+        if buffers.len() < self.max_idle && buf.capacity() > 0 {
+            buf.clear();
+            buffers.push(buf);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+        self.buffers.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("max_idle", &self.max_idle)
+            .field("idle", &self.idle())
+            .finish()
+    }
+}
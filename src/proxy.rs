@@ -0,0 +1,183 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::Poll;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::common::poll_fn;
+use crate::{server, TlsAcceptor};
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The longest v1 header allowed by the spec, `\r\n` included.
+const V1_MAX_LEN: usize = 107;
+
+/// The addresses of a connection relayed by a proxy, as sent in a PROXY protocol
+/// header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProxyHeader {
+    /// The address of the client that connected to the proxy.
+    pub source: SocketAddr,
+    /// The address the client connected to.
+    pub destination: SocketAddr,
+}
+
+impl ProxyHeader {
+    /// Reads a PROXY protocol header, version 1 or 2, from the start of `io`.
+    ///
+    /// Exactly the header is read, so whatever follows it is left in `io`. Returns
+    /// `None` for headers that carry no addresses: v1 `UNKNOWN`, v2 `LOCAL` (the
+    /// proxy's own health checks), and address families other than TCP and UDP over
+    /// IPv4 and IPv6. Streams that don't start with a valid header fail with an error
+    /// of kind `InvalidData`.
+    pub async fn read<IO>(io: &mut IO) -> io::Result<Option<ProxyHeader>>
+    where
+        IO: AsyncRead + Unpin,
+    {
+        // Shorter than the shortest v1 header, so nothing past the header is read.
+        let mut start = [0; 12];
+        read_exact(io, &mut start).await?;
+        match &start {
+            V2_SIGNATURE => read_v2(io).await,
+            start if start.starts_with(b"PROXY ") => read_v1(io, &start[..]).await,
+            _ => Err(invalid("missing PROXY protocol header")),
+        }
+    }
+}
+
+async fn read_v1<IO: AsyncRead + Unpin>(
+    io: &mut IO,
+    start: &[u8],
+) -> io::Result<Option<ProxyHeader>> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() == V1_MAX_LEN {
+            return Err(invalid("PROXY protocol v1 header too long"));
+        }
+        let mut byte = [0];
+        read_exact(io, &mut byte).await?;
+        line.push(byte[0]);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY protocol v1 header is not ASCII"))?;
+    let mut fields = line.split(' ').skip(1);
+    match fields.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid("unknown PROXY protocol v1 address family")),
+    }
+    let mut field = || {
+        fields
+            .next()
+            .ok_or_else(|| invalid("PROXY protocol v1 header too short"))
+    };
+    let (source, destination) = (field()?, field()?);
+    let (source_port, destination_port) = (field()?, field()?);
+    let addr = |ip: &str, port: &str| -> io::Result<SocketAddr> {
+        let ip = ip.parse::<IpAddr>().map_err(invalid)?;
+        let port = port.parse::<u16>().map_err(invalid)?;
+        Ok(SocketAddr::new(ip, port))
+    };
+    Ok(Some(ProxyHeader {
+        source: addr(source, source_port)?,
+        destination: addr(destination, destination_port)?,
+    }))
+}
+
+async fn read_v2<IO: AsyncRead + Unpin>(io: &mut IO) -> io::Result<Option<ProxyHeader>> {
+    let mut header = [0; 4];
+    read_exact(io, &mut header).await?;
+    let [version_command, family, len @ ..] = header;
+    let mut addresses = vec![0; usize::from(u16::from_be_bytes(len))];
+    read_exact(io, &mut addresses).await?;
+
+    match version_command {
+        0x20 => return Ok(None),
+        0x21 => {}
+        _ => return Err(invalid("unknown PROXY protocol v2 version or command")),
+    }
+    let port = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+    match family {
+        // TCP or UDP over IPv4.
+        0x11 | 0x12 if addresses.len() >= 12 => {
+            let ip = |bytes: &[u8]| Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+            Ok(Some(ProxyHeader {
+                source: SocketAddr::new(ip(&addresses[0..4]).into(), port(&addresses[8..10])),
+                destination: SocketAddr::new(ip(&addresses[4..8]).into(), port(&addresses[10..12])),
+            }))
+        }
+        // TCP or UDP over IPv6.
+        0x21 | 0x22 if addresses.len() >= 36 => {
+            let ip = |bytes: &[u8]| {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(bytes);
+                Ipv6Addr::from(octets)
+            };
+            Ok(Some(ProxyHeader {
+                source: SocketAddr::new(ip(&addresses[0..16]).into(), port(&addresses[32..34])),
+                destination: SocketAddr::new(
+                    ip(&addresses[16..32]).into(),
+                    port(&addresses[34..36]),
+                ),
+            }))
+        }
+        0x11 | 0x12 | 0x21 | 0x22 => Err(invalid("PROXY protocol v2 addresses too short")),
+        _ => Ok(None),
+    }
+}
+
+async fn read_exact<IO: AsyncRead + Unpin>(io: &mut IO, buf: &mut [u8]) -> io::Result<()> {
+    let mut buf = ReadBuf::new(buf);
+    poll_fn(|cx| {
+        while buf.remaining() > 0 {
+            let filled = buf.filled().len();
+            ready!(Pin::new(&mut *io).poll_read(cx, &mut buf))?;
+            if buf.filled().len() == filled {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+        Poll::Ready(Ok(()))
+    })
+    .await
+}
+
+fn invalid(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// An acceptor for connections relayed by a proxy that speaks the PROXY protocol,
+/// like HAProxy or an AWS Network Load Balancer.
+///
+/// The header the proxy sends ahead of the TLS handshake is read before the
+/// handshake starts, see [`ProxyHeader::read`]. Connections without a header are
+/// refused: clients reaching the server directly could otherwise claim any address.
+#[derive(Clone)]
+pub struct ProxyProtocolAcceptor {
+    acceptor: TlsAcceptor,
+}
+
+impl ProxyProtocolAcceptor {
+    /// Accepts connections with `acceptor` once the header is read.
+    pub fn new(acceptor: TlsAcceptor) -> Self {
+        Self { acceptor }
+    }
+
+    /// Returns the acceptor connections are accepted with.
+    pub fn acceptor(&self) -> &TlsAcceptor {
+        &self.acceptor
+    }
+
+    /// Reads the PROXY protocol header from `stream`, then accepts the connection.
+    pub async fn accept<IO>(
+        &self,
+        mut stream: IO,
+    ) -> io::Result<(server::TlsStream<IO>, Option<ProxyHeader>)>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let header = ProxyHeader::read(&mut stream).await?;
+        let stream = self.acceptor.accept(stream).await?;
+        Ok((stream, header))
+    }
+}
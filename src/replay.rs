@@ -0,0 +1,167 @@
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rustls::{KeyLog, ServerConnection};
+
+/// Decides whether early (0-RTT) data may be processed, see
+/// [`TlsAcceptor::anti_replay`](crate::TlsAcceptor::anti_replay).
+///
+/// An attacker can record the first flight of a client and send it again. rustls
+/// resumes each session at most once from its session store, which stops replays
+/// to the server that issued the ticket; servers sharing sessions through a common
+/// store, or running several stores for one ticket, need a check across all of
+/// them, keyed on the random of the ClientHello.
+pub trait AntiReplay: Send + Sync {
+    /// Returns whether early data sent with a ClientHello carrying `client_random`
+    /// may be processed, which is only the case the first time it is seen.
+    fn check(&self, client_random: &[u8; 32]) -> bool;
+}
+
+/// An [`AntiReplay`] check that remembers the ClientHellos of one process for
+/// `window`.
+///
+/// rustls rejects early data sent with a ticket whose age is more than a minute
+/// off, so replays come within a minute of the original and a window of a minute
+/// is enough.
+pub struct ReplayCache {
+    window: Duration,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    randoms: HashSet<[u8; 32]>,
+    /// The randoms in `randoms`, oldest first, with when they were seen.
+    order: VecDeque<(Instant, [u8; 32])>,
+}
+
+impl ReplayCache {
+    /// Remembers ClientHellos for `window`.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::default(),
+        }
+    }
+}
+
+impl AntiReplay for ReplayCache {
+    fn check(&self, client_random: &[u8; 32]) -> bool {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap_or_else(|err| err.into_inner());
+        while let Some((at, random)) = seen.order.front().copied() {
+            if now.duration_since(at) < self.window {
+                break;
+            }
+            seen.order.pop_front();
+            seen.randoms.remove(&random);
+        }
+        if !seen.randoms.insert(*client_random) {
+            return false;
+        }
+        seen.order.push_back((now, *client_random));
+        true
+    }
+}
+
+impl fmt::Debug for ReplayCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayCache")
+            .field("window", &self.window)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The label rustls logs the early traffic secret under, with the client random.
+const EARLY_SECRET: &str = "CLIENT_EARLY_TRAFFIC_SECRET";
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Option<[u8; 32]>>> = const { RefCell::new(None) };
+}
+
+/// The anti-replay check of one handshake.
+///
+/// rustls only hands the client random to the key log, which has no way to tell
+/// connections apart. Like the ClientHello summary, the random is logged
+/// synchronously while a handshake is polled, so [`ReplayKeyLog`] hands it over to
+/// the handshake being polled on the same thread.
+#[derive(Default)]
+pub(crate) struct ReplayCheck {
+    guard: Option<Arc<dyn AntiReplay>>,
+    random: Option<[u8; 32]>,
+    checked: bool,
+}
+
+impl ReplayCheck {
+    pub(crate) fn new(guard: Option<Arc<dyn AntiReplay>>) -> Self {
+        Self {
+            guard,
+            random: None,
+            checked: false,
+        }
+    }
+
+    pub(crate) fn poll<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        if self.guard.is_none() {
+            return f();
+        }
+
+        CAPTURED.with(|captured| *captured.borrow_mut() = Some(None));
+        let output = f();
+        if let Some(random) = CAPTURED.with(|captured| captured.borrow_mut().take().flatten()) {
+            self.random = Some(random);
+        }
+        output
+    }
+
+    /// Checks the early data `session` accepted, before any of it is read.
+    ///
+    /// Fails with an error of kind `InvalidData` if it was replayed, or if the
+    /// client random was not captured, as when the key log was replaced.
+    pub(crate) fn check(&mut self, session: &mut ServerConnection) -> io::Result<()> {
+        let guard = match &self.guard {
+            Some(guard) if !self.checked => guard,
+            _ => return Ok(()),
+        };
+        if session.early_data().is_none() {
+            return Ok(());
+        }
+
+        self.checked = true;
+        match self.random {
+            Some(random) if guard.check(&random) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "early data was replayed",
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ReplayKeyLog {
+    pub(crate) inner: Arc<dyn KeyLog>,
+}
+
+impl KeyLog for ReplayKeyLog {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        if label == EARLY_SECRET {
+            CAPTURED.with(|captured| {
+                if let Some(slot) = captured.borrow_mut().as_mut() {
+                    *slot = <[u8; 32]>::try_from(client_random).ok();
+                }
+            });
+        }
+        if self.inner.will_log(label) {
+            self.inner.log(label, client_random, secret);
+        }
+    }
+
+    fn will_log(&self, label: &str) -> bool {
+        label == EARLY_SECRET || self.inner.will_log(label)
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+/// Serves an ECDSA certificate to clients that can verify it, and an RSA
+/// certificate to everyone else.
+///
+/// Both chains are expected to cover the same names. Whether a client can verify
+/// the ECDSA certificate is decided by the signature schemes in its ClientHello.
+#[derive(Debug)]
+pub struct DualCertResolver {
+    ecdsa: Arc<CertifiedKey>,
+    rsa: Arc<CertifiedKey>,
+}
+
+impl DualCertResolver {
+    pub fn new(ecdsa: Arc<CertifiedKey>, rsa: Arc<CertifiedKey>) -> Self {
+        DualCertResolver { ecdsa, rsa }
+    }
+}
+
+impl ResolvesServerCert for DualCertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let schemes = client_hello.signature_schemes();
+        if self.ecdsa.key.choose_scheme(schemes).is_some() {
+            Some(self.ecdsa.clone())
+        } else {
+            Some(self.rsa.clone())
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use pki_types::pem::PemObject;
+use pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::{DigitallySignedStruct, Error, RootCertStore, SignatureScheme};
+
+/// Trust anchors for verifying servers that can be replaced while in use.
+///
+/// Install them with [`TlsConnector::trust_anchors`](crate::TlsConnector::trust_anchors);
+/// every new connection then verifies the server against whatever roots are current
+/// at the time, so long-running clients pick up CA additions and removals without
+/// rebuilding connectors. Updates are pushed with [`set`](Self::set) or
+/// [`load_pem_file`](Self::load_pem_file), or picked up from disk by
+/// [`watch_file`](Self::watch_file). Connections already established are not affected.
+#[derive(Debug)]
+pub struct ReloadingRoots {
+    provider: Arc<CryptoProvider>,
+    verifier: RwLock<Arc<WebPkiServerVerifier>>,
+}
+
+impl ReloadingRoots {
+    /// Verify servers against `roots`, using the signature algorithms of `provider`.
+    ///
+    /// Fails if `roots` is empty.
+    pub fn new(roots: RootCertStore, provider: Arc<CryptoProvider>) -> io::Result<Self> {
+        let verifier = build(roots, &provider)?;
+        Ok(ReloadingRoots {
+            provider,
+            verifier: RwLock::new(verifier),
+        })
+    }
+
+    /// Verify servers on new connections against `roots`.
+    ///
+    /// The current roots are kept if `roots` is empty.
+    pub fn set(&self, roots: RootCertStore) -> io::Result<()> {
+        let verifier = build(roots, &self.provider)?;
+        *self.verifier.write().unwrap_or_else(|err| err.into_inner()) = verifier;
+        Ok(())
+    }
+
+    /// Read PEM certificates from `path`, and trust them, and only them, on new connections.
+    ///
+    /// The current roots are kept if the file cannot be loaded.
+    pub fn load_pem_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(path).map_err(invalid)? {
+            roots.add(cert.map_err(invalid)?).map_err(invalid)?;
+        }
+        self.set(roots)
+    }
+
+    /// Reload the roots from `path` whenever it changes, checking every `interval`
+    /// in a task on the current runtime.
+    ///
+    /// A file that fails to load, for example while it is being rewritten, is retried
+    /// on the next change. Abort the returned handle to stop watching.
+    #[cfg(feature = "reload")]
+    pub fn watch_file(
+        self: Arc<Self>,
+        path: std::path::PathBuf,
+        interval: std::time::Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut seen = None;
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let current = std::fs::metadata(&path)
+                    .and_then(|meta| meta.modified())
+                    .ok();
+                if seen != Some(current) && self.load_pem_file(&path).is_ok() {
+                    seen = Some(current);
+                }
+            }
+        })
+    }
+
+    fn current(&self) -> Arc<WebPkiServerVerifier> {
+        self.verifier
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+fn build(
+    roots: RootCertStore,
+    provider: &Arc<CryptoProvider>,
+) -> io::Result<Arc<WebPkiServerVerifier>> {
+    WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(invalid)
+}
+
+fn invalid(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+impl ServerCertVerifier for ReloadingRoots {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        self.current().verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.current().verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.current().verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.current().supported_verify_schemes()
+    }
+}
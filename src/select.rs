@@ -0,0 +1,72 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use rustls::server::{Acceptor, ClientHello};
+use rustls::ServerConfig;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{server, Accept, LazyConfigAcceptor};
+
+/// Picks the `ServerConfig` for a connection by its ClientHello, for acceptors built
+/// on [`SelectAccept`].
+pub(crate) trait SelectConfig {
+    /// What the selector tells its acceptor about the connection, along with the stream.
+    type Choice: Copy + Unpin;
+
+    fn select_config(
+        &self,
+        client_hello: &ClientHello<'_>,
+    ) -> io::Result<(Arc<ServerConfig>, Self::Choice)>;
+}
+
+/// Reads the ClientHello with a [`LazyConfigAcceptor`], then accepts the connection
+/// with the config `S` selects for it.
+pub(crate) struct SelectAccept<IO, S: SelectConfig> {
+    state: SelectAcceptState<IO, S>,
+}
+
+// Both variants are handshake state of similar size; boxing either would only add
+// an allocation per connection.
+#[allow(clippy::large_enum_variant)]
+enum SelectAcceptState<IO, S: SelectConfig> {
+    ClientHello(LazyConfigAcceptor<IO>, S),
+    Handshaking(Accept<IO>, S::Choice),
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin, S: SelectConfig> SelectAccept<IO, S> {
+    pub(crate) fn new(stream: IO, selector: S) -> Self {
+        SelectAccept {
+            state: SelectAcceptState::ClientHello(
+                LazyConfigAcceptor::new(Acceptor::default(), stream),
+                selector,
+            ),
+        }
+    }
+}
+
+impl<IO, S> Future for SelectAccept<IO, S>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+    S: SelectConfig + Unpin,
+{
+    type Output = io::Result<(server::TlsStream<IO>, S::Choice)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match &mut self.state {
+                SelectAcceptState::ClientHello(lazy, selector) => {
+                    let start = ready!(Pin::new(lazy).poll(cx))?;
+                    let (config, choice) = selector.select_config(&start.client_hello())?;
+                    self.state = SelectAcceptState::Handshaking(start.into_stream(config), choice);
+                }
+                SelectAcceptState::Handshaking(accept, choice) => {
+                    let choice = *choice;
+                    return Pin::new(accept).poll(cx).map_ok(|stream| (stream, choice));
+                }
+            }
+        }
+    }
+}
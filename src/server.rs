@@ -1,15 +1,59 @@
-use std::io;
+use std::io::{self, BufRead, Read};
+#[cfg(target_os = "wasi")]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
 #[cfg(unix)]
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
 #[cfg(windows)]
-use std::os::windows::io::{AsRawSocket, RawSocket};
+use std::os::windows::io::{AsRawSocket, AsSocket, BorrowedSocket, RawSocket};
 use std::pin::Pin;
+#[cfg(feature = "early-data")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "early-data")]
+use std::task::Waker;
 use std::task::{Context, Poll};
+#[cfg(feature = "timeout")]
+use std::time::Duration;
 
-use rustls::ServerConnection;
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+#[cfg(feature = "bytes")]
+use bytes::{Buf, BufMut};
+use pki_types::CertificateDer;
+use rustls::crypto::SupportedKxGroup;
+use rustls::{ProtocolVersion, ServerConnection, SupportedCipherSuite};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
 
-use crate::common::{IoSession, Stream, TlsState};
+use crate::alpn::AlpnProtocol;
+use crate::common::{self, poll_fn};
+use crate::common::{
+    ConnectionState, EofReason, IoSession, ReadAhead, Stream, TlsState, WriteWatermarks,
+};
+#[cfg(feature = "events")]
+use crate::events::{Events, StreamEvent};
+use crate::hello::ClientHelloSummary;
+use crate::metrics::{SniTracker, StatsTracker};
+use crate::split::{self, ReadHalf, WriteHalf};
+#[cfg(feature = "timeout")]
+use crate::timeout;
+#[cfg(feature = "x509")]
+use crate::PeerCertificate;
+
+/// Whether a server accepted 0-RTT data, see [`TlsStream::early_data_status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EarlyDataStatus {
+    /// The handshake has not completed yet.
+    Handshaking,
+    /// No early data was accepted.
+    ///
+    /// Either the client sent none, or it was rejected; rustls does not tell these
+    /// apart.
+    NotAccepted,
+    /// Early data was accepted, and the first `len` bytes read from the stream arrived
+    /// as early data; with `TlsAcceptor::accept_early`, they were read from the
+    /// `EarlyDataReader` instead.
+    ///
+    /// Early data can be replayed by an attacker, so it should only carry requests
+    /// that are safe to process more than once.
+    Accepted { len: usize },
+}
 
 /// A wrapper around an underlying raw stream which implements the TLS or SSL
 /// protocol.
@@ -18,6 +62,17 @@ pub struct TlsStream<IO> {
     pub(crate) io: IO,
     pub(crate) session: ServerConnection,
     pub(crate) state: TlsState,
+    pub(crate) read_ahead: ReadAhead,
+    pub(crate) write_watermarks: WriteWatermarks,
+    pub(crate) read_eof: Option<EofReason>,
+    pub(crate) lenient_eof: bool,
+    #[cfg(feature = "events")]
+    pub(crate) events: Events,
+    pub(crate) tracker: Option<SniTracker>,
+    pub(crate) stats: Option<StatsTracker>,
+    pub(crate) client_hello: Option<ClientHelloSummary>,
+    pub(crate) early_data_status: EarlyDataStatus,
+    pub(crate) early_data: Vec<u8>,
 }
 
 impl<IO> TlsStream<IO> {
@@ -35,6 +90,368 @@ impl<IO> TlsStream<IO> {
     pub fn into_inner(self) -> (IO, ServerConnection) {
         (self.io, self.session)
     }
+
+    /// Splits the stream into halves that can be read from and written to by
+    /// separate tasks, and put back together with [`ReadHalf::reunite`].
+    ///
+    /// Unlike [`tokio::io::split`], both halves give access to the stream, and so to
+    /// the TLS session, through their `lock` methods. Shutting the write half down
+    /// sends `close_notify` once, and leaves the read half open until the client
+    /// closes its side; `reader.lock().read_eof_reason()` then tells whether it did
+    /// so cleanly, which the halves of `tokio::io::split` can't.
+    pub fn into_split(self) -> (ReadHalf<Self>, WriteHalf<Self>) {
+        split::split(self)
+    }
+
+    /// Replaces the underlying IO, returning the old one; the TLS session carries on
+    /// over the new IO.
+    ///
+    /// Flush the stream first: ciphertext rustls has not written out yet is written
+    /// to the new IO. Ciphertext already read ahead from the old IO is kept.
+    #[inline]
+    pub fn replace_io(&mut self, io: IO) -> IO {
+        std::mem::replace(&mut self.io, io)
+    }
+
+    /// Returns the state of the connection, without having to attempt a read or write.
+    #[inline]
+    pub fn connection_state(&self) -> ConnectionState {
+        self.state.connection_state()
+    }
+
+    /// Returns whether the handshake has yet to complete, as while early data is
+    /// being sent.
+    #[inline]
+    pub fn is_handshaking(&self) -> bool {
+        self.state.is_early_data() || self.session.is_handshaking()
+    }
+
+    /// Returns whether rustls holds ciphertext that hasn't been written to the IO
+    /// yet; flushing the stream writes it out.
+    #[inline]
+    pub fn wants_write(&self) -> bool {
+        self.session.wants_write()
+    }
+
+    /// Returns why the read side of the stream ended, or `None` while it is open.
+    ///
+    /// This tells a peer that closed the connection with `close_notify` apart from
+    /// one whose data may have been truncated by the transport closing first.
+    #[inline]
+    pub fn read_eof_reason(&self) -> Option<EofReason> {
+        self.read_eof
+    }
+
+    /// Returns the negotiated ALPN protocol, if any.
+    #[inline]
+    pub fn alpn_protocol(&self) -> Option<AlpnProtocol> {
+        self.session.alpn_protocol().map(AlpnProtocol::from)
+    }
+
+    /// Returns the raw identifier of the negotiated ALPN protocol, without copying it.
+    #[inline]
+    pub fn alpn_protocol_bytes(&self) -> Option<&[u8]> {
+        self.session.alpn_protocol()
+    }
+
+    /// Returns the certificate chain the client presented, end-entity certificate first.
+    ///
+    /// Clients are only asked for a certificate if the server config has a client
+    /// certificate verifier.
+    #[inline]
+    pub fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        self.session.peer_certificates()
+    }
+
+    /// Returns the subject, names, key fingerprint and validity of the certificate the
+    /// client authenticated with, or `None` if it presented none.
+    ///
+    /// Fails with an error of kind `InvalidData` if the certificate can't be parsed.
+    #[cfg(feature = "x509")]
+    pub fn client_identity(&self) -> io::Result<Option<PeerCertificate>> {
+        PeerCertificate::from_connection(&self.session)
+    }
+
+    /// Returns the negotiated TLS version, once the handshake has got that far.
+    #[inline]
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.session.protocol_version()
+    }
+
+    /// Returns the negotiated cipher suite, once the handshake has got that far.
+    #[inline]
+    pub fn negotiated_cipher_suite(&self) -> Option<SupportedCipherSuite> {
+        self.session.negotiated_cipher_suite()
+    }
+
+    /// Returns the key exchange group used for the handshake, once the key exchange
+    /// has completed.
+    ///
+    /// This is `None` for TLS 1.2 sessions that were resumed.
+    #[inline]
+    pub fn negotiated_key_exchange_group(&self) -> Option<&'static dyn SupportedKxGroup> {
+        self.session.negotiated_key_exchange_group()
+    }
+
+    /// Derives `output_len` bytes of keying material from the session, as described
+    /// in RFC 5705 (TLS 1.2) and RFC 8446 section 7.5 (TLS 1.3).
+    ///
+    /// Both peers derive the same bytes for the same `label` and `context`. Fails
+    /// while the handshake is in progress.
+    pub fn export_keying_material(
+        &self,
+        label: &[u8],
+        context: Option<&[u8]>,
+        output_len: usize,
+    ) -> io::Result<Vec<u8>> {
+        self.session
+            .export_keying_material(vec![0; output_len], label, context)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    /// Returns what the client offered in its ClientHello.
+    ///
+    /// This is `None` unless the stream was accepted by a [`TlsAcceptor`](crate::TlsAcceptor)
+    /// created with [`capture_client_hello`](crate::TlsAcceptor::capture_client_hello).
+    #[inline]
+    pub fn client_hello(&self) -> Option<&ClientHelloSummary> {
+        self.client_hello.as_ref()
+    }
+
+    /// Update the traffic keys, and ask the peer to update theirs.
+    ///
+    /// The TLS 1.3 `key_update` message is queued and sent with the next write or
+    /// flush; the stream stays usable throughout. Fails for TLS 1.2 sessions.
+    ///
+    /// rustls already updates keys as the cipher suite requires, and peers may limit
+    /// how many updates they accept, so this is for rolling keys on long-lived
+    /// connections, like before a period of idleness, rather than for frequent use.
+    pub fn refresh_traffic_keys(&mut self) -> io::Result<()> {
+        self.session
+            .refresh_traffic_keys()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        #[cfg(feature = "events")]
+        self.events.emit(StreamEvent::KeyUpdate);
+        Ok(())
+    }
+
+    /// Returns a receiver for the TLS-level events of this stream, replacing any
+    /// previous subscription.
+    #[cfg(feature = "events")]
+    pub fn subscribe(&mut self) -> tokio::sync::mpsc::UnboundedReceiver<StreamEvent> {
+        self.events.subscribe()
+    }
+
+    /// Read up to `size` bytes of ciphertext from the IO at a time.
+    ///
+    /// rustls consumes ciphertext a few kilobytes at a time. With read-ahead enabled,
+    /// each read from the IO fills a buffer of `size` bytes instead, and several TLS
+    /// records can be decrypted into one `poll_read` call without further syscalls.
+    /// A `size` of zero, the default, reads from the IO on demand.
+    ///
+    /// Ciphertext that was read ahead but not yet processed is discarded by
+    /// [`TlsStream::into_inner`].
+    pub fn set_read_ahead(&mut self, size: usize) {
+        self.read_ahead.set_size(size);
+    }
+
+    /// Bound the ciphertext buffered for writing: once more than `high` bytes are
+    /// waiting to be written to the IO, `poll_write` returns `Poll::Pending` until
+    /// writing out has brought the backlog down to `low` bytes.
+    ///
+    /// This gives writers backpressure well before rustls' buffer limit is reached.
+    /// A `high` of zero, the default, disables the watermarks.
+    ///
+    /// # Panics
+    ///
+    /// If `low` is greater than `high`.
+    pub fn set_write_watermarks(&mut self, high: usize, low: usize) {
+        self.write_watermarks = WriteWatermarks::new(high, low);
+    }
+
+    /// Bound the data rustls buffers for writing to `limit` bytes, or lift the bound
+    /// with `None`.
+    ///
+    /// This covers both plaintext written before the handshake completes and
+    /// ciphertext waiting to be written to the IO. Once it is reached, `poll_write`
+    /// returns `Poll::Pending` until the IO takes some of it, so writers to slow peers
+    /// are held back instead of buffering without end. The default is 64 KiB; see
+    /// [`set_write_watermarks`](TlsStream::set_write_watermarks) for backpressure
+    /// below the limit.
+    pub fn set_buffer_limit(&mut self, limit: Option<usize>) {
+        self.session.set_buffer_limit(limit);
+    }
+
+    /// Report the IO ending without `close_notify` as the end of the stream, instead
+    /// of an `UnexpectedEof` error.
+    ///
+    /// Reads fail by default, because whoever can close the transport could otherwise
+    /// truncate the data unnoticed. Enable this for peers that never send
+    /// `close_notify`, like HTTP clients that delimit their messages themselves;
+    /// [`TlsStream::read_eof_reason`] still tells the two cases apart.
+    pub fn set_lenient_eof(&mut self, lenient: bool) {
+        self.lenient_eof = lenient;
+    }
+
+    fn unclean_eof(&self, err: io::Error) -> io::Result<()> {
+        match self.lenient_eof && err.kind() == io::ErrorKind::UnexpectedEof {
+            true => Ok(()),
+            false => Err(err),
+        }
+    }
+
+    /// Returns whether early (0-RTT) data was accepted on this connection, and how much.
+    ///
+    /// Early data is read from the stream like any other data, ahead of the data sent
+    /// after the handshake. Servers only accept early data if `max_early_data_size` is
+    /// set in the server config.
+    #[inline]
+    pub fn early_data_status(&self) -> EarlyDataStatus {
+        self.early_data_status
+    }
+
+    pub(crate) fn handshake_completed(&mut self) {
+        // rustls keeps early data apart from the data read through `reader()`;
+        // move it to the front of the stream.
+        self.early_data_status = match self.session.early_data() {
+            Some(mut early_data) => {
+                let _ = early_data.read_to_end(&mut self.early_data);
+                EarlyDataStatus::Accepted {
+                    len: self.early_data.len(),
+                }
+            }
+            None => EarlyDataStatus::NotAccepted,
+        };
+
+        if let Some(tracker) = &mut self.tracker {
+            tracker.completed(self.session.server_name());
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.completed();
+        }
+    }
+}
+
+/// Early data of a handshake in progress, returned by
+/// [`TlsAcceptor::accept_early`](crate::TlsAcceptor::accept_early).
+///
+/// Reads return early data as it arrives, while the [`Accept`](crate::Accept) future
+/// waits for the client to finish the handshake, and end once it has. They fail
+/// with an error of kind `UnexpectedEof` if the handshake fails first, and data
+/// read until then must be discarded, as when it was replayed.
+#[cfg(feature = "early-data")]
+#[derive(Debug)]
+pub struct EarlyDataReader {
+    queue: Arc<Mutex<EarlyDataQueue>>,
+}
+
+#[cfg(feature = "early-data")]
+#[derive(Debug, Default)]
+struct EarlyDataQueue {
+    data: Vec<u8>,
+    /// How much early data was received in all.
+    len: usize,
+    finished: bool,
+    aborted: bool,
+    waker: Option<Waker>,
+}
+
+/// The handshake side of an [`EarlyDataReader`]; dropping it before
+/// [`finish`](Self::finish) fails the reader.
+#[cfg(feature = "early-data")]
+#[derive(Debug)]
+pub(crate) struct EarlyDataSender {
+    queue: Arc<Mutex<EarlyDataQueue>>,
+}
+
+#[cfg(feature = "early-data")]
+impl EarlyDataReader {
+    pub(crate) fn new() -> (Self, EarlyDataSender) {
+        let queue = Arc::new(Mutex::new(EarlyDataQueue::default()));
+        (
+            Self {
+                queue: queue.clone(),
+            },
+            EarlyDataSender { queue },
+        )
+    }
+}
+
+#[cfg(feature = "early-data")]
+impl AsyncRead for EarlyDataReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut queue = lock(&self.queue);
+        if !queue.data.is_empty() {
+            let len = queue.data.len().min(buf.remaining());
+            buf.put_slice(&queue.data[..len]);
+            queue.data.drain(..len);
+            return Poll::Ready(Ok(()));
+        }
+        match (queue.finished, queue.aborted) {
+            (true, _) => Poll::Ready(Ok(())),
+            (_, true) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "handshake failed before the early data ended",
+            ))),
+            _ => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(feature = "early-data")]
+impl EarlyDataSender {
+    /// Hands the early data rustls has received so far to the reader.
+    pub(crate) fn send(&self, session: &mut ServerConnection) {
+        let mut queue = lock(&self.queue);
+        if let Some(mut early_data) = session.early_data() {
+            let len = queue.data.len();
+            let _ = early_data.read_to_end(&mut queue.data);
+            queue.len += queue.data.len() - len;
+            if queue.data.len() > len {
+                wake(&mut queue);
+            }
+        }
+    }
+
+    /// Hands the rest of the early data to the reader, and ends it.
+    pub(crate) fn finish<IO>(self, stream: &mut TlsStream<IO>) {
+        let mut queue = lock(&self.queue);
+        queue.len += stream.early_data.len();
+        queue.data.append(&mut stream.early_data);
+        if let EarlyDataStatus::Accepted { len } = &mut stream.early_data_status {
+            *len = queue.len;
+        }
+        queue.finished = true;
+        wake(&mut queue);
+    }
+}
+
+#[cfg(feature = "early-data")]
+impl Drop for EarlyDataSender {
+    fn drop(&mut self) {
+        let mut queue = lock(&self.queue);
+        queue.aborted = true;
+        wake(&mut queue);
+    }
+}
+
+#[cfg(feature = "early-data")]
+fn lock(queue: &Mutex<EarlyDataQueue>) -> std::sync::MutexGuard<'_, EarlyDataQueue> {
+    queue.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(feature = "early-data")]
+fn wake(queue: &mut EarlyDataQueue) {
+    if let Some(waker) = queue.waker.take() {
+        waker.wake();
+    }
 }
 
 impl<IO> IoSession for TlsStream<IO> {
@@ -47,14 +464,150 @@ impl<IO> IoSession for TlsStream<IO> {
     }
 
     #[inline]
-    fn get_mut(&mut self) -> (&mut TlsState, &mut Self::Io, &mut Self::Session) {
-        (&mut self.state, &mut self.io, &mut self.session)
+    fn get_mut(
+        &mut self,
+    ) -> (
+        &mut TlsState,
+        &mut Self::Io,
+        &mut Self::Session,
+        &mut ReadAhead,
+    ) {
+        (
+            &mut self.state,
+            &mut self.io,
+            &mut self.session,
+            &mut self.read_ahead,
+        )
     }
 
     #[inline]
     fn into_io(self) -> Self::Io {
         self.io
     }
+
+    fn handshake_failed(&mut self) {
+        if let Some(tracker) = &self.tracker {
+            tracker.failed(self.session.server_name());
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.failed();
+        }
+    }
+}
+
+impl<IO> TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Write the contents of `buf`, advancing it past the bytes written.
+    ///
+    /// Unlike `poll_write`, this hands rustls all chunks of a non-contiguous buffer
+    /// (such as a chain of [`Bytes`](bytes::Bytes)) at once, so they can share TLS
+    /// records without being copied into one slice first.
+    ///
+    /// Like `poll_write`, this does not guarantee the data has been sent; call `flush`.
+    #[cfg(feature = "bytes")]
+    pub fn poll_write_buf<B: Buf>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.state.writeable() {
+            return Poll::Ready(Err(common::write_after_shutdown()));
+        }
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_eof(!this.state.readable())
+            .set_read_ahead(&mut this.read_ahead)
+            .set_write_watermarks(&mut this.write_watermarks);
+        stream.poll_write_buf(cx, buf)
+    }
+
+    /// Write all of `buf`, see [`TlsStream::poll_write_buf`].
+    #[cfg(feature = "bytes")]
+    pub async fn write_all_buf<B: Buf>(&mut self, buf: &mut B) -> io::Result<()> {
+        while buf.has_remaining() {
+            let n = poll_fn(|cx| Pin::new(&mut *self).poll_write_buf(cx, buf)).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Read plaintext into the spare capacity of `buf`, advancing it past the bytes read.
+    ///
+    /// Plaintext is copied straight from rustls into `buf`, such as a
+    /// [`BytesMut`](bytes::BytesMut), without an intermediate buffer. Returns `Ok(0)` at
+    /// the end of the stream, or if `buf` has no room left.
+    #[cfg(feature = "bytes")]
+    pub fn poll_read_buf<B: BufMut>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<io::Result<usize>> {
+        common::poll_read_buf(self, cx, buf)
+    }
+
+    /// Read plaintext into `buf`, see [`TlsStream::poll_read_buf`].
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> io::Result<usize> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_read_buf(cx, buf)).await
+    }
+
+    /// Copy plaintext into `buf` without consuming it, so that the next read returns
+    /// it again, like `TcpStream::poll_peek`.
+    ///
+    /// Only the plaintext of the first TLS record rustls has buffered is copied, so
+    /// this may return fewer bytes than have arrived; reading makes way for the rest.
+    /// Returns `Ok(0)` at the end of the stream, or if `buf` has no room left.
+    pub fn poll_peek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<usize>> {
+        common::poll_peek(self, cx, buf)
+    }
+
+    /// Copy plaintext into `buf` without consuming it, see [`TlsStream::poll_peek`].
+    pub async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buf = ReadBuf::new(buf);
+        poll_fn(|cx| Pin::new(&mut *self).poll_peek(cx, &mut buf)).await
+    }
+
+    /// Ends the write side of the stream, and keeps the read side open: a half-close.
+    ///
+    /// Sends `close_notify` after the data written so far, and shuts the IO down for
+    /// writing, as `AsyncWriteExt::shutdown` does. Data from the peer can still be
+    /// read until it closes its side; [`read_eof_reason`](Self::read_eof_reason) then
+    /// tells whether it did so cleanly. Writes fail with an error of kind
+    /// `BrokenPipe` from then on, and calling this again only retries shutting the IO
+    /// down.
+    ///
+    /// TLS 1.3 peers may keep sending after `close_notify`, but TLS 1.2 peers are
+    /// expected to close their side right away, so proxies can only rely on
+    /// half-closes over TLS 1.3.
+    pub async fn shutdown_write(&mut self) -> io::Result<()> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_shutdown(cx)).await
+    }
+
+    /// Closes the connection in both directions, the way TLS intends.
+    ///
+    /// Flushes pending plaintext and sends `close_notify`, then reads until the peer's
+    /// `close_notify` or the end of the IO, discarding any data the peer still sends.
+    /// Returns how the peer closed its side: [`EofReason::Transport`] means the data
+    /// it sent may have been truncated. Fails with an error of kind `TimedOut` if this
+    /// takes longer than `timeout`.
+    #[cfg(feature = "timeout")]
+    pub async fn graceful_shutdown(&mut self, timeout: Duration) -> io::Result<EofReason> {
+        match timeout::graceful_shutdown(self, timeout).await {
+            Ok(()) => {}
+            // The transport closed without `close_notify`; `read_eof` says so.
+            Err(_) if self.read_eof.is_some() => {}
+            Err(err) => return Err(err),
+        }
+        Ok(self.read_eof.unwrap_or(EofReason::Transport))
+    }
 }
 
 impl<IO> AsyncRead for TlsStream<IO>
@@ -67,27 +620,43 @@ where
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
         let this = self.get_mut();
-        let mut stream =
-            Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
+        if !this.early_data.is_empty() {
+            let len = this.early_data.len().min(buf.remaining());
+            buf.put_slice(&this.early_data[..len]);
+            this.early_data.drain(..len);
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_eof(!this.state.readable())
+            .set_read_ahead(&mut this.read_ahead);
 
         match &this.state {
             TlsState::Stream | TlsState::WriteShutdown => {
                 let prev = buf.remaining();
+                #[cfg(feature = "events")]
+                let eof_before = this.read_eof;
 
-                match stream.as_mut_pin().poll_read(cx, buf) {
+                let output = match stream.as_mut_pin().poll_read(cx, buf) {
                     Poll::Ready(Ok(())) => {
                         if prev == buf.remaining() || stream.eof {
+                            this.read_eof = Some(stream.eof_reason());
                             this.state.shutdown_read();
                         }
 
                         Poll::Ready(Ok(()))
                     }
                     Poll::Ready(Err(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        this.read_eof = Some(EofReason::Transport);
                         this.state.shutdown_read();
-                        Poll::Ready(Err(err))
+                        Poll::Ready(this.unclean_eof(err))
                     }
                     output => output,
-                }
+                };
+
+                #[cfg(feature = "events")]
+                this.events.after_read(&output, eof_before, this.read_eof);
+                output
             }
             TlsState::ReadShutdown | TlsState::FullyShutdown => Poll::Ready(Ok(())),
             #[cfg(feature = "early-data")]
@@ -96,6 +665,64 @@ where
     }
 }
 
+/// Hands out the plaintext rustls has already decrypted, without copying it into a
+/// buffer of its own.
+impl<IO> AsyncBufRead for TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if !this.early_data.is_empty() {
+            return Poll::Ready(Ok(&this.early_data));
+        }
+
+        match &this.state {
+            TlsState::Stream | TlsState::WriteShutdown => {
+                let mut stream = Stream::new(&mut this.io, &mut this.session)
+                    .set_eof(!this.state.readable())
+                    .set_read_ahead(&mut this.read_ahead);
+                #[cfg(feature = "events")]
+                let eof_before = this.read_eof;
+
+                let output = match stream.poll_fill_plaintext(cx) {
+                    Poll::Ready(Ok(0)) => {
+                        this.read_eof = Some(EofReason::CloseNotify);
+                        this.state.shutdown_read();
+                        Poll::Ready(Ok(0))
+                    }
+                    Poll::Ready(Err(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        this.read_eof = Some(EofReason::Transport);
+                        this.state.shutdown_read();
+                        Poll::Ready(this.unclean_eof(err).map(|()| 0))
+                    }
+                    output => output,
+                };
+
+                #[cfg(feature = "events")]
+                this.events.after_read(&output, eof_before, this.read_eof);
+                ready!(output)?;
+                Poll::Ready(Ok(this
+                    .session
+                    .reader()
+                    .into_first_chunk()
+                    .unwrap_or_default()))
+            }
+            TlsState::ReadShutdown | TlsState::FullyShutdown => Poll::Ready(Ok(&[])),
+            #[cfg(feature = "early-data")]
+            s => unreachable!("server TLS can not hit this state: {:?}", s),
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        match this.early_data.is_empty() {
+            true => this.session.reader().consume(amt),
+            false => drop(this.early_data.drain(..amt)),
+        }
+    }
+}
+
 impl<IO> AsyncWrite for TlsStream<IO>
 where
     IO: AsyncRead + AsyncWrite + Unpin,
@@ -108,15 +735,42 @@ where
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
-        let mut stream =
-            Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
+        if !this.state.writeable() {
+            return Poll::Ready(Err(common::write_after_shutdown()));
+        }
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_eof(!this.state.readable())
+            .set_read_ahead(&mut this.read_ahead)
+            .set_write_watermarks(&mut this.write_watermarks);
         stream.as_mut_pin().poll_write(cx, buf)
     }
 
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.state.writeable() {
+            return Poll::Ready(Err(common::write_after_shutdown()));
+        }
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_eof(!this.state.readable())
+            .set_read_ahead(&mut this.read_ahead)
+            .set_write_watermarks(&mut this.write_watermarks);
+        stream.as_mut_pin().poll_write_vectored(cx, bufs)
+    }
+
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = self.get_mut();
-        let mut stream =
-            Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_eof(!this.state.readable())
+            .set_read_ahead(&mut this.read_ahead);
         stream.as_mut_pin().poll_flush(cx)
     }
 
@@ -127,13 +781,14 @@ where
         }
 
         let this = self.get_mut();
-        let mut stream =
-            Stream::new(&mut this.io, &mut this.session).set_eof(!this.state.readable());
+        let mut stream = Stream::new(&mut this.io, &mut this.session)
+            .set_eof(!this.state.readable())
+            .set_read_ahead(&mut this.read_ahead);
         stream.as_mut_pin().poll_shutdown(cx)
     }
 }
 
-#[cfg(unix)]
+#[cfg(any(unix, target_os = "wasi"))]
 impl<IO> AsRawFd for TlsStream<IO>
 where
     IO: AsRawFd,
@@ -143,6 +798,16 @@ where
     }
 }
 
+#[cfg(any(unix, target_os = "wasi"))]
+impl<IO> AsFd for TlsStream<IO>
+where
+    IO: AsFd,
+{
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.get_ref().0.as_fd()
+    }
+}
+
 #[cfg(windows)]
 impl<IO> AsRawSocket for TlsStream<IO>
 where
@@ -152,3 +817,13 @@ where
         self.get_ref().0.as_raw_socket()
     }
 }
+
+#[cfg(windows)]
+impl<IO> AsSocket for TlsStream<IO>
+where
+    IO: AsSocket,
+{
+    fn as_socket(&self) -> BorrowedSocket<'_> {
+        self.get_ref().0.as_socket()
+    }
+}
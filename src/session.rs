@@ -0,0 +1,255 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+
+use pki_types::ServerName;
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue};
+use rustls::client::{Resumption, Tls13ClientSessionValue};
+use rustls::{ClientConfig, NamedGroup};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::common::MidHandshake;
+use crate::{client, TlsConnector};
+
+/// The future returned by the methods of [`SessionStore`].
+pub type SessionFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Where a [`TlsConnector`] keeps TLS 1.3 session tickets, see
+/// [`TlsConnector::session_store`].
+///
+/// rustls keeps tickets in memory per client config. A store outlives configs and
+/// can be shared between connectors, or kept out of process.
+pub trait SessionStore: Send + Sync {
+    /// Takes a ticket for `server_name` out of the store.
+    ///
+    /// Tickets are meant to be used once, so a ticket that was returned should not be
+    /// returned again.
+    fn get<'a>(
+        &'a self,
+        server_name: &'a ServerName<'static>,
+    ) -> SessionFuture<'a, Option<Tls13ClientSessionValue>>;
+
+    /// Keeps `ticket` for later connections to `server_name`.
+    fn put(
+        &self,
+        server_name: ServerName<'static>,
+        ticket: Tls13ClientSessionValue,
+    ) -> SessionFuture<'_, ()>;
+}
+
+/// A [`SessionStore`] in memory, that keeps the tickets of the servers connected to
+/// most recently.
+pub struct LruSessionStore {
+    capacity: usize,
+    servers: Mutex<VecDeque<(ServerName<'static>, VecDeque<Tls13ClientSessionValue>)>>,
+}
+
+impl LruSessionStore {
+    /// The number of tickets kept per server, as in rustls.
+    const TICKETS_PER_SERVER: usize = 8;
+
+    /// Keeps tickets for up to `capacity` servers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            servers: Mutex::default(),
+        }
+    }
+
+    fn servers(
+        &self,
+    ) -> MutexGuard<'_, VecDeque<(ServerName<'static>, VecDeque<Tls13ClientSessionValue>)>> {
+        self.servers.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl SessionStore for LruSessionStore {
+    fn get<'a>(
+        &'a self,
+        server_name: &'a ServerName<'static>,
+    ) -> SessionFuture<'a, Option<Tls13ClientSessionValue>> {
+        let mut servers = self.servers();
+        let ticket = servers
+            .iter()
+            .position(|(name, _)| name == server_name)
+            .and_then(|i| {
+                let entry = servers.remove(i)?;
+                servers.push_back(entry);
+                servers.back_mut()?.1.pop_back()
+            });
+        Box::pin(async move { ticket })
+    }
+
+    fn put(
+        &self,
+        server_name: ServerName<'static>,
+        ticket: Tls13ClientSessionValue,
+    ) -> SessionFuture<'_, ()> {
+        let mut servers = self.servers();
+        let mut tickets = match servers.iter().position(|(name, _)| *name == server_name) {
+            Some(i) => servers
+                .remove(i)
+                .map(|(_, tickets)| tickets)
+                .unwrap_or_default(),
+            None => VecDeque::new(),
+        };
+        if tickets.len() == Self::TICKETS_PER_SERVER {
+            tickets.pop_front();
+        }
+        tickets.push_back(ticket);
+        servers.push_back((server_name, tickets));
+        while servers.len() > self.capacity {
+            servers.pop_front();
+        }
+        Box::pin(async {})
+    }
+}
+
+impl fmt::Debug for LruSessionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LruSessionStore")
+            .field("capacity", &self.capacity)
+            .field("servers", &self.servers().len())
+            .finish()
+    }
+}
+
+thread_local! {
+    static FETCHED: RefCell<Option<(ServerName<'static>, Tls13ClientSessionValue)>> = const { RefCell::new(None) };
+}
+
+/// The session store of a client config, in front of a [`SessionStore`].
+///
+/// rustls looks tickets up synchronously while it writes the ClientHello, so
+/// [`Fetch`] gets a ticket from the store first, and hands it over to the connection
+/// it creates on the same thread. Tickets rustls receives are put into the store on
+/// a task of their own. Key exchange hints and TLS 1.2 sessions stay in memory.
+pub(crate) struct StoreBridge {
+    store: Arc<dyn SessionStore>,
+    memory: ClientSessionMemoryCache,
+}
+
+impl StoreBridge {
+    pub(crate) fn install(config: &mut ClientConfig, store: Arc<dyn SessionStore>) -> Arc<Self> {
+        let bridge = Arc::new(Self {
+            store,
+            memory: ClientSessionMemoryCache::new(256),
+        });
+        config.resumption = Resumption::store(bridge.clone());
+        bridge
+    }
+}
+
+impl ClientSessionStore for StoreBridge {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.memory.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.memory.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.memory.set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.memory.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.memory.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        // Outside a runtime there is nothing to run the store on; the ticket is lost.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let store = self.store.clone();
+            runtime.spawn(async move { store.put(server_name, value).await });
+        }
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        FETCHED.with(|fetched| {
+            let mut fetched = fetched.borrow_mut();
+            match &*fetched {
+                Some((name, _)) if name == server_name => fetched.take().map(|(_, ticket)| ticket),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl fmt::Debug for StoreBridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreBridge").finish_non_exhaustive()
+    }
+}
+
+/// A connection waiting for a ticket from the session store before it starts.
+pub(crate) struct Fetch<IO> {
+    ticket: SessionFuture<'static, Option<Tls13ClientSessionValue>>,
+    connector: TlsConnector,
+    config: Arc<ClientConfig>,
+    domain: ServerName<'static>,
+    pub(crate) io: IO,
+}
+
+impl<IO> Fetch<IO> {
+    pub(crate) fn new(
+        bridge: &Arc<StoreBridge>,
+        connector: TlsConnector,
+        config: Arc<ClientConfig>,
+        domain: ServerName<'static>,
+        io: IO,
+    ) -> Self {
+        let store = bridge.store.clone();
+        let server_name = domain.clone();
+        Self {
+            ticket: Box::pin(async move { store.get(&server_name).await }),
+            connector,
+            config,
+            domain,
+            io,
+        }
+    }
+}
+
+/// Polls the pending fetch in `fetch`, if any, and starts the handshake in `inner`
+/// once it completes.
+pub(crate) fn poll_fetch<IO>(
+    fetch: &mut Option<Fetch<IO>>,
+    inner: &mut MidHandshake<client::TlsStream<IO>>,
+    cx: &mut Context<'_>,
+) -> Poll<()>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let ticket = match fetch {
+        Some(pending) => ready!(pending.ticket.as_mut().poll(cx)),
+        None => return Poll::Ready(()),
+    };
+
+    let Fetch {
+        connector,
+        config,
+        domain,
+        io,
+        ..
+    } = fetch.take().expect("fetch is pending");
+    FETCHED.with(|fetched| *fetched.borrow_mut() = ticket.map(|ticket| (domain.clone(), ticket)));
+    *inner = connector.connect_inner(config, domain, io, |_| ()).inner;
+    FETCHED.with(|fetched| fetched.borrow_mut().take());
+    Poll::Ready(())
+}
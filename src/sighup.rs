@@ -0,0 +1,146 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rustls::crypto::CryptoProvider;
+use rustls::ServerConfig;
+use tokio::signal::unix::{signal, SignalKind};
+
+#[cfg(feature = "crl")]
+use crate::CrlVerifier;
+use crate::{ReloadingClientCert, ReloadingRoots, RotatingTicketer, TlsAcceptor};
+
+type LoadConfig = Box<dyn FnMut() -> io::Result<Arc<ServerConfig>> + Send>;
+
+/// Reloads certificates, ticket keys and revocation lists when the process receives
+/// `SIGHUP`, the way operators expect daemons to pick up new files.
+///
+/// Register what to reload with the builder methods, then [`spawn`](Self::spawn) a
+/// task that reloads all of it on every `SIGHUP`. Each target keeps its current
+/// state if reloading it fails, and the others are reloaded regardless.
+#[derive(Default)]
+pub struct ReloadOnSighup {
+    targets: Vec<Target>,
+}
+
+enum Target {
+    Config(TlsAcceptor, LoadConfig),
+    Ticketer(Arc<RotatingTicketer>),
+    Roots(Arc<ReloadingRoots>, PathBuf),
+    ClientCert(
+        Arc<ReloadingClientCert>,
+        PathBuf,
+        PathBuf,
+        Arc<CryptoProvider>,
+    ),
+    #[cfg(feature = "crl")]
+    Crls(Arc<CrlVerifier>),
+}
+
+impl ReloadOnSighup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Swap the config `load` returns into `acceptor`, see [`TlsAcceptor::swap_config`].
+    pub fn server_config(
+        mut self,
+        acceptor: TlsAcceptor,
+        load: impl FnMut() -> io::Result<Arc<ServerConfig>> + Send + 'static,
+    ) -> Self {
+        self.targets.push(Target::Config(acceptor, Box::new(load)));
+        self
+    }
+
+    /// Switch `ticketer` to a new key, see [`RotatingTicketer::rotate`].
+    pub fn ticketer(mut self, ticketer: Arc<RotatingTicketer>) -> Self {
+        self.targets.push(Target::Ticketer(ticketer));
+        self
+    }
+
+    /// Reload `roots` from the PEM file at `path`, see [`ReloadingRoots::load_pem_file`].
+    pub fn roots(mut self, roots: Arc<ReloadingRoots>, path: PathBuf) -> Self {
+        self.targets.push(Target::Roots(roots, path));
+        self
+    }
+
+    /// Reload `identity` from the PEM files `cert` and `key`, see
+    /// [`ReloadingClientCert::load_pem_files`].
+    pub fn client_cert(
+        mut self,
+        identity: Arc<ReloadingClientCert>,
+        cert: PathBuf,
+        key: PathBuf,
+        provider: Arc<CryptoProvider>,
+    ) -> Self {
+        self.targets
+            .push(Target::ClientCert(identity, cert, key, provider));
+        self
+    }
+
+    /// Download the CRLs of `verifier` again, see [`CrlVerifier::refresh`].
+    #[cfg(feature = "crl")]
+    pub fn crls(mut self, verifier: Arc<CrlVerifier>) -> Self {
+        self.targets.push(Target::Crls(verifier));
+        self
+    }
+
+    /// Reload all targets now, as on `SIGHUP`.
+    ///
+    /// Returns the first error, once all targets have been tried.
+    pub async fn reload(&mut self) -> io::Result<()> {
+        let mut first_err = None;
+        for target in &mut self.targets {
+            if let Err(err) = target.reload().await {
+                event!(warn, error = %err, "reload failed");
+                first_err.get_or_insert(err);
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Reload all targets on every `SIGHUP`, in a task on the current runtime.
+    ///
+    /// Fails if the signal handler cannot be installed. Abort the returned handle to
+    /// stop reloading.
+    pub fn spawn(mut self) -> io::Result<tokio::task::JoinHandle<()>> {
+        let mut hangups = signal(SignalKind::hangup())?;
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                let _ = self.reload().await;
+            }
+        }))
+    }
+}
+
+impl Target {
+    async fn reload(&mut self) -> io::Result<()> {
+        match self {
+            Target::Config(acceptor, load) => {
+                acceptor.swap_config(load()?);
+                Ok(())
+            }
+            Target::Ticketer(ticketer) => ticketer
+                .rotate()
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err)),
+            Target::Roots(roots, path) => roots.load_pem_file(path),
+            Target::ClientCert(identity, cert, key, provider) => {
+                identity.load_pem_files(cert, key, provider)
+            }
+            #[cfg(feature = "crl")]
+            Target::Crls(verifier) => verifier.refresh().await,
+        }
+    }
+}
+
+impl fmt::Debug for ReloadOnSighup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadOnSighup")
+            .field("targets", &self.targets.len())
+            .finish()
+    }
+}
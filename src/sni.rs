@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use rustls::server::ClientHello;
+use rustls::ServerConfig;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::select::{SelectAccept, SelectConfig};
+use crate::server;
+
+/// An acceptor that picks its `ServerConfig` by the server name a client sends with
+/// SNI, for serving several virtual hosts on one listener.
+///
+/// Like [`AlpnAcceptor`](crate::AlpnAcceptor), this reads the ClientHello with a
+/// [`LazyConfigAcceptor`](crate::LazyConfigAcceptor) before starting the handshake.
+/// Names are matched without regard to case; a name of exactly matching host takes
+/// precedence over a wildcard. Clients whose name matches no host get the fallback
+/// config, or fail with an [`SniError`] if there is none.
+#[derive(Clone, Default)]
+pub struct SniAcceptor {
+    hosts: Arc<HashMap<String, Arc<ServerConfig>>>,
+    wildcards: Arc<HashMap<String, Arc<ServerConfig>>>,
+    fallback: Option<Arc<ServerConfig>>,
+}
+
+impl SniAcceptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `config` for clients that send `name`.
+    ///
+    /// A name of the form `*.example.com` matches any name one label below
+    /// `example.com`, like `www.example.com`, but neither `example.com` itself nor
+    /// `a.www.example.com`.
+    pub fn host(mut self, name: &str, config: Arc<ServerConfig>) -> Self {
+        let name = normalize(name);
+        match name.strip_prefix("*.") {
+            Some(parent) => Arc::make_mut(&mut self.wildcards).insert(parent.to_owned(), config),
+            None => Arc::make_mut(&mut self.hosts).insert(name, config),
+        };
+        self
+    }
+
+    /// Use `config` for clients that send no server name, or one that matches no host.
+    pub fn fallback(mut self, config: Arc<ServerConfig>) -> Self {
+        self.fallback = Some(config);
+        self
+    }
+
+    /// Returns the config used for a client that sent `server_name`, as given by
+    /// [`ClientHello::server_name`](rustls::server::ClientHello::server_name).
+    pub fn select(&self, server_name: Option<&str>) -> Result<Arc<ServerConfig>, SniError> {
+        let name = match server_name {
+            Some(name) => normalize(name),
+            None => return self.fallback.clone().ok_or(SniError::NoServerName),
+        };
+
+        let wildcard = || {
+            let (_, parent) = name.split_once('.')?;
+            self.wildcards.get(parent)
+        };
+        match self.hosts.get(&name).or_else(wildcard) {
+            Some(config) => Ok(config.clone()),
+            None => self
+                .fallback
+                .clone()
+                .ok_or(SniError::UnknownServerName(name)),
+        }
+    }
+
+    pub fn accept<IO>(&self, stream: IO) -> SniAccept<IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        SniAccept {
+            inner: SelectAccept::new(stream, self.clone()),
+        }
+    }
+}
+
+impl SelectConfig for SniAcceptor {
+    type Choice = ();
+
+    fn select_config(&self, client_hello: &ClientHello<'_>) -> io::Result<(Arc<ServerConfig>, ())> {
+        let config = self
+            .select(client_hello.server_name())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        Ok((config, ()))
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// The error an [`SniAccept`] fails with when no config matches the client, as the
+/// inner error of an `io::Error`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SniError {
+    /// The client sent no server name.
+    NoServerName,
+    /// The client sent a server name that matches no host.
+    UnknownServerName(String),
+}
+
+impl fmt::Display for SniError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SniError::NoServerName => f.write_str("client sent no server name"),
+            SniError::UnknownServerName(name) => write!(f, "no config for server name {}", name),
+        }
+    }
+}
+
+impl Error for SniError {}
+
+/// Future returned from `SniAcceptor::accept` which will resolve
+/// once the accept handshake has finished.
+pub struct SniAccept<IO> {
+    inner: SelectAccept<IO, SniAcceptor>,
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Future for SniAccept<IO> {
+    type Output = io::Result<server::TlsStream<IO>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.inner)
+            .poll(cx)
+            .map_ok(|(stream, ())| stream)
+    }
+}
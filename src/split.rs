@@ -0,0 +1,204 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Wake, Waker};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The read half of a TLS stream, created by
+/// [`client::TlsStream::into_split`](crate::client::TlsStream::into_split),
+/// [`server::TlsStream::into_split`](crate::server::TlsStream::into_split) or
+/// [`TlsStream::into_split`](crate::TlsStream::into_split).
+///
+/// Reading may write to the IO as well, to answer key updates or send alerts, so both
+/// halves share the stream. Each poll holds the stream for its duration only.
+///
+/// As the halves share the connection state too, shutting down the [`WriteHalf`]
+/// is a half-close: `close_notify` is sent once, however often `poll_shutdown` is
+/// called, and reads go on until the peer closes its side.
+#[derive(Debug)]
+pub struct ReadHalf<S> {
+    inner: Arc<Shared<S>>,
+}
+
+/// The write half of a TLS stream, see [`ReadHalf`].
+#[derive(Debug)]
+pub struct WriteHalf<S> {
+    inner: Arc<Shared<S>>,
+}
+
+#[derive(Debug)]
+struct Shared<S> {
+    stream: Mutex<S>,
+    wakers: Arc<Wakers>,
+    waker: Waker,
+}
+
+/// The wakers of the tasks polling either half.
+///
+/// Both halves poll the same IO, which may only remember the waker of whichever half
+/// polled it last. The IO is always polled with a waker that wakes both tasks, so
+/// neither misses its wakeup.
+#[derive(Debug, Default)]
+struct Wakers {
+    read: Mutex<Option<Waker>>,
+    write: Mutex<Option<Waker>>,
+}
+
+impl Wake for Wakers {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        for waker in [&self.read, &self.write] {
+            if let Some(waker) = lock(waker).take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+pub(crate) fn split<S>(stream: S) -> (ReadHalf<S>, WriteHalf<S>) {
+    let wakers = Arc::new(Wakers::default());
+    let inner = Arc::new(Shared {
+        stream: Mutex::new(stream),
+        waker: Waker::from(wakers.clone()),
+        wakers,
+    });
+    let read = ReadHalf {
+        inner: inner.clone(),
+    };
+    (read, WriteHalf { inner })
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panic mid-poll leaves the stream no worse off than it does without splitting.
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl<S> Shared<S> {
+    fn poll<T>(
+        &self,
+        slot: &Mutex<Option<Waker>>,
+        cx: &mut Context<'_>,
+        f: impl FnOnce(Pin<&mut S>, &mut Context<'_>) -> Poll<T>,
+    ) -> Poll<T>
+    where
+        S: Unpin,
+    {
+        *lock(slot) = Some(cx.waker().clone());
+        let mut cx = Context::from_waker(&self.waker);
+        f(Pin::new(&mut *lock(&self.stream)), &mut cx)
+    }
+}
+
+impl<S> ReadHalf<S> {
+    /// Locks the stream, to reach the TLS session and the other accessors of the stream.
+    ///
+    /// The write half blocks while the guard is held, so don't hold it across an
+    /// `.await`.
+    pub fn lock(&self) -> MutexGuard<'_, S> {
+        lock(&self.inner.stream)
+    }
+
+    /// Puts the stream back together, if `other` is the write half split from it.
+    pub fn reunite(self, other: WriteHalf<S>) -> Result<S, ReuniteError<S>> {
+        if !Arc::ptr_eq(&self.inner, &other.inner) {
+            return Err(ReuniteError(self, other));
+        }
+
+        drop(other);
+        let inner = Arc::try_unwrap(self.inner)
+            .ok()
+            .expect("TLS stream: try_unwrap failed in reunite");
+        Ok(inner
+            .stream
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner()))
+    }
+}
+
+impl<S> WriteHalf<S> {
+    /// Locks the stream, see [`ReadHalf::lock`].
+    pub fn lock(&self) -> MutexGuard<'_, S> {
+        lock(&self.inner.stream)
+    }
+
+    /// Puts the stream back together, see [`ReadHalf::reunite`].
+    pub fn reunite(self, other: ReadHalf<S>) -> Result<S, ReuniteError<S>> {
+        other.reunite(self)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ReadHalf<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let inner = &self.inner;
+        inner.poll(&inner.wakers.read, cx, |stream, cx| {
+            stream.poll_read(cx, buf)
+        })
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for WriteHalf<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let inner = &self.inner;
+        inner.poll(&inner.wakers.write, cx, |stream, cx| {
+            stream.poll_write(cx, buf)
+        })
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let inner = &self.inner;
+        inner.poll(&inner.wakers.write, cx, |stream, cx| {
+            stream.poll_write_vectored(cx, bufs)
+        })
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.lock().is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let inner = &self.inner;
+        inner.poll(&inner.wakers.write, cx, |stream, cx| stream.poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let inner = &self.inner;
+        inner.poll(&inner.wakers.write, cx, |stream, cx| {
+            stream.poll_shutdown(cx)
+        })
+    }
+}
+
+/// Error returned by [`ReadHalf::reunite`] for halves of different streams.
+pub struct ReuniteError<S>(pub ReadHalf<S>, pub WriteHalf<S>);
+
+impl<S> fmt::Debug for ReuniteError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReuniteError").finish()
+    }
+}
+
+impl<S> fmt::Display for ReuniteError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("tried to reunite halves that are not from the same TLS stream")
+    }
+}
+
+impl<S> Error for ReuniteError<S> {}
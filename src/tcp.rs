@@ -0,0 +1,91 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use socket2::SockRef;
+use tokio::net::TcpStream;
+
+use crate::{client, server, TlsConnector, TlsStream};
+
+macro_rules! tcp_options {
+    ($stream:ty, $tcp:ident => $get:expr) => {
+        /// Socket options of the underlying [`TcpStream`].
+        impl $stream {
+            /// Returns the remote address of the underlying socket.
+            pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+                let $tcp = self;
+                $get.peer_addr()
+            }
+
+            /// Returns the local address of the underlying socket.
+            pub fn local_addr(&self) -> io::Result<SocketAddr> {
+                let $tcp = self;
+                $get.local_addr()
+            }
+
+            /// Returns whether `TCP_NODELAY` is set on the underlying socket.
+            pub fn nodelay(&self) -> io::Result<bool> {
+                let $tcp = self;
+                $get.nodelay()
+            }
+
+            /// Sets `TCP_NODELAY` on the underlying socket, see [`TcpStream::set_nodelay`].
+            pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+                let $tcp = self;
+                $get.set_nodelay(nodelay)
+            }
+
+            /// Returns the `SO_LINGER` timeout of the underlying socket.
+            pub fn linger(&self) -> io::Result<Option<Duration>> {
+                let $tcp = self;
+                $get.linger()
+            }
+
+            /// Returns whether `SO_KEEPALIVE` is set on the underlying socket.
+            pub fn keepalive(&self) -> io::Result<bool> {
+                let $tcp = self;
+                SockRef::from($get).keepalive()
+            }
+
+            /// Sets `SO_KEEPALIVE` on the underlying socket.
+            pub fn set_keepalive(&self, keepalive: bool) -> io::Result<()> {
+                let $tcp = self;
+                SockRef::from($get).set_keepalive(keepalive)
+            }
+        }
+    };
+}
+
+tcp_options!(client::TlsStream<TcpStream>, stream => &stream.io);
+tcp_options!(server::TlsStream<TcpStream>, stream => &stream.io);
+tcp_options!(TlsStream<TcpStream>, stream => stream.get_ref().0);
+
+impl TlsConnector {
+    /// Resolves `addr`, a `host:port` string, connects to it over TCP, and completes
+    /// the handshake with `host` as the server name.
+    ///
+    /// The resolved addresses are tried in turn, as [`TcpStream::connect`] does. IPv6
+    /// addresses are written in brackets, as in `[::1]:443`. An `addr` without a port,
+    /// or whose host is neither a DNS name nor an IP address, fails with an error of
+    /// kind `InvalidInput` before anything is resolved.
+    pub async fn connect_addr(&self, addr: &str) -> io::Result<client::TlsStream<TcpStream>> {
+        let host = match addr.rsplit_once(':') {
+            Some((host, _)) => host,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "address has no port",
+                ))
+            }
+        };
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        let domain = pki_types::ServerName::try_from(host.to_owned())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        let stream = TcpStream::connect(addr).await?;
+        self.connect(domain, stream).await
+    }
+}
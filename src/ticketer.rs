@@ -0,0 +1,158 @@
+use std::fmt;
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+use rustls::server::ProducesTickets;
+
+/// Makes the ticketer for a new key, like `rustls::crypto::ring::Ticketer::new`.
+pub type TicketerGenerator = fn() -> Result<Arc<dyn ProducesTickets>, rustls::Error>;
+
+/// A session ticket encrypter that switches to a new key every `lifetime`, and keeps
+/// decrypting tickets of replaced keys for an `overlap` window.
+///
+/// Install it with [`TlsAcceptor::ticketer`](crate::TlsAcceptor::ticketer). Keys
+/// are rotated when tickets are issued or redeemed; start
+/// [`spawn_rotation`](Self::spawn_rotation) to also rotate, and forget expired keys,
+/// while no clients connect. Each key is a ticketer made by the generator; the
+/// ticketers of the crypto providers change their own keys every 6 hours, so keep
+/// `lifetime` and `overlap` together below that.
+pub struct RotatingTicketer {
+    generator: TicketerGenerator,
+    lifetime: Duration,
+    overlap: Duration,
+    keys: RwLock<Keys>,
+}
+
+struct Keys {
+    current: Arc<dyn ProducesTickets>,
+    /// When `current` is due to be replaced.
+    rotate_at: Instant,
+    /// Replaced keys, with when they stop decrypting.
+    previous: Vec<(Arc<dyn ProducesTickets>, Instant)>,
+}
+
+impl RotatingTicketer {
+    /// Issue tickets with a key made by `generator`, replaced every `lifetime`, and
+    /// accept tickets of replaced keys for `overlap` after they are replaced.
+    ///
+    /// Clients are told that tickets are valid for `overlap`, as tickets issued just
+    /// before a rotation are not accepted for longer.
+    pub fn new(
+        generator: TicketerGenerator,
+        lifetime: Duration,
+        overlap: Duration,
+    ) -> Result<Self, rustls::Error> {
+        Ok(Self {
+            generator,
+            lifetime,
+            overlap,
+            keys: RwLock::new(Keys {
+                current: generator()?,
+                rotate_at: Instant::now() + lifetime,
+                previous: Vec::new(),
+            }),
+        })
+    }
+
+    /// Switch to a new key now, for instance when the current one may have leaked.
+    pub fn rotate(&self) -> Result<(), rustls::Error> {
+        let mut keys = self.write();
+        self.rotate_locked(&mut keys, Instant::now())
+    }
+
+    /// Rotate keys on time in a task on the current runtime, and forget keys once
+    /// their overlap has passed.
+    ///
+    /// Failed rotations are retried a minute later. Abort the returned handle to
+    /// stop rotating.
+    #[cfg(feature = "reload")]
+    pub fn spawn_rotation(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let now = Instant::now();
+                let wait = {
+                    let mut keys = self.write();
+                    let rotated =
+                        keys.rotate_at > now || self.rotate_locked(&mut keys, now).is_ok();
+                    keys.previous.retain(|(_, expiry)| *expiry > now);
+                    let next = keys
+                        .previous
+                        .iter()
+                        .map(|(_, expiry)| *expiry)
+                        .fold(keys.rotate_at, Instant::min);
+                    match rotated {
+                        true => next.saturating_duration_since(now),
+                        false => Duration::from_secs(60),
+                    }
+                };
+                tokio::time::sleep(wait).await;
+            }
+        })
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Keys> {
+        self.keys.write().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn rotate_locked(&self, keys: &mut Keys, now: Instant) -> Result<(), rustls::Error> {
+        let new = (self.generator)()?;
+        let old = std::mem::replace(&mut keys.current, new);
+        keys.previous.retain(|(_, expiry)| *expiry > now);
+        keys.previous.push((old, now + self.overlap));
+        keys.rotate_at = now + self.lifetime;
+        Ok(())
+    }
+
+    /// Returns the current key, rotating first if it is due.
+    fn current(&self) -> Arc<dyn ProducesTickets> {
+        let now = Instant::now();
+        {
+            let keys = self.keys.read().unwrap_or_else(|err| err.into_inner());
+            if keys.rotate_at > now {
+                return keys.current.clone();
+            }
+        }
+        let mut keys = self.write();
+        // Another thread may have rotated in the meantime. If the generator fails,
+        // the current key stays in use until the next try.
+        if keys.rotate_at <= now {
+            let _ = self.rotate_locked(&mut keys, now);
+        }
+        keys.current.clone()
+    }
+}
+
+impl ProducesTickets for RotatingTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        u32::try_from(self.overlap.as_secs()).unwrap_or(u32::MAX)
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.current().encrypt(plain)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        if let Some(plain) = self.current().decrypt(cipher) {
+            return Some(plain);
+        }
+        let now = Instant::now();
+        let keys = self.keys.read().unwrap_or_else(|err| err.into_inner());
+        keys.previous
+            .iter()
+            .filter(|(_, expiry)| *expiry > now)
+            .find_map(|(key, _)| key.decrypt(cipher))
+    }
+}
+
+impl fmt::Debug for RotatingTicketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RotatingTicketer")
+            .field("lifetime", &self.lifetime)
+            .field("overlap", &self.overlap)
+            .finish_non_exhaustive()
+    }
+}
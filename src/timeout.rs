@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use crate::common::poll_fn;
+
+/// The timeout of a handshake, if any.
+///
+/// The timer starts when the handshake is first polled, so creating the future
+/// outside of a runtime is fine.
+#[derive(Debug, Default)]
+pub(crate) struct Deadline {
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Deadline {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            sleep: None,
+        }
+    }
+
+    /// Whether the deadline has passed; registers for a wakeup if not.
+    pub(crate) fn poll_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+        self.sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)))
+            .as_mut()
+            .poll(cx)
+            .is_ready()
+    }
+}
+
+pub(crate) fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out")
+}
+
+/// Sends `close_notify`, then reads and discards plaintext until the peer's, all
+/// within `timeout`.
+pub(crate) async fn graceful_shutdown<S>(stream: &mut S, timeout: Duration) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let close = async {
+        poll_fn(|cx| Pin::new(&mut *stream).poll_shutdown(cx)).await?;
+        let mut buf = [0; 1024];
+        loop {
+            let mut buf = ReadBuf::new(&mut buf);
+            poll_fn(|cx| Pin::new(&mut *stream).poll_read(cx, &mut buf)).await?;
+            if buf.filled().is_empty() {
+                return Ok(());
+            }
+        }
+    };
+    match tokio::time::timeout(timeout, close).await {
+        Ok(output) => output,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "TLS shutdown timed out",
+        )),
+    }
+}
+
+/// A stream that fails reads and writes that wait too long, and connections that
+/// stay idle too long.
+///
+/// This wraps any stream, usually a [`TlsStream`](crate::TlsStream), so the
+/// deadlines apply to plaintext: a read times out when no plaintext arrives, even if
+/// the peer keeps sending partial records. Operations that time out fail with an
+/// error of kind `TimedOut`, with a [`StreamTimeout`] as the inner error. No
+/// deadlines are set by default.
+#[derive(Debug)]
+pub struct TimeoutStream<S> {
+    stream: S,
+    read: Timer,
+    write: Timer,
+    idle: Timer,
+}
+
+/// Which deadline of a [`TimeoutStream`] passed, as the inner error of an `io::Error`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StreamTimeout {
+    /// A read waited longer than the read timeout.
+    Read,
+    /// A write, flush or shutdown waited longer than the write timeout.
+    Write,
+    /// Nothing was read or written for longer than the idle timeout.
+    Idle,
+}
+
+impl fmt::Display for StreamTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StreamTimeout::Read => "TLS read timed out",
+            StreamTimeout::Write => "TLS write timed out",
+            StreamTimeout::Idle => "TLS connection idle for too long",
+        })
+    }
+}
+
+impl Error for StreamTimeout {}
+
+impl From<StreamTimeout> for io::Error {
+    fn from(timeout: StreamTimeout) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, timeout)
+    }
+}
+
+/// A timeout, and the timer for it while it runs.
+#[derive(Debug, Default)]
+struct Timer {
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Timer {
+    /// Starts the timer unless it runs, and returns whether it expired.
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+        self.sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)))
+            .as_mut()
+            .poll(cx)
+            .is_ready()
+    }
+
+    fn stop(&mut self) {
+        self.sleep = None;
+    }
+
+    /// Restarts the timer if it runs, without allocating a new one.
+    fn restart(&mut self) {
+        if let (Some(timeout), Some(sleep)) = (self.timeout, &mut self.sleep) {
+            sleep.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+}
+
+impl<S> TimeoutStream<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            read: Timer::default(),
+            write: Timer::default(),
+            idle: Timer::default(),
+        }
+    }
+
+    /// Fail reads that wait longer than `timeout` for data.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail writes, flushes and shutdowns that wait longer than `timeout` for the
+    /// stream to take data.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail reads and writes once nothing was read or written for `timeout`.
+    ///
+    /// Idle connections are only noticed while a read or write is pending, so keep
+    /// a read pending to reap them, as servers usually do anyway.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle.timeout = Some(timeout);
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Updates the timers for an operation that returned `poll`, timed by `timer`.
+    fn poll_timeouts<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<T>>,
+        timer: fn(&mut Self) -> &mut Timer,
+        timeout: StreamTimeout,
+    ) -> Poll<io::Result<T>> {
+        match poll {
+            Poll::Ready(output) => {
+                timer(self).stop();
+                self.idle.restart();
+                Poll::Ready(output)
+            }
+            Poll::Pending if timer(self).poll_expired(cx) => {
+                timer(self).stop();
+                Poll::Ready(Err(timeout.into()))
+            }
+            Poll::Pending if self.idle.poll_expired(cx) => {
+                self.idle.stop();
+                Poll::Ready(Err(StreamTimeout::Idle.into()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TimeoutStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_read(cx, buf);
+        this.poll_timeouts(cx, poll, |this| &mut this.read, StreamTimeout::Read)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TimeoutStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+        this.poll_timeouts(cx, poll, |this| &mut this.write, StreamTimeout::Write)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_write_vectored(cx, bufs);
+        this.poll_timeouts(cx, poll, |this| &mut this.write, StreamTimeout::Write)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_flush(cx);
+        this.poll_timeouts(cx, poll, |this| &mut this.write, StreamTimeout::Write)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_shutdown(cx);
+        this.poll_timeouts(cx, poll, |this| &mut this.write, StreamTimeout::Write)
+    }
+}
@@ -0,0 +1,264 @@
+//! Debug dumps of the handshake messages exchanged on a connection.
+//!
+//! Wrap the IO of a connection in a [`Transcript`] to have each plaintext handshake
+//! message and alert passed to a callback, or written out with [`dump`]:
+//!
+//! ```no_run
+//! # async fn f(connector: tokio_rustls::TlsConnector) -> std::io::Result<()> {
+//! use tokio_rustls::transcript::{self, Transcript};
+//!
+//! let stream = tokio::net::TcpStream::connect("example.com:443").await?;
+//! let stream = Transcript::new(stream, transcript::dump(std::io::stderr()));
+//! let domain = rustls::pki_types::ServerName::try_from("example.com").unwrap();
+//! let stream = connector.connect(domain, stream).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Once a direction switches to encrypted records, nothing more can be decoded in it;
+//! this is reported once with [`Event::Encrypted`].
+
+use std::fmt;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use rustls::{AlertDescription, ContentType, HandshakeType};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Which way a message went, from the point of view of this end of the connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Something seen on the wire while the handshake was in the clear.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A complete handshake message; `payload` excludes the 4-byte message header.
+    Handshake {
+        direction: Direction,
+        typ: HandshakeType,
+        payload: &'a [u8],
+    },
+    /// A plaintext alert.
+    Alert {
+        direction: Direction,
+        fatal: bool,
+        description: AlertDescription,
+    },
+    /// Records are encrypted from here on in this direction.
+    Encrypted { direction: Direction },
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = |direction: &Direction| match direction {
+            Direction::Sent => ">>",
+            Direction::Received => "<<",
+        };
+
+        match self {
+            Event::Handshake {
+                direction,
+                typ,
+                payload,
+            } => {
+                write!(f, "{} {:?}, {} bytes", arrow(direction), typ, payload.len())?;
+                for line in payload.chunks(16) {
+                    f.write_str("\n   ")?;
+                    for byte in line {
+                        write!(f, " {:02x}", byte)?;
+                    }
+                }
+                Ok(())
+            }
+            Event::Alert {
+                direction,
+                fatal,
+                description,
+            } => {
+                let level = if *fatal { "fatal" } else { "warning" };
+                write!(f, "{} Alert, {} {:?}", arrow(direction), level, description)
+            }
+            Event::Encrypted { direction } => write!(f, "{} (encrypted)", arrow(direction)),
+        }
+    }
+}
+
+/// Returns a callback for [`Transcript::new`] that writes each event to `writer`,
+/// one line per event plus the hex dump of handshake payloads.
+///
+/// Write errors are ignored, so a broken writer doesn't take down the connection.
+pub fn dump<W: Write>(mut writer: W) -> impl FnMut(Event<'_>) {
+    move |event| {
+        let _ = writeln!(writer, "{}", event);
+    }
+}
+
+/// An IO wrapper that decodes the TLS records passing through it.
+pub struct Transcript<IO, F> {
+    io: IO,
+    callback: F,
+    sent: Decoder,
+    received: Decoder,
+}
+
+impl<IO, F> Transcript<IO, F>
+where
+    F: FnMut(Event<'_>),
+{
+    pub fn new(io: IO, callback: F) -> Self {
+        Transcript {
+            io,
+            callback,
+            sent: Decoder::new(Direction::Sent),
+            received: Decoder::new(Direction::Received),
+        }
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &IO {
+        &self.io
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> IO {
+        self.io
+    }
+}
+
+impl<IO, F> AsyncRead for Transcript<IO, F>
+where
+    IO: AsyncRead + Unpin,
+    F: FnMut(Event<'_>) + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut this.io).poll_read(cx, buf))?;
+        this.received
+            .feed(&buf.filled()[before..], &mut this.callback);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO, F> AsyncWrite for Transcript<IO, F>
+where
+    IO: AsyncWrite + Unpin,
+    F: FnMut(Event<'_>) + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.io).poll_write(cx, buf))?;
+        this.sent.feed(&buf[..n], &mut this.callback);
+        Poll::Ready(Ok(n))
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+/// Reassembles records, and handshake messages spanning records, in one direction.
+struct Decoder {
+    direction: Direction,
+    record: Vec<u8>,
+    handshake: Vec<u8>,
+    encrypted: bool,
+}
+
+impl Decoder {
+    const HEADER_LEN: usize = 5;
+
+    fn new(direction: Direction) -> Self {
+        Decoder {
+            direction,
+            record: Vec::new(),
+            handshake: Vec::new(),
+            encrypted: false,
+        }
+    }
+
+    fn feed(&mut self, mut data: &[u8], callback: &mut impl FnMut(Event<'_>)) {
+        while !self.encrypted && !data.is_empty() {
+            let take = (self.wanted() - self.record.len()).min(data.len());
+            self.record.extend_from_slice(&data[..take]);
+            data = &data[take..];
+
+            if self.record.len() >= Self::HEADER_LEN && self.record.len() == self.wanted() {
+                let mut record = std::mem::take(&mut self.record);
+                self.record(
+                    ContentType::from(record[0]),
+                    &record[Self::HEADER_LEN..],
+                    callback,
+                );
+                record.clear();
+                self.record = record;
+            }
+        }
+    }
+
+    /// The length of the record being read, or of its header while that is incomplete.
+    fn wanted(&self) -> usize {
+        match self.record.get(3..Self::HEADER_LEN) {
+            Some(len) => Self::HEADER_LEN + usize::from(u16::from_be_bytes([len[0], len[1]])),
+            None => Self::HEADER_LEN,
+        }
+    }
+
+    fn record(&mut self, typ: ContentType, payload: &[u8], callback: &mut impl FnMut(Event<'_>)) {
+        match typ {
+            ContentType::Handshake => {
+                self.handshake.extend_from_slice(payload);
+                while let Some(header) = self.handshake.get(..4) {
+                    let len = usize::from(header[1]) << 16
+                        | usize::from(header[2]) << 8
+                        | usize::from(header[3]);
+                    if self.handshake.len() < 4 + len {
+                        break;
+                    }
+
+                    callback(Event::Handshake {
+                        direction: self.direction,
+                        typ: HandshakeType::from(header[0]),
+                        payload: &self.handshake[4..4 + len],
+                    });
+                    self.handshake.drain(..4 + len);
+                }
+            }
+            ContentType::Alert if payload.len() == 2 => callback(Event::Alert {
+                direction: self.direction,
+                fatal: payload[0] == 2,
+                description: AlertDescription::from(payload[1]),
+            }),
+            ContentType::ChangeCipherSpec | ContentType::ApplicationData => {
+                self.encrypted = true;
+                callback(Event::Encrypted {
+                    direction: self.direction,
+                });
+            }
+            _ => {}
+        }
+    }
+}
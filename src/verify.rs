@@ -0,0 +1,395 @@
+//! Combinators for building layered server certificate verification policies.
+//!
+//! Each combinator implements [`ServerCertVerifier`], so they nest freely and can be
+//! handed to [`TlsConnector::verifier`](crate::TlsConnector::verifier):
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use rustls::client::WebPkiServerVerifier;
+//! # use rustls::crypto::CryptoProvider;
+//! # use tokio_rustls::verify::{AllOf, AnyOf, Pinned};
+//! # fn f(
+//! #     connector: tokio_rustls::TlsConnector,
+//! #     roots: Arc<rustls::RootCertStore>,
+//! #     backup_roots: Arc<rustls::RootCertStore>,
+//! #     pins: Vec<rustls::pki_types::CertificateDer<'static>>,
+//! #     provider: &CryptoProvider,
+//! # ) -> Result<(), rustls::client::VerifierBuilderError> {
+//! // Trust either root set, but only for the pinned certificates.
+//! let webpki = AnyOf::new(vec![
+//!     WebPkiServerVerifier::builder(roots).build()?,
+//!     WebPkiServerVerifier::builder(backup_roots).build()?,
+//! ]);
+//! let verifier = AllOf::new(vec![Arc::new(webpki), Arc::new(Pinned::new(pins, provider))]);
+//! let connector = connector.verifier(Arc::new(verifier));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Checks that have to wait on IO, like revocation lookups, go in an
+//! [`AsyncVerifier`] instead.
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::{CertificateError, DigitallySignedStruct, Error, SignatureScheme};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::common::IoSession;
+use crate::{client, server};
+
+/// Accepts a server only if every one of its verifiers does.
+///
+/// The error of the first verifier to reject the server is returned.
+#[derive(Debug)]
+pub struct AllOf {
+    verifiers: Vec<Arc<dyn ServerCertVerifier>>,
+}
+
+impl AllOf {
+    pub fn new(verifiers: Vec<Arc<dyn ServerCertVerifier>>) -> Self {
+        AllOf { verifiers }
+    }
+}
+
+impl ServerCertVerifier for AllOf {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        for verifier in &self.verifiers {
+            verifier.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )?;
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        for verifier in &self.verifiers {
+            verifier.verify_tls12_signature(message, cert, dss)?;
+        }
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        for verifier in &self.verifiers {
+            verifier.verify_tls13_signature(message, cert, dss)?;
+        }
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    /// The schemes supported by all verifiers.
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        let mut verifiers = self.verifiers.iter();
+        let mut schemes = match verifiers.next() {
+            Some(verifier) => verifier.supported_verify_schemes(),
+            None => return Vec::new(),
+        };
+        for verifier in verifiers {
+            let supported = verifier.supported_verify_schemes();
+            schemes.retain(|scheme| supported.contains(scheme));
+        }
+        schemes
+    }
+}
+
+/// Accepts a server if any one of its verifiers does.
+///
+/// Verifiers are tried in order; if all of them reject the server, the error of
+/// the last one is returned.
+#[derive(Debug)]
+pub struct AnyOf {
+    verifiers: Vec<Arc<dyn ServerCertVerifier>>,
+}
+
+impl AnyOf {
+    pub fn new(verifiers: Vec<Arc<dyn ServerCertVerifier>>) -> Self {
+        AnyOf { verifiers }
+    }
+
+    fn first_ok<T>(
+        &self,
+        f: impl Fn(&dyn ServerCertVerifier) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut last = Error::InvalidCertificate(CertificateError::UnknownIssuer);
+        for verifier in &self.verifiers {
+            match f(verifier.as_ref()) {
+                Ok(valid) => return Ok(valid),
+                Err(err) => last = err,
+            }
+        }
+        Err(last)
+    }
+}
+
+impl ServerCertVerifier for AnyOf {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        self.first_ok(|verifier| {
+            verifier.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        })
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.first_ok(|verifier| verifier.verify_tls12_signature(message, cert, dss))
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.first_ok(|verifier| verifier.verify_tls13_signature(message, cert, dss))
+    }
+
+    /// The schemes supported by any verifier.
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        let mut schemes = Vec::new();
+        for verifier in &self.verifiers {
+            for scheme in verifier.supported_verify_schemes() {
+                if !schemes.contains(&scheme) {
+                    schemes.push(scheme);
+                }
+            }
+        }
+        schemes
+    }
+}
+
+/// Accepts a server only if its end-entity certificate is one of a fixed set.
+///
+/// This does not check the certificate chain, validity period or server name, so it
+/// is usually combined with a regular verifier through [`AllOf`].
+pub struct Pinned {
+    certs: Vec<CertificateDer<'static>>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl Pinned {
+    /// Pin `certs`, verifying handshake signatures with the algorithms of `provider`.
+    pub fn new(certs: Vec<CertificateDer<'static>>, provider: &CryptoProvider) -> Self {
+        Pinned {
+            certs,
+            algorithms: provider.signature_verification_algorithms,
+        }
+    }
+}
+
+impl fmt::Debug for Pinned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pinned")
+            .field("certs", &self.certs.len())
+            .finish()
+    }
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        if self.certs.iter().any(|cert| cert == end_entity) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// The future returned by [`AsyncVerifier::verify`].
+pub type VerifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+/// A check of the peer's certificates that waits on IO, like an OCSP or CRL lookup.
+///
+/// rustls verifies certificates synchronously, in the middle of the handshake, where
+/// a lookup would block the runtime. An `AsyncVerifier`, installed with
+/// [`TlsConnector::async_verifier`](crate::TlsConnector::async_verifier) or
+/// [`TlsAcceptor::async_verifier`](crate::TlsAcceptor::async_verifier), runs once the
+/// handshake is done and the config's verifier has accepted the peer instead. The
+/// connect or accept future only resolves once it has accepted the peer too, so no
+/// application data is read or written before then.
+pub trait AsyncVerifier: Send + Sync {
+    /// Checks `certificates`, the chain the peer presented, end-entity certificate
+    /// first.
+    ///
+    /// `server_name` is the name connected to on clients, and the SNI the client sent
+    /// on servers. An error fails the handshake with an error of kind `InvalidData`,
+    /// and the peer is sent a `close_notify` alert. Peers that presented no
+    /// certificate, as clients may when client auth is optional, are not checked.
+    fn verify<'a>(
+        &'a self,
+        certificates: &'a [CertificateDer<'static>],
+        server_name: Option<&'a ServerName<'static>>,
+    ) -> VerifyFuture<'a>;
+}
+
+/// The streams an [`AsyncVerifier`] can check.
+pub(crate) trait Verifiable: AsyncWrite + Unpin {
+    fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]>;
+
+    /// The server name, if the stream knows it.
+    fn server_name(&self) -> Option<ServerName<'static>>;
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Verifiable for client::TlsStream<IO> {
+    fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        self.session.peer_certificates()
+    }
+
+    fn server_name(&self) -> Option<ServerName<'static>> {
+        None
+    }
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Verifiable for server::TlsStream<IO> {
+    fn peer_certificates(&self) -> Option<&[CertificateDer<'static>]> {
+        self.session.peer_certificates()
+    }
+
+    fn server_name(&self) -> Option<ServerName<'static>> {
+        let sni = self.session.server_name()?;
+        ServerName::try_from(sni.to_owned()).ok()
+    }
+}
+
+/// The [`AsyncVerifier`] of a connect or accept future, and the stream it is
+/// checking.
+pub(crate) struct PendingVerify<S> {
+    verifier: Option<Arc<dyn AsyncVerifier>>,
+    server_name: Option<ServerName<'static>>,
+    pending: Option<(S, VerifyFuture<'static>)>,
+}
+
+impl<S> Default for PendingVerify<S> {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+impl<S> PendingVerify<S> {
+    pub(crate) fn new(
+        verifier: Option<Arc<dyn AsyncVerifier>>,
+        server_name: Option<ServerName<'static>>,
+    ) -> Self {
+        Self {
+            verifier,
+            server_name,
+            pending: None,
+        }
+    }
+}
+
+impl<S: Verifiable + IoSession> PendingVerify<S> {
+    /// Polls `handshake`, then the verifier on the stream it completed with.
+    pub(crate) fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        handshake: impl FnOnce(&mut Context<'_>) -> Poll<Result<S, (io::Error, S::Io)>>,
+    ) -> Poll<Result<S, (io::Error, S::Io)>> {
+        if self.pending.is_none() {
+            let stream = ready!(handshake(cx))?;
+            let verifier = match &self.verifier {
+                Some(verifier) => verifier.clone(),
+                None => return Poll::Ready(Ok(stream)),
+            };
+            let certificates = match stream.peer_certificates() {
+                Some(certificates) => certificates.to_vec(),
+                None => return Poll::Ready(Ok(stream)),
+            };
+            let server_name = self.server_name.take().or_else(|| stream.server_name());
+            let verify =
+                Box::pin(async move { verifier.verify(&certificates, server_name.as_ref()).await });
+            self.pending = Some((stream, verify));
+        }
+
+        let (_, verify) = self.pending.as_mut().expect("verification is pending");
+        let result = ready!(verify.as_mut().poll(cx));
+        let (stream, _) = self.pending.take().expect("verification is pending");
+        Poll::Ready(match result {
+            Ok(()) => Ok(stream),
+            Err(err) => {
+                let err = io::Error::new(io::ErrorKind::InvalidData, err);
+                Err((err, close(cx, stream).into_io()))
+            }
+        })
+    }
+
+    /// Takes the IO of the stream being checked, if any, to abandon the handshake.
+    #[cfg(any(feature = "cancel", feature = "timeout"))]
+    pub(crate) fn abort(&mut self, cx: &mut Context<'_>) -> Option<S::Io> {
+        let (stream, _) = self.pending.take()?;
+        Some(close(cx, stream).into_io())
+    }
+}
+
+/// Sends `close_notify`, if the IO takes it without waiting.
+fn close<S: AsyncWrite + Unpin>(cx: &mut Context<'_>, mut stream: S) -> S {
+    let _ = Pin::new(&mut stream).poll_shutdown(cx);
+    stream
+}
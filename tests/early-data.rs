@@ -1,6 +1,6 @@
 #![cfg(feature = "early-data")]
 
-use std::io::{self, BufRead, BufReader, Cursor};
+use std::io::{self, BufRead, BufReader, Cursor, Write};
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::process::{Child, Command, Stdio};
@@ -11,11 +11,12 @@ use std::time::Duration;
 
 use futures_util::{future, future::Future, ready};
 use rustls::{self, ClientConfig, RootCertStore};
-use tokio::io::{split, AsyncRead, AsyncWriteExt, ReadBuf};
+use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
 use tokio::sync::oneshot;
 use tokio::time::sleep;
-use tokio_rustls::{client::TlsStream, TlsConnector};
+use tokio_rustls::server::EarlyDataStatus;
+use tokio_rustls::{client::TlsStream, ReplayCache, TlsAcceptor, TlsConnector};
 
 struct Read1<T>(T);
 
@@ -90,6 +91,31 @@ async fn send(
     Ok(rd.unsplit(wd))
 }
 
+async fn send_explicit(
+    config: Arc<ClientConfig>,
+    addr: SocketAddr,
+    data: &[u8],
+) -> io::Result<TlsStream<TcpStream>> {
+    let connector = TlsConnector::from(config).early_data(true);
+    let stream = TcpStream::connect(&addr).await?;
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+
+    let mut stream = connector.connect(domain, stream).await?;
+    {
+        let mut early_data = stream.early_data().expect("early data should be available");
+        let bytes_left = early_data.bytes_left();
+        early_data.write_all(data)?;
+        assert_eq!(early_data.bytes_left(), bytes_left - data.len());
+        assert_eq!(early_data.bytes_written(), data.len());
+    }
+
+    stream.flush().await?;
+    assert!(stream.early_data().is_none());
+    stream.shutdown().await?;
+
+    Ok(stream)
+}
+
 struct DropKill(Child);
 
 impl Drop for DropKill {
@@ -155,7 +181,10 @@ async fn test_0rtt() -> io::Result<()> {
     let io = send(config.clone(), addr, b"hello").await?;
     assert!(!io.get_ref().1.is_early_data_accepted());
 
-    let io = send(config, addr, b"world!").await?;
+    let io = send(config.clone(), addr, b"world!").await?;
+    assert!(io.get_ref().1.is_early_data_accepted());
+
+    let io = send_explicit(config, addr, b"explicit").await?;
     assert!(io.get_ref().1.is_early_data_accepted());
 
     let stdout = handle.0.stdout.as_mut().unwrap();
@@ -163,8 +192,246 @@ async fn test_0rtt() -> io::Result<()> {
 
     let has_msg1 = lines.by_ref().any(|line| line.unwrap().contains("hello"));
     let has_msg2 = lines.by_ref().any(|line| line.unwrap().contains("world!"));
+    let has_msg3 = lines
+        .by_ref()
+        .any(|line| line.unwrap().contains("explicit"));
 
-    assert!(has_msg1 && has_msg2);
+    assert!(has_msg1 && has_msg2 && has_msg3);
 
     Ok(())
 }
+
+#[tokio::test]
+async fn server_early_data_status() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let mut sconfig = rustls::ServerConfig::clone(&sconfig);
+    sconfig.max_early_data_size = 1024;
+    let acceptor = TlsAcceptor::from(Arc::new(sconfig));
+    let mut cconfig = rustls::ClientConfig::clone(&cconfig);
+    cconfig.enable_early_data = true;
+    let connector = TlsConnector::from(Arc::new(cconfig)).early_data(true);
+
+    let mut statuses = Vec::new();
+    for _ in 0..2 {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let acceptor = acceptor.clone();
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(sstream).await?;
+            let status = stream.early_data_status();
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await?;
+            stream.shutdown().await?;
+            Ok((status, buf)) as io::Result<_>
+        });
+
+        // The first connection gets a ticket; the second sends its request as 0-RTT.
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let mut stream = connector.connect(domain, cstream).await?;
+        stream.write_all(b"early").await?;
+        stream.flush().await?;
+        stream.write_all(b" late").await?;
+        stream.shutdown().await?;
+        stream.read_to_end(&mut Vec::new()).await?;
+
+        let (status, buf) = server.await.unwrap()?;
+        assert_eq!(buf, b"early late");
+        statuses.push((
+            status,
+            stream.is_early_data_accepted(),
+            stream.early_data_len(),
+        ));
+    }
+
+    assert_eq!(
+        statuses,
+        [
+            (EarlyDataStatus::NotAccepted, false, 0),
+            (EarlyDataStatus::Accepted { len: 5 }, true, 5)
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_early() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let mut sconfig = rustls::ServerConfig::clone(&sconfig);
+    sconfig.max_early_data_size = 1024;
+    let acceptor = TlsAcceptor::from(Arc::new(sconfig.clone()));
+    // Shares the ticketer, so it resumes sessions but rejects their early data.
+    sconfig.max_early_data_size = 0;
+    let rejecting = TlsAcceptor::from(Arc::new(sconfig));
+    let mut cconfig = rustls::ClientConfig::clone(&cconfig);
+    cconfig.enable_early_data = true;
+    let connector = TlsConnector::from(Arc::new(cconfig));
+
+    let mut results = Vec::new();
+    for acceptor in [&acceptor, &acceptor, &rejecting] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let acceptor = acceptor.clone();
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(sstream).await?;
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await?;
+            stream.shutdown().await?;
+            Ok(buf) as io::Result<_>
+        });
+
+        // The first connection gets a ticket; the others can send early data.
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (mut early, connect) = connector.connect_early(domain, cstream);
+        let offered = early.bytes_left() > 0;
+        if offered {
+            early.write_all(b"early")?;
+            assert_eq!(early.bytes_written(), 5);
+        }
+        let mut stream = connect.await?;
+        assert!(early.write(b"late").is_err());
+        stream.write_all(b" late").await?;
+        stream.shutdown().await?;
+        stream.read_to_end(&mut Vec::new()).await?;
+
+        let buf = server.await.unwrap()?;
+        results.push((
+            offered,
+            stream.is_early_data_accepted(),
+            stream.early_data_len(),
+            String::from_utf8(buf).unwrap(),
+        ));
+    }
+
+    // Rejected early data is not sent again.
+    assert_eq!(
+        results,
+        [
+            (false, false, 0, " late".to_owned()),
+            (true, true, 5, "early late".to_owned()),
+            (true, false, 5, " late".to_owned()),
+        ]
+    );
+
+    Ok(())
+}
+
+/// Completes a handshake, so `connector` gets a ticket to resume with.
+async fn get_ticket(connector: &TlsConnector, acceptor: &TlsAcceptor) -> io::Result<()> {
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        future::join(connector.connect(domain, cstream), acceptor.accept(sstream)).await;
+    let (mut client, mut server) = (client?, server?);
+    server.shutdown().await?;
+    client.read_to_end(&mut Vec::new()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn accept_early() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).max_early_data_size(1024);
+    let mut cconfig = rustls::ClientConfig::clone(&cconfig);
+    cconfig.enable_early_data = true;
+    let connector = TlsConnector::from(Arc::new(cconfig));
+    get_ticket(&connector, &acceptor).await?;
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (mut early, mut connect) = connector.connect_early(domain, cstream);
+    early.write_all(b"early")?;
+    let (mut reader, mut accept) = acceptor.accept_early(sstream);
+
+    // The early data is read before the client has finished the handshake.
+    assert!(futures_util::poll!(&mut connect).is_pending());
+    assert!(futures_util::poll!(&mut accept).is_pending());
+    let mut buf = [0; 5];
+    reader.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"early");
+
+    let (client, server) = future::join(connect, accept).await;
+    let (mut client, mut server) = (client?, server?);
+    assert_eq!(reader.read(&mut buf).await?, 0);
+    assert_eq!(
+        server.early_data_status(),
+        EarlyDataStatus::Accepted { len: 5 }
+    );
+
+    client.write_all(b"late").await?;
+    client.shutdown().await?;
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"late");
+
+    Ok(())
+}
+
+/// A session store whose sessions can be resumed more than once, like a store
+/// shared between servers without an atomic `take`.
+#[derive(Debug)]
+struct ReusableSessions(Arc<rustls::server::ServerSessionMemoryCache>);
+
+impl rustls::server::StoresServerSessions for ReusableSessions {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.0.put(key, value)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+
+    fn can_cache(&self) -> bool {
+        true
+    }
+}
+
+#[tokio::test]
+async fn anti_replay() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let mut sconfig = rustls::ServerConfig::clone(&sconfig);
+    sconfig.session_storage = Arc::new(ReusableSessions(
+        rustls::server::ServerSessionMemoryCache::new(32),
+    ));
+    let acceptor = TlsAcceptor::from(Arc::new(sconfig))
+        .max_early_data_size(1024)
+        .anti_replay(Arc::new(ReplayCache::new(Duration::from_secs(60))));
+    let mut cconfig = rustls::ClientConfig::clone(&cconfig);
+    cconfig.enable_early_data = true;
+    let connector = TlsConnector::from(Arc::new(cconfig));
+    get_ticket(&connector, &acceptor).await?;
+
+    // An attacker records the first flight of a client sending early data.
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (mut early, mut connect) = connector.connect_early(domain, cstream);
+    early.write_all(b"early")?;
+    assert!(futures_util::poll!(&mut connect).is_pending());
+    let (mut read, write) = split(sstream);
+    let mut flight = vec![0; 4096];
+    let len = read.read(&mut flight).await?;
+    flight.truncate(len);
+
+    let io = tokio::io::join(Cursor::new(flight.clone()).chain(read), write);
+    let (client, server) = future::join(connect, acceptor.accept(io)).await;
+    let (_client, server) = (client?, server?);
+    assert_eq!(
+        server.early_data_status(),
+        EarlyDataStatus::Accepted { len: 5 }
+    );
+
+    // Sending it again is refused before the early data can be read.
+    let (_attacker, sstream) = tokio::io::duplex(4096);
+    let (read, write) = split(sstream);
+    let io = tokio::io::join(Cursor::new(flight).chain(read), write);
+    let err = acceptor.accept(io).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "early data was replayed");
+
+    Ok(())
+}
+
+// Share `utils` module with other tests
+include!("utils.rs");
@@ -0,0 +1,296 @@
+//! Interop tests against the `openssl` command line tool.
+//!
+//! Run with `cargo test --features interop --test interop`. Tests pass without
+//! doing anything if `openssl` can't be run.
+#![cfg(feature = "interop")]
+
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+
+use futures_util::future::join;
+use rustls::ProtocolVersion;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::{Child, Command};
+use tokio_rustls::{EofReason, TlsAcceptor, TlsConnector};
+
+include!("utils.rs");
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/end.cert");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/end.rsa");
+const CHAIN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/end.chain");
+
+fn openssl_available() -> bool {
+    let available = std::process::Command::new("openssl")
+        .arg("version")
+        .output()
+        .map_or(false, |output| output.status.success());
+    if !available {
+        eprintln!("skipping: openssl not found");
+    }
+    available
+}
+
+fn openssl(args: &[&str]) -> Command {
+    let mut command = Command::new("openssl");
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    command
+}
+
+/// Starts `openssl s_server` with `args`, returning it and the port it listens on.
+async fn s_server(args: &[&str]) -> io::Result<(Child, u16)> {
+    // s_server only reports the port it picked without `-quiet`, so pick one for it.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port();
+    let accept = format!("127.0.0.1:{}", port);
+    let mut command = openssl(&[
+        "s_server", "-accept", &accept, "-naccept", "1", "-cert", CERT, "-key", KEY,
+    ]);
+    Ok((command.args(args).spawn()?, port))
+}
+
+/// Connects to the s_server on `port`, once it is up.
+async fn connect(
+    port: u16,
+    connector: &TlsConnector,
+) -> io::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let mut attempts = 0;
+    let stream = loop {
+        match TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => break stream,
+            Err(err) if attempts == 100 => return Err(err),
+            Err(_) => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+    };
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    connector.connect(domain, stream).await
+}
+
+async fn read_line(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<String> {
+    let mut line = Vec::new();
+    loop {
+        let byte = stream.read_u8().await?;
+        line.push(byte);
+        if byte == b'\n' {
+            return Ok(String::from_utf8(line).unwrap());
+        }
+    }
+}
+
+#[tokio::test]
+async fn connect_to_s_server() -> io::Result<()> {
+    if !openssl_available() {
+        return Ok(());
+    }
+
+    let (_, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig).alpn_protocols(tokio_rustls::AlpnProtocol::HTTP);
+
+    // `-rev` echoes each line back reversed.
+    let (mut server, port) = s_server(&["-rev", "-alpn", "http/1.1"]).await?;
+    let mut client = connect(port, &connector).await?;
+    {
+        let (_, session) = client.get_ref();
+        assert_eq!(session.protocol_version(), Some(ProtocolVersion::TLSv1_3));
+        assert_eq!(session.alpn_protocol(), Some(&b"http/1.1"[..]));
+    }
+
+    client.write_all(b"hello\n").await?;
+    client.flush().await?;
+    assert_eq!(read_line(&mut client).await?, "olleh\n");
+
+    client.shutdown().await?;
+    assert!(server.wait().await?.success());
+    Ok(())
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn connect_to_s_server_tls12() -> io::Result<()> {
+    if !openssl_available() {
+        return Ok(());
+    }
+
+    let (_, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let (mut server, port) = s_server(&["-rev", "-tls1_2"]).await?;
+    let mut client = connect(port, &connector).await?;
+    assert_eq!(
+        client.get_ref().1.protocol_version(),
+        Some(ProtocolVersion::TLSv1_2)
+    );
+
+    client.write_all(b"hello\n").await?;
+    client.flush().await?;
+    assert_eq!(read_line(&mut client).await?, "olleh\n");
+
+    client.shutdown().await?;
+    assert!(server.wait().await?.success());
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_to_s_server_large_transfer() -> io::Result<()> {
+    if !openssl_available() {
+        return Ok(());
+    }
+
+    let (_, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+
+    // Without `-rev`, s_server copies what it receives to stdout, and stdin to the client.
+    let (mut server, port) = s_server(&["-quiet"]).await?;
+    let mut client = connect(port, &connector).await?;
+
+    let data = test_data(1024 * 1024);
+    let mut received = vec![0; data.len()];
+    let mut output = server.stdout.take().unwrap();
+    let (written, read) = tokio::join!(
+        async {
+            client.write_all(&data).await?;
+            client.flush().await
+        },
+        output.read_exact(&mut received)
+    );
+    written?;
+    read?;
+    assert!(received == data);
+
+    // Closing s_server's stdin has it send close_notify.
+    drop(server.stdin.take());
+    assert_eq!(client.read(&mut [0; 1]).await?, 0);
+    assert_eq!(client.read_eof_reason(), Some(EofReason::CloseNotify));
+    Ok(())
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn decline_s_server_renegotiation() -> io::Result<()> {
+    if !openssl_available() {
+        return Ok(());
+    }
+
+    let (_, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let (mut server, port) = s_server(&["-tls1_2"]).await?;
+    let mut client = connect(port, &connector).await?;
+
+    // An `r` line has s_server send a HelloRequest; data sent after it still arrives.
+    // s_server only recognizes the command on a read of its own, hence the pause.
+    let stdin = server.stdin.as_mut().unwrap();
+    stdin.write_all(b"r\n").await?;
+    stdin.flush().await?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    stdin.write_all(b"after\n").await?;
+    stdin.flush().await?;
+    assert_eq!(read_line(&mut client).await?, "after\n");
+
+    // rustls declines with a `no_renegotiation` warning, which OpenSSL treats as fatal.
+    client.flush().await?;
+    let err = client.read_to_end(&mut Vec::new()).await.unwrap_err();
+    let err = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<rustls::Error>());
+    assert_eq!(
+        err,
+        Some(&rustls::Error::AlertReceived(
+            rustls::AlertDescription::HandshakeFailure
+        ))
+    );
+    Ok(())
+}
+
+/// Has `openssl s_client`, run with `args`, send a line to an acceptor, which answers
+/// with `len` bytes and closes the connection.
+///
+/// Returns the protocol version negotiated.
+async fn accept_from_s_client(args: &[&str], len: usize) -> io::Result<Option<ProtocolVersion>> {
+    let (sconfig, _) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?.to_string();
+
+    // `-quiet` prints only the data received, and keeps the connection open until the
+    // server closes it. s_client mishandles reading 16 KiB or more from stdin at once,
+    // so large transfers go from the acceptor to s_client only.
+    let mut client = openssl(&[
+        "s_client",
+        "-connect",
+        &addr,
+        "-quiet",
+        "-nocommands",
+        "-CAfile",
+        CHAIN,
+        "-servername",
+        "foobar.com",
+        "-verify_hostname",
+        "foobar.com",
+        "-verify_return_error",
+    ])
+    .args(args)
+    .spawn()?;
+    let mut input = client.stdin.take().unwrap();
+    let mut output = client.stdout.take().unwrap();
+
+    let (stream, _) = listener.accept().await?;
+    let mut server = acceptor.accept(stream).await?;
+    let version = server.get_ref().1.protocol_version();
+
+    input.write_all(b"hello\n").await?;
+    input.flush().await?;
+    assert_eq!(read_line(&mut server).await?, "hello\n");
+
+    let data = test_data(len);
+    let mut received = Vec::new();
+    let write = async {
+        server.write_all(&data).await?;
+        server.shutdown().await
+    };
+    let (written, read) = join(write, output.read_to_end(&mut received)).await;
+    written?;
+    read?;
+    assert!(received == data);
+
+    // s_client answers close_notify with its own.
+    assert_eq!(server.read(&mut [0; 1]).await?, 0);
+    assert_eq!(server.read_eof_reason(), Some(EofReason::CloseNotify));
+    assert!(client.wait().await?.success());
+    Ok(version)
+}
+
+#[tokio::test]
+async fn accept_from_s_client_large_transfer() -> io::Result<()> {
+    if !openssl_available() {
+        return Ok(());
+    }
+
+    let version = accept_from_s_client(&[], 1024 * 1024).await?;
+    assert_eq!(version, Some(ProtocolVersion::TLSv1_3));
+    Ok(())
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn accept_from_s_client_tls12() -> io::Result<()> {
+    if !openssl_available() {
+        return Ok(());
+    }
+
+    let version = accept_from_s_client(&["-tls1_2"], 1024).await?;
+    assert_eq!(version, Some(ProtocolVersion::TLSv1_2));
+    Ok(())
+}
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
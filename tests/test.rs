@@ -13,7 +13,7 @@ use tokio::io::{copy, split, AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::oneshot;
 use tokio::{runtime, time};
-use tokio_rustls::{LazyConfigAcceptor, TlsAcceptor, TlsConnector};
+use tokio_rustls::{AlpnAcceptor, LazyConfigAcceptor, SniMetrics, TlsAcceptor, TlsConnector};
 
 const CERT: &str = include_str!("end.cert");
 const CHAIN: &[u8] = include_bytes!("end.chain");
@@ -185,6 +185,36 @@ async fn test_lazy_config_acceptor() -> io::Result<()> {
 }
 
 // This test is a follow-up from https://github.com/tokio-rs/tls/issues/85
+#[tokio::test]
+async fn lazy_config_acceptor_reject() -> io::Result<()> {
+    let (_, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let (cstream, sstream) = tokio::io::duplex(4096);
+
+    let server = async move {
+        let acceptor = LazyConfigAcceptor::new(rustls::server::Acceptor::default(), sstream);
+        let start = acceptor.await?;
+        assert_eq!(start.client_hello().server_name(), Some("unknown.example"));
+        start
+            .reject(rustls::AlertDescription::UnrecognisedName)
+            .await
+    };
+    let domain = pki_types::ServerName::try_from("unknown.example").unwrap();
+    let (client, server) = tokio::join!(connector.connect(domain, cstream), server);
+    server?;
+
+    let err = client.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(
+        err.into_inner().unwrap().downcast::<rustls::Error>().ok(),
+        Some(Box::new(rustls::Error::AlertReceived(
+            rustls::AlertDescription::UnrecognisedName
+        )))
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn lazy_config_acceptor_eof() {
     let buf = Cursor::new(Vec::new());
@@ -241,5 +271,3574 @@ async fn lazy_config_acceptor_take_io() -> Result<(), rustls::Error> {
     Ok(())
 }
 
+#[tokio::test]
+async fn lazy_config_acceptor_plaintext_fallback() -> io::Result<()> {
+    let (mut cstream, sstream) = tokio::io::duplex(1200);
+    let request = b"GET / HTTP/1.1\r\nHost: foobar.com\r\n\r\n";
+    cstream.write_all(request).await?;
+
+    let acceptor = LazyConfigAcceptor::new(rustls::server::Acceptor::default(), sstream)
+        .plaintext_fallback(true);
+    futures_util::pin_mut!(acceptor);
+    let err = acceptor.as_mut().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let (mut io, read) = acceptor.take_plaintext().unwrap();
+    assert_eq!(read, request);
+    io.write_all(b"HTTP/1.1 301 Moved Permanently\r\n\r\n")
+        .await?;
+    drop(io);
+
+    // The client gets the answer, and no alert ahead of it.
+    let mut buf = Vec::new();
+    cstream.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"HTTP/1.1 301 Moved Permanently\r\n\r\n");
+    assert!(acceptor.take_plaintext().is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lazy_config_acceptor_client_hello_bytes() -> io::Result<()> {
+    let (_, cconfig) = utils::make_configs();
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let mut client = rustls::ClientConnection::new(cconfig, domain).unwrap();
+    let mut records = Vec::new();
+    client.write_tls(&mut records)?;
+    let message = records[5..].to_vec();
+
+    // Also split the message across two records.
+    let (first, second) = message.split_at(100);
+    let mut fragmented = Vec::new();
+    for fragment in [first, second] {
+        fragmented.extend_from_slice(&[0x16, 0x03, 0x01]);
+        fragmented.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        fragmented.extend_from_slice(fragment);
+    }
+
+    for records in [records, fragmented] {
+        let io = Cursor::new(records);
+        let acceptor = LazyConfigAcceptor::new(rustls::server::Acceptor::default(), io);
+        let start = acceptor.await?;
+        assert_eq!(start.client_hello_bytes(), message);
+        assert_eq!(start.client_hello_bytes()[0], 1);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resumption_metrics() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).track_resumption();
+    let connector = TlsConnector::from(cconfig);
+
+    for _ in 0..2 {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let acceptor = acceptor.clone();
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(sstream).await?;
+            stream.write_all(b"hello").await?;
+            stream.shutdown().await?;
+            Ok(()) as io::Result<()>
+        });
+
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let mut stream = connector.connect(domain, cstream).await?;
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await?;
+        assert_eq!(buf, b"hello");
+        server.await.unwrap()?;
+    }
+
+    let client = connector.resumption_metrics();
+    assert_eq!((client.handshakes, client.resumed), (2, 1));
+
+    let server = acceptor.resumption_metrics();
+    assert_eq!((server.handshakes, server.resumed), (2, 1));
+    assert_eq!(server.attempted, 1);
+    assert!(server.issued >= 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sni_metrics() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).track_sni(1);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (_client, server) = (client?, server?);
+
+    let foobar = Some("foobar.com".to_owned());
+    let expected = SniMetrics {
+        handshakes: 1,
+        failures: 0,
+        active: 1,
+    };
+    assert_eq!(acceptor.sni_metrics()[&foobar], expected);
+    drop(server);
+    assert_eq!(acceptor.sni_metrics()[&foobar].active, 0);
+
+    // Over the name limit, and rejected by the client
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("other.example").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    assert!(client.is_err() && server.is_err());
+    assert_eq!(acceptor.sni_metrics()[&None].failures, 1);
+    assert_eq!(acceptor.sni_metrics().len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn capture_client_hello() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).capture_client_hello();
+    let connector = TlsConnector::from(cconfig).with_config(|config| {
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    });
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (_client, server) = (client?, server?);
+
+    let hello = server.client_hello().unwrap();
+    assert_eq!(hello.server_name.as_deref(), Some("foobar.com"));
+    assert_eq!(hello.alpn_protocols, [&b"h2"[..], b"http/1.1"]);
+    let suites = connector.config().crypto_provider().cipher_suites.iter();
+    let suites = suites.map(|suite| suite.suite()).collect::<Vec<_>>();
+    // Followed by the renegotiation SCSV
+    assert_eq!(hello.cipher_suites[..suites.len()], suites);
+    assert!(hello.named_groups.is_some());
+    assert!(hello.fingerprint.ends_with("-h2,http/1.1"));
+
+    // Not captured unless asked for
+    let (sconfig, _) = utils::make_configs();
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) = tokio::join!(
+        connector.connect(domain, cstream),
+        TlsAcceptor::from(sconfig).accept(sstream)
+    );
+    let (_client, server) = (client?, server?);
+    assert!(server.client_hello().is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn into_split() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, server) = (client?, server?);
+
+    let echo = tokio::spawn(async move {
+        let (mut reader, mut writer) = tokio::io::split(server);
+        copy(&mut reader, &mut writer).await?;
+        writer.shutdown().await
+    });
+
+    let (mut reader, mut writer) = client.into_split();
+    let data = vec![0x42; 64 * 1024];
+    let expected = data.clone();
+    let write = tokio::spawn(async move {
+        writer.write_all(&data).await?;
+        writer.flush().await?;
+        Ok::<_, io::Error>(writer)
+    });
+    let mut buf = vec![0; expected.len()];
+    reader.read_exact(&mut buf).await?;
+    assert_eq!(buf, expected);
+    assert!(reader.lock().get_ref().1.peer_certificates().is_some());
+
+    let mut writer = write.await??;
+    assert!(!writer.lock().get_ref().1.is_handshaking());
+    writer.shutdown().await?;
+    echo.await??;
+
+    let client = reader.reunite(writer).unwrap();
+    assert!(client.get_ref().1.alpn_protocol().is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn split_half_close() -> io::Result<()> {
+    use tokio_rustls::EofReason;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, server) = (client?, server?);
+
+    // The server answers once the client is done sending.
+    let server = tokio::spawn(async move {
+        let (mut reader, mut writer) = tokio_rustls::TlsStream::from(server).into_split();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await?;
+        assert_eq!(
+            reader.lock().read_eof_reason(),
+            Some(EofReason::CloseNotify)
+        );
+        writer.write_all(&buf).await?;
+        writer.shutdown().await
+    });
+
+    let (mut reader, mut writer) = client.into_split();
+    let read = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await?;
+        Ok::<_, io::Error>((reader, buf))
+    });
+    writer.write_all(b"request").await?;
+    writer.shutdown().await?;
+    // Shutting down again only retries shutting the IO down.
+    writer.shutdown().await?;
+    server.await??;
+
+    let (reader, buf) = read.await??;
+    assert_eq!(buf, b"request");
+    assert_eq!(
+        reader.lock().read_eof_reason(),
+        Some(EofReason::CloseNotify)
+    );
+    let client = reader.reunite(writer).unwrap();
+    assert_eq!(
+        client.connection_state(),
+        tokio_rustls::ConnectionState::Closed
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_with_and_accept_with() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) = tokio::join!(
+        connector.connect_with(domain, cstream, |conn| {
+            assert!(conn.is_handshaking());
+            conn.set_buffer_limit(Some(1024));
+        }),
+        acceptor.accept_with(sstream, |conn| {
+            assert!(conn.is_handshaking());
+            conn.set_buffer_limit(None);
+        })
+    );
+    let (mut client, mut server) = (client?, server?);
+
+    // The limits only bound buffered data; streams write through them.
+    let data = vec![0x42; 8 * 1024];
+    let write = async {
+        client.write_all(&data).await?;
+        client.flush().await
+    };
+    let (written, read) = tokio::join!(write, async {
+        let mut buf = vec![0; data.len()];
+        server.read_exact(&mut buf).await.map(|_| buf)
+    });
+    written?;
+    assert_eq!(read?, data);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn into_stream_with() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let server = async move {
+        let acceptor = LazyConfigAcceptor::new(rustls::server::Acceptor::default(), sstream);
+        let start = acceptor.await?;
+        let limit = match start.client_hello().server_name() {
+            Some("foobar.com") => Some(1024),
+            _ => None,
+        };
+        start
+            .into_stream_with(sconfig, |conn| {
+                assert!(conn.is_handshaking());
+                conn.set_buffer_limit(limit);
+            })
+            .await
+    };
+
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) = tokio::join!(connector.connect(domain, cstream), server);
+    let (mut client, mut server) = (client?, server?);
+
+    server.write_all(b"hello").await?;
+    server.flush().await?;
+    let mut buf = [0; 5];
+    client.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn buf_read() -> io::Result<()> {
+    use tokio::io::AsyncBufReadExt;
+    use tokio_rustls::EofReason;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    client.write_all(b"EHLO foobar.com\r\nQUIT\r\n").await?;
+    client.shutdown().await?;
+
+    // No `BufReader` needed for line-based protocols.
+    let mut line = String::new();
+    server.read_line(&mut line).await?;
+    assert_eq!(line, "EHLO foobar.com\r\n");
+    let mut lines = server.lines();
+    assert_eq!(lines.next_line().await?.as_deref(), Some("QUIT"));
+    assert_eq!(lines.next_line().await?, None);
+    let server = lines.into_inner();
+    assert_eq!(server.read_eof_reason(), Some(EofReason::CloseNotify));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_verifier() -> io::Result<()> {
+    use rustls::pki_types::{CertificateDer, ServerName};
+    use tokio_rustls::verify::{AsyncVerifier, VerifyFuture};
+
+    struct Revoked(Vec<CertificateDer<'static>>);
+
+    impl AsyncVerifier for Revoked {
+        fn verify<'a>(
+            &'a self,
+            certificates: &'a [CertificateDer<'static>],
+            server_name: Option<&'a ServerName<'static>>,
+        ) -> VerifyFuture<'a> {
+            Box::pin(async move {
+                // Stands in for a lookup over the network.
+                tokio::task::yield_now().await;
+                assert_eq!(
+                    server_name.map(|name| name.to_str()).as_deref(),
+                    Some("foobar.com")
+                );
+                match self.0.contains(&certificates[0]) {
+                    true => Err(rustls::Error::InvalidCertificate(
+                        rustls::CertificateError::Revoked,
+                    )),
+                    false => Ok(()),
+                }
+            })
+        }
+    }
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let end_entity = certs(&mut BufReader::new(Cursor::new(CERT)))
+        .next()
+        .unwrap()?;
+
+    for revoked in [vec![], vec![end_entity]] {
+        let connector =
+            TlsConnector::from(cconfig.clone()).async_verifier(Arc::new(Revoked(revoked.clone())));
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+
+        match client {
+            Ok(mut client) => {
+                assert!(revoked.is_empty());
+                let mut server = server?;
+                client.write_all(b"hello").await?;
+                client.flush().await?;
+                let mut buf = [0; 5];
+                server.read_exact(&mut buf).await?;
+                assert_eq!(&buf, b"hello");
+            }
+            Err(err) => {
+                assert!(!revoked.is_empty());
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resume_handshake() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let mut accept = acceptor.accept(sstream);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let mut connect = connector.connect(domain, cstream);
+
+    // Interrupt both sides mid-handshake, then pick up where they left off.
+    tokio::select! {
+        biased;
+        _ = &mut connect => panic!("handshake completed early"),
+        _ = &mut accept => panic!("handshake completed early"),
+        () = std::future::ready(()) => {}
+    }
+    assert!(accept
+        .session()
+        .map_or(false, |session| session.is_handshaking()));
+
+    let (client, server) = tokio::join!(connect, accept);
+    let (mut client, mut server) = (client?, server?);
+    client.write_all(b"hello").await?;
+    client.flush().await?;
+    let mut buf = [0; 5];
+    server.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn peek() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    client.write_all(b"PRI * HTTP/2.0\r\n").await?;
+    client.shutdown().await?;
+
+    // Sniff the protocol, then read it all as if nothing happened.
+    let mut start = [0; 3];
+    assert_eq!(server.peek(&mut start).await?, 3);
+    assert_eq!(&start, b"PRI");
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"PRI * HTTP/2.0\r\n");
+    assert_eq!(server.peek(&mut start).await?, 0);
+
+    Ok(())
+}
+
 // Include `utils` module
 include!("utils.rs");
+
+#[tokio::test]
+async fn alpn_acceptor() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let mut h2 = rustls::ServerConfig::clone(&sconfig);
+    h2.send_tls13_tickets = 0;
+    let acceptor = AlpnAcceptor::new(sconfig.clone())
+        .protocol("h2", Arc::new(h2))
+        .protocol("custom", sconfig);
+
+    for (offered, expected) in [
+        (vec![], None),
+        (vec![b"http/1.1".to_vec()], None),
+        (vec![b"custom".to_vec(), b"h2".to_vec()], Some(&b"h2"[..])),
+        (vec![b"custom".to_vec()], Some(&b"custom"[..])),
+    ] {
+        let mut cconfig = rustls::ClientConfig::clone(&cconfig);
+        cconfig.alpn_protocols = offered;
+        let connector = TlsConnector::from(Arc::new(cconfig));
+
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        let (client, server) = (client?, server?);
+
+        assert_eq!(server.get_ref().1.alpn_protocol(), expected);
+        assert_eq!(client.get_ref().1.alpn_protocol(), expected);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sni_acceptor() -> io::Result<()> {
+    use tokio_rustls::{SniAcceptor, SniError};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let wildcard = Arc::new(rustls::ServerConfig::clone(&sconfig));
+    let acceptor = SniAcceptor::new()
+        .host("FOOBAR.com", sconfig.clone())
+        .host("*.foobar.com", wildcard.clone());
+
+    let selected = |name| acceptor.select(name).unwrap();
+    assert!(Arc::ptr_eq(&selected(Some("foobar.com.")), &sconfig));
+    assert!(Arc::ptr_eq(&selected(Some("www.Foobar.com")), &wildcard));
+    let error = |name| acceptor.select(name).err();
+    let unknown = |name: &str| Some(SniError::UnknownServerName(name.into()));
+    assert_eq!(error(Some("a.www.foobar.com")), unknown("a.www.foobar.com"));
+    assert_eq!(error(Some("other.com")), unknown("other.com"));
+    assert_eq!(error(None), Some(SniError::NoServerName));
+
+    let fallback = acceptor.clone().fallback(wildcard.clone());
+    assert!(Arc::ptr_eq(&fallback.select(None).unwrap(), &wildcard));
+
+    let connector = TlsConnector::from(cconfig);
+    for (name, ok) in [("foobar.com", true), ("testserver.com", false)] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from(name).unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        assert_eq!(client.is_ok(), ok);
+        match server {
+            Ok(server) => assert_eq!(server.get_ref().1.server_name(), Some(name)),
+            Err(err) => {
+                let err = err.into_inner().unwrap().downcast::<SniError>().unwrap();
+                assert_eq!(*err, SniError::UnknownServerName(name.into()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn acme_acceptor() -> io::Result<()> {
+    use rustls::sign::CertifiedKey;
+    use tokio_rustls::verify::Pinned;
+    use tokio_rustls::{AcmeAcceptor, AcmeChallenges, AlpnProtocol};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+
+    // rcgen can make proper challenge certificates; any will do for the acceptor.
+    let challenge = rcgen::generate_simple_self_signed(vec!["foobar.com".into()]).unwrap();
+    let challenge_cert = challenge.cert.der().clone();
+    let challenge_key = pki_types::PrivatePkcs8KeyDer::from(challenge.key_pair.serialize_der());
+    let challenge_key = provider
+        .key_provider
+        .load_private_key(challenge_key.into())
+        .unwrap();
+    let challenges = Arc::new(AcmeChallenges::new());
+    challenges.insert(
+        "FOOBAR.com",
+        Arc::new(CertifiedKey::new(
+            vec![challenge_cert.clone()],
+            challenge_key,
+        )),
+    );
+    let acceptor = AcmeAcceptor::new(sconfig, challenges.clone());
+
+    let validator = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(Pinned::new(
+            vec![challenge_cert.clone()],
+            &provider,
+        )))
+        .with_no_client_auth();
+    let validator =
+        TlsConnector::from(Arc::new(validator)).alpn_protocols(&[AlpnProtocol::AcmeTls1]);
+
+    // Validation servers get the challenge certificate, and the connection is closed.
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(validator.connect(domain, cstream), acceptor.accept(sstream));
+    let mut client = client?;
+    assert!(server?.is_none());
+    assert_eq!(client.alpn_protocol(), Some(AlpnProtocol::AcmeTls1));
+    assert_eq!(client.peer_certificates().unwrap(), [challenge_cert]);
+    assert_eq!(client.read(&mut [0; 1]).await?, 0);
+
+    // Other clients get the server config.
+    let connector = TlsConnector::from(cconfig);
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    client?;
+    assert!(server?.is_some());
+
+    challenges.remove("foobar.com");
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(validator.connect(domain, cstream), acceptor.accept(sstream));
+    assert!(client.is_err());
+    assert!(server.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn proxy_protocol() -> io::Result<()> {
+    use tokio_rustls::{ProxyHeader, ProxyProtocolAcceptor};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = ProxyProtocolAcceptor::new(TlsAcceptor::from(sconfig));
+    let connector = TlsConnector::from(cconfig);
+
+    let v4 = ProxyHeader {
+        source: "192.0.2.1:56324".parse().unwrap(),
+        destination: "198.51.100.7:443".parse().unwrap(),
+    };
+    let v6 = ProxyHeader {
+        source: "[2001:db8::1]:56324".parse().unwrap(),
+        destination: "[2001:db8::7]:443".parse().unwrap(),
+    };
+    let mut v2 = b"\r\n\r\n\0\r\nQUIT\n\x21\x21\x00\x24".to_vec();
+    v2.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    v2.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7]);
+    v2.extend_from_slice(&[0xdc, 0x04, 0x01, 0xbb]);
+
+    for (header, expected) in [
+        (
+            &b"PROXY TCP4 192.0.2.1 198.51.100.7 56324 443\r\n"[..],
+            Some(v4),
+        ),
+        (&b"PROXY UNKNOWN\r\n"[..], None),
+        (&v2[..], Some(v6)),
+        // A v2 health check from the proxy itself.
+        (&b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00"[..], None),
+    ] {
+        let (mut cstream, sstream) = tokio::io::duplex(4096);
+        cstream.write_all(header).await?;
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        client?;
+        assert_eq!(server?.1, expected);
+    }
+
+    // Clients can't reach the server without going through the proxy.
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    assert!(client.is_err());
+    assert_eq!(server.unwrap_err().kind(), ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn starttls_upgrade() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (mut cstream, mut sstream) = tokio::io::duplex(4096);
+    let client = async {
+        cstream.write_all(b"STARTTLS\r\n").await?;
+        let mut reply = [0; 10];
+        cstream.read_exact(&mut reply).await?;
+        assert_eq!(&reply, b"220 Ready\n");
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let mut stream = connector.upgrade(domain, cstream, &[]).await?;
+        stream.write_all(b"EHLO").await?;
+        stream.shutdown().await?;
+        io::Result::Ok(stream)
+    };
+    let server = async {
+        let mut command = [0; 10];
+        sstream.read_exact(&mut command).await?;
+        assert_eq!(&command, b"STARTTLS\r\n");
+        sstream.write_all(b"220 Ready\n").await?;
+        let mut stream = acceptor.upgrade(sstream, &[]).await?;
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await?;
+        assert_eq!(buf, b"EHLO");
+        io::Result::Ok(stream)
+    };
+    let (client, server) = tokio::join!(client, server);
+    client?;
+    server?;
+
+    // A command pipelined after STARTTLS would otherwise be lost, or taken for part of
+    // the TLS session.
+    let (_, sstream) = tokio::io::duplex(4096);
+    let err = acceptor
+        .upgrade(sstream, b"RCPT TO:<victim>")
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dual_cert_resolver() -> io::Result<()> {
+    use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+    use rustls::sign::CertifiedKey;
+    use rustls::SignatureScheme;
+    use tokio_rustls::verify::Pinned;
+    use tokio_rustls::DualCertResolver;
+
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+
+    let ecdsa = rcgen::generate_simple_self_signed(vec!["foobar.com".into()]).unwrap();
+    let ecdsa_cert = ecdsa.cert.der().clone();
+    let ecdsa_key = pki_types::PrivatePkcs8KeyDer::from(ecdsa.key_pair.serialize_der());
+    let ecdsa_key = provider
+        .key_provider
+        .load_private_key(ecdsa_key.into())
+        .unwrap();
+
+    let rsa_certs =
+        certs(&mut BufReader::new(Cursor::new(CERT))).collect::<io::Result<Vec<_>>>()?;
+    let rsa_key = rsa_private_keys(&mut BufReader::new(Cursor::new(RSA)))
+        .next()
+        .unwrap()?;
+    let rsa_key = provider
+        .key_provider
+        .load_private_key(rsa_key.into())
+        .unwrap();
+
+    let resolver = DualCertResolver::new(
+        Arc::new(CertifiedKey::new(vec![ecdsa_cert.clone()], ecdsa_key)),
+        Arc::new(CertifiedKey::new(rsa_certs.clone(), rsa_key)),
+    );
+    let sconfig = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    let acceptor = TlsAcceptor::from(Arc::new(sconfig));
+
+    // A legacy client that only offers RSA signature schemes.
+    let rsa_only = provider
+        .signature_verification_algorithms
+        .mapping
+        .iter()
+        .filter(|(scheme, _)| {
+            !matches!(
+                scheme,
+                SignatureScheme::ECDSA_NISTP256_SHA256
+                    | SignatureScheme::ECDSA_NISTP384_SHA384
+                    | SignatureScheme::ECDSA_NISTP521_SHA512
+                    | SignatureScheme::ED25519
+            )
+        })
+        .copied()
+        .collect::<Vec<_>>();
+    let legacy = CryptoProvider {
+        signature_verification_algorithms: WebPkiSupportedAlgorithms {
+            all: provider.signature_verification_algorithms.all,
+            mapping: Box::leak(rsa_only.into_boxed_slice()),
+        },
+        ..CryptoProvider::clone(&provider)
+    };
+
+    let pins = vec![ecdsa_cert.clone(), rsa_certs[0].clone()];
+    for (provider, expected) in [(&*provider, &ecdsa_cert), (&legacy, &rsa_certs[0])] {
+        let cconfig = rustls::ClientConfig::builder_with_provider(Arc::new(provider.clone()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Pinned::new(pins.clone(), provider)))
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(cconfig));
+
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        let (client, _server) = (client?, server?);
+
+        let peer_certs = client.get_ref().1.peer_certificates().unwrap();
+        assert_eq!(&peer_certs[0], expected);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn verifier_combinators() -> io::Result<()> {
+    use rustls::client::WebPkiServerVerifier;
+    use tokio_rustls::verify::{AllOf, AnyOf, Pinned};
+
+    async fn connect(
+        connector: &TlsConnector,
+        verifier: impl rustls::client::danger::ServerCertVerifier + 'static,
+    ) -> io::Result<()> {
+        let (sconfig, _) = utils::make_configs();
+        let acceptor = TlsAcceptor::from(sconfig);
+        let connector = connector.clone().verifier(Arc::new(verifier));
+
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        server?;
+        client.map(drop)
+    }
+
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+    let connector = TlsConnector::from(cconfig);
+    let mut roots = rustls::RootCertStore::empty();
+    let chain = certs(&mut BufReader::new(Cursor::new(CHAIN))).collect::<io::Result<Vec<_>>>()?;
+    roots.add_parsable_certificates(chain.clone());
+    let webpki = || {
+        WebPkiServerVerifier::builder(Arc::new(roots.clone()))
+            .build()
+            .unwrap()
+    };
+    let end = certs(&mut BufReader::new(Cursor::new(CERT)))
+        .next()
+        .unwrap()?;
+    let pin = |cert: &pki_types::CertificateDer<'static>| {
+        Arc::new(Pinned::new(vec![cert.clone()], &provider))
+    };
+
+    let ca = chain.last().unwrap();
+
+    connect(&connector, AllOf::new(vec![webpki(), pin(&end)])).await?;
+    let err = connect(&connector, AllOf::new(vec![webpki(), pin(ca)])).await;
+    assert!(err.is_err());
+    connect(&connector, AnyOf::new(vec![pin(ca), webpki()])).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn copy_bidirectional() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    // The server proxies to an echo backend; the client half-closes after its request.
+    let (mut proxy, mut backend) = tokio::io::duplex(4096);
+    let request = vec![0x42; 100 * 1024];
+    let (copied, echoed, response) = tokio::join!(
+        tokio_rustls::copy_bidirectional(&mut server, &mut proxy),
+        async {
+            let (mut reader, mut writer) = split(&mut backend);
+            copy(&mut reader, &mut writer).await?;
+            writer.shutdown().await
+        },
+        async {
+            let (mut reader, mut writer) = split(&mut client);
+            let mut response = Vec::new();
+            let (written, read) = tokio::join!(
+                async {
+                    writer.write_all(&request).await?;
+                    writer.shutdown().await
+                },
+                reader.read_to_end(&mut response)
+            );
+            written.and(read).map(|_| response)
+        }
+    );
+    echoed?;
+
+    let len = request.len() as u64;
+    assert_eq!(copied?, (len, len));
+    assert_eq!(response?, request);
+
+    Ok(())
+}
+
+#[cfg(feature = "bytes")]
+#[tokio::test]
+async fn write_all_buf() -> io::Result<()> {
+    use bytes::{Buf, Bytes};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    let large = Bytes::from(vec![0x42; 64 * 1024]);
+    let mut buf = Bytes::from_static(b"hello ").chain(large.clone());
+    let (written, read) = tokio::join!(
+        async {
+            client.write_all_buf(&mut buf).await?;
+            client.shutdown().await
+        },
+        async {
+            let mut received = Vec::new();
+            server.read_to_end(&mut received).await.map(|_| received)
+        }
+    );
+    written?;
+
+    let received = read?;
+    assert!(!buf.has_remaining());
+    assert_eq!(&received[..6], b"hello ");
+    assert_eq!(&received[6..], &large[..]);
+
+    Ok(())
+}
+
+#[cfg(feature = "bytes")]
+#[tokio::test]
+async fn write_all_buf_buffer_limit() -> io::Result<()> {
+    use bytes::Bytes;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, _server) = (client?, server?);
+
+    // rustls takes nothing, so writing fails instead of retrying forever.
+    client.set_buffer_limit(Some(0));
+    let mut buf = Bytes::from_static(b"hello");
+    let err = client.write_all_buf(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+
+    Ok(())
+}
+
+#[cfg(feature = "bytes")]
+#[tokio::test]
+async fn read_buf() -> io::Result<()> {
+    use bytes::BytesMut;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    let data = vec![0x42; 64 * 1024];
+    let (written, read) = tokio::join!(
+        async {
+            server.write_all(&data).await?;
+            server.shutdown().await
+        },
+        async {
+            let mut received = BytesMut::with_capacity(1024);
+            while client.read_buf(&mut received).await? != 0 {
+                received.reserve(1024);
+            }
+            Ok::<_, io::Error>(received)
+        }
+    );
+    written?;
+    assert_eq!(&read?[..], &data[..]);
+
+    Ok(())
+}
+
+#[cfg(feature = "transcript")]
+#[tokio::test]
+async fn handshake_transcript() -> io::Result<()> {
+    use std::sync::Mutex;
+
+    use rustls::HandshakeType;
+    use tokio_rustls::transcript::{Direction, Event, Transcript};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let cstream = Transcript::new(cstream, move |event: Event<'_>| {
+        let event = match event {
+            Event::Handshake { direction, typ, .. } => (direction, Some(typ)),
+            Event::Alert { direction, .. } => (direction, None),
+            Event::Encrypted { direction } => (direction, None),
+        };
+        recorded.lock().unwrap().push(event);
+    });
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (_client, _server) = (client?, server?);
+
+    let events = events.lock().unwrap();
+    assert_eq!(
+        events[..2],
+        [
+            (Direction::Sent, Some(HandshakeType::ClientHello)),
+            (Direction::Received, Some(HandshakeType::ServerHello)),
+        ]
+    );
+    assert!(events.contains(&(Direction::Received, None)));
+
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn sync_tls_stream() -> io::Result<()> {
+    use std::io::{Read, Write};
+
+    use tokio_rustls::SyncTlsStream;
+
+    let runtime = runtime::Runtime::new()?;
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (client, server) = runtime.block_on(async {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream))
+    });
+    let (client, server) = (client?, server?);
+
+    // Echo on the async side, talk on the blocking side.
+    let echo = runtime.spawn(async move {
+        let (mut reader, mut writer) = split(server);
+        copy(&mut reader, &mut writer).await
+    });
+
+    let mut client = SyncTlsStream::new(client, runtime.handle().clone());
+    client.write_all(b"hello blocking world")?;
+    client.flush()?;
+
+    let mut buf = [0; 20];
+    client.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"hello blocking world");
+
+    drop(client);
+    assert!(runtime.block_on(echo).is_ok());
+    Ok(())
+}
+
+#[cfg(feature = "listener")]
+#[tokio::test]
+async fn lazy_tls_listener() -> io::Result<()> {
+    use tokio_rustls::LazyTlsListener;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mut listener =
+        LazyTlsListener::new(listener).client_hello_timeout(Duration::from_millis(50));
+    let addr = listener.local_addr()?;
+
+    // A connection that never sends a ClientHello times out.
+    let idle = TcpStream::connect(addr).await?;
+    let err = listener.accept().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    drop(idle);
+
+    let client = tokio::spawn(async move {
+        let stream = TcpStream::connect(addr).await?;
+        let peer = stream.local_addr()?;
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let mut stream = connector.connect(domain, stream).await?;
+        stream.write_all(b"hello").await?;
+        stream.shutdown().await?;
+        Ok::<_, io::Error>(peer)
+    });
+
+    let (start, peer) = listener.accept().await?;
+    assert_eq!(start.client_hello().server_name(), Some("foobar.com"));
+    let mut stream = start.into_stream(sconfig).await?;
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"hello");
+    assert_eq!(client.await.unwrap()?, peer);
+
+    Ok(())
+}
+
+#[cfg(feature = "listener")]
+#[tokio::test]
+async fn tls_listener() -> io::Result<()> {
+    use futures_util::StreamExt;
+    use tokio_rustls::TlsListener;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mut listener = TlsListener::new(listener, TlsAcceptor::from(sconfig))
+        .handshake_timeout(Duration::from_millis(50));
+    let addr = listener.local_addr()?;
+
+    // A stalled handshake neither blocks the next one nor outlives the timeout.
+    let idle = TcpStream::connect(addr).await?;
+    let client = tokio::spawn(async move {
+        let stream = TcpStream::connect(addr).await?;
+        let peer = stream.local_addr()?;
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let mut stream = connector.connect(domain, stream).await?;
+        stream.write_all(b"hello").await?;
+        stream.shutdown().await?;
+        Ok::<_, io::Error>(peer)
+    });
+
+    let mut conn = listener.next().await.unwrap()?;
+    assert!(!conn.stream.get_ref().1.is_handshaking());
+    assert_eq!(conn.server_name.as_deref(), Some("foobar.com"));
+    assert_eq!(conn.alpn_protocol, None);
+    assert_eq!(conn.local_addr, addr);
+    let mut buf = Vec::new();
+    conn.stream.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"hello");
+    assert_eq!(client.await.unwrap()?, conn.remote_addr);
+
+    let err = listener.accept().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    drop(idle);
+
+    Ok(())
+}
+
+#[cfg(feature = "listener")]
+#[tokio::test]
+async fn tls_listener_error_policy() -> io::Result<()> {
+    use futures_util::StreamExt;
+    use tokio_rustls::{ErrorPolicy, TlsListener};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let acceptor = TlsAcceptor::from(sconfig);
+
+    // A client that fails its handshake.
+    let fail = |addr| async move {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(&[0xff; 16]).await
+    };
+
+    // Failures go to the callback, and the next connection is still yielded.
+    let (failed, mut failures) = tokio::sync::mpsc::unbounded_channel();
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mut listener = TlsListener::new(listener, acceptor.clone()).error_policy(
+        ErrorPolicy::Callback(Arc::new(move |err| {
+            let _ = failed.send(err);
+        })),
+    );
+    let addr = listener.local_addr()?;
+    let accept = tokio::spawn(async move { listener.accept().await });
+    fail(addr).await?;
+    assert_eq!(
+        failures.recv().await.unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+
+    let stream = TcpStream::connect(addr).await?;
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, conn) = tokio::join!(connector.connect(domain, stream), accept);
+    let (_client, conn) = (client?, conn.unwrap()?);
+    assert_eq!(conn.server_name.as_deref(), Some("foobar.com"));
+
+    // The first failure ends a terminating listener.
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mut listener = TlsListener::new(listener, acceptor).error_policy(ErrorPolicy::Terminate);
+    fail(listener.local_addr()?).await?;
+    assert_eq!(
+        listener.next().await.unwrap().unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert!(listener.next().await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_eof_reason() -> io::Result<()> {
+    use tokio_rustls::EofReason;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+    assert_eq!(server.read_eof_reason(), None);
+
+    // A clean shutdown sends close_notify.
+    client.write_all(b"clean").await?;
+    client.shutdown().await?;
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"clean");
+    assert_eq!(server.read_eof_reason(), Some(EofReason::CloseNotify));
+
+    // Closing the transport directly truncates the stream.
+    server.write_all(b"truncated").await?;
+    server.flush().await?;
+    drop(server);
+    let mut buf = Vec::new();
+    let err = client.read_to_end(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(buf, b"truncated");
+    assert_eq!(client.read_eof_reason(), Some(EofReason::Transport));
+
+    // As on the server, the read side is shut after the unclean EOF: reading again
+    // reports EOF instead of the error.
+    assert_eq!(client.read(&mut [0; 8]).await?, 0);
+    assert_eq!(client.read_eof_reason(), Some(EofReason::Transport));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lenient_eof() -> io::Result<()> {
+    use tokio_rustls::EofReason;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).lenient_eof(true);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    // The server was accepted leniently, the client opts in after connecting.
+    client.set_lenient_eof(true);
+    server.write_all(b"truncated").await?;
+    server.flush().await?;
+    drop(server);
+    let mut buf = Vec::new();
+    client.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"truncated");
+    assert_eq!(client.read_eof_reason(), Some(EofReason::Transport));
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, mut server) = (client?, server?);
+
+    drop(client);
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).await?;
+    assert!(buf.is_empty());
+    assert_eq!(server.read_eof_reason(), Some(EofReason::Transport));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn connection_state() -> io::Result<()> {
+    use tokio_rustls::ConnectionState;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+    assert_eq!(client.connection_state(), ConnectionState::Open);
+    assert!(!client.is_handshaking() && !server.is_handshaking());
+    assert!(!client.wants_write());
+
+    client.shutdown().await?;
+    assert_eq!(client.connection_state(), ConnectionState::WriteClosed);
+    assert!(client.connection_state().is_readable());
+    assert!(!client.connection_state().is_writable());
+    server.read_to_end(&mut Vec::new()).await?;
+    assert_eq!(server.connection_state(), ConnectionState::ReadClosed);
+
+    server.shutdown().await?;
+    assert_eq!(server.connection_state(), ConnectionState::Closed);
+    client.read_to_end(&mut Vec::new()).await?;
+    assert_eq!(client.connection_state(), ConnectionState::Closed);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn half_close() -> io::Result<()> {
+    use tokio_rustls::EofReason;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    client.write_all(b"request").await?;
+    client.shutdown_write().await?;
+    let err = client.write_all(b"more").await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+    // The server reads the whole request, and still gets to answer it.
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"request");
+    assert_eq!(server.read_eof_reason(), Some(EofReason::CloseNotify));
+    server.write_all(b"response").await?;
+    server.shutdown_write().await?;
+
+    let mut buf = Vec::new();
+    client.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"response");
+    assert_eq!(client.read_eof_reason(), Some(EofReason::CloseNotify));
+
+    Ok(())
+}
+
+/// A server requiring client certificates issued by a fresh CA, and a way to issue them.
+fn client_auth_setup() -> (TlsAcceptor, impl Fn(&str) -> (String, String)) {
+    use rustls::server::WebPkiClientVerifier;
+
+    let ca_key = rcgen::KeyPair::generate().unwrap();
+    let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(ca.der().clone()).unwrap();
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .build()
+        .unwrap();
+    let cert = certs(&mut BufReader::new(Cursor::new(CERT)))
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    let key = rsa_private_keys(&mut BufReader::new(Cursor::new(RSA)))
+        .next()
+        .unwrap()
+        .unwrap();
+    let sconfig = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(cert, key.into())
+        .unwrap();
+
+    let issue = move |name: &str| {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec![name.to_owned()])
+            .unwrap()
+            .signed_by(&key, &ca, &ca_key)
+            .unwrap();
+        (cert.pem(), key.serialize_pem())
+    };
+    (TlsAcceptor::from(Arc::new(sconfig)), issue)
+}
+
+async fn client_cert_seen_by(
+    acceptor: &TlsAcceptor,
+    connector: &TlsConnector,
+) -> io::Result<pki_types::CertificateDer<'static>> {
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        futures_util::future::join(connector.connect(domain, cstream), acceptor.accept(sstream))
+            .await;
+    let (_client, server) = (client?, server?);
+    Ok(server.peer_certificates().unwrap()[0].clone())
+}
+
+#[tokio::test]
+async fn peer_certificates() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, server) = (client?, server?);
+
+    let chain = certs(&mut BufReader::new(Cursor::new(CERT))).collect::<io::Result<Vec<_>>>()?;
+    assert_eq!(client.peer_certificates(), Some(&chain[..]));
+    // The server didn't ask for a client certificate.
+    assert_eq!(server.peer_certificates(), None);
+
+    let client = tokio_rustls::TlsStream::from(client);
+    assert_eq!(client.peer_certificates(), Some(&chain[..]));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn negotiated_parameters() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, server) = (client?, server?);
+
+    assert_eq!(
+        client.protocol_version(),
+        Some(rustls::ProtocolVersion::TLSv1_3)
+    );
+    assert_eq!(server.protocol_version(), client.protocol_version());
+    let suite = client.negotiated_cipher_suite().unwrap();
+    assert_eq!(suite.version(), &rustls::version::TLS13);
+    assert_eq!(server.negotiated_cipher_suite(), Some(suite));
+    let group = client.negotiated_key_exchange_group().unwrap().name();
+    assert_eq!(
+        server.negotiated_key_exchange_group().map(|g| g.name()),
+        Some(group)
+    );
+
+    let server = tokio_rustls::TlsStream::from(server);
+    assert_eq!(server.negotiated_cipher_suite(), Some(suite));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn export_keying_material() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, server) = (client?, server?);
+
+    let label = b"EXPERIMENTAL tokio-rustls";
+    let exported = client.export_keying_material(label, Some(b"context"), 32)?;
+    assert_eq!(exported.len(), 32);
+    assert_eq!(
+        server.export_keying_material(label, Some(b"context"), 32)?,
+        exported
+    );
+    assert_ne!(client.export_keying_material(label, None, 32)?, exported);
+
+    let server = tokio_rustls::TlsStream::from(server);
+    assert_eq!(
+        server.export_keying_material(label, Some(b"context"), 32)?,
+        exported
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refresh_traffic_keys() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    for round in 0..3u8 {
+        // A flush on its own sends the key update.
+        client.refresh_traffic_keys()?;
+        client.flush().await?;
+        client.write_all(&[round]).await?;
+        client.flush().await?;
+        assert_eq!(server.read_u8().await?, round);
+
+        server.refresh_traffic_keys()?;
+        server.write_all(&[round]).await?;
+        server.flush().await?;
+        assert_eq!(client.read_u8().await?, round);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reloading_client_cert() -> io::Result<()> {
+    use tokio_rustls::ReloadingClientCert;
+
+    let (acceptor, issue) = client_auth_setup();
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+    let identity = Arc::new(ReloadingClientCert::default());
+    let connector = TlsConnector::from(cconfig).client_identity(identity.clone());
+
+    let dir = std::env::temp_dir().join(format!("tokio-rustls-identity-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let (cert_path, key_path) = (dir.join("client.pem"), dir.join("client.key"));
+
+    for name in ["client-a", "client-b"] {
+        let (cert, key) = issue(name);
+        std::fs::write(&cert_path, &cert)?;
+        std::fs::write(&key_path, key)?;
+        identity.load_pem_files(&cert_path, &key_path, &provider)?;
+
+        let seen = client_cert_seen_by(&acceptor, &connector).await?;
+        assert_eq!(
+            seen.as_ref(),
+            rustls_pemfile::certs(&mut cert.as_bytes())
+                .next()
+                .unwrap()?
+                .as_ref()
+        );
+    }
+
+    std::fs::remove_dir_all(&dir)
+}
+
+#[cfg(feature = "reload")]
+#[tokio::test]
+async fn watch_client_cert_files() -> io::Result<()> {
+    use tokio_rustls::ReloadingClientCert;
+
+    let (acceptor, issue) = client_auth_setup();
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+    let identity = Arc::new(ReloadingClientCert::default());
+    let connector = TlsConnector::from(cconfig).client_identity(identity.clone());
+
+    let dir = std::env::temp_dir().join(format!("tokio-rustls-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let (cert_path, key_path) = (dir.join("client.pem"), dir.join("client.key"));
+    let (cert, key) = issue("client-a");
+    std::fs::write(&cert_path, cert)?;
+    std::fs::write(&key_path, key)?;
+
+    let watcher = identity.clone().watch_files(
+        cert_path.clone(),
+        key_path.clone(),
+        provider,
+        Duration::from_millis(10),
+    );
+    while identity.current().is_none() {
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    let first = client_cert_seen_by(&acceptor, &connector).await?;
+
+    let (cert, key) = issue("client-b");
+    std::fs::write(&cert_path, cert)?;
+    std::fs::write(&key_path, key)?;
+    let rotated = time::timeout(Duration::from_secs(5), async {
+        loop {
+            let seen = client_cert_seen_by(&acceptor, &connector).await?;
+            if seen != first {
+                return Ok::<_, io::Error>(());
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+    rotated.await??;
+
+    watcher.abort();
+    std::fs::remove_dir_all(&dir)
+}
+
+#[cfg(feature = "x509")]
+#[tokio::test]
+async fn client_identity() -> io::Result<()> {
+    use tokio_rustls::ReloadingClientCert;
+
+    let (acceptor, issue) = client_auth_setup();
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+
+    let (cert, key) = issue("client.example");
+    let cert = rustls_pemfile::certs(&mut cert.as_bytes())
+        .next()
+        .unwrap()?;
+    let key = rustls_pemfile::private_key(&mut key.as_bytes())?.unwrap();
+    let key = provider.key_provider.load_private_key(key).unwrap();
+    let identity = rustls::sign::CertifiedKey::new(vec![cert], key);
+    let connector = TlsConnector::from(cconfig)
+        .client_identity(Arc::new(ReloadingClientCert::new(Arc::new(identity))));
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (_client, server) = (client?, server?);
+
+    let identity = server.client_identity()?.unwrap();
+    assert_eq!(identity.subject, "CN=rcgen self signed cert");
+    assert_eq!(identity.sans, ["DNS:client.example"]);
+    assert_eq!(identity.spki_fingerprint.len(), 64);
+    assert!(identity.not_after > identity.not_before);
+
+    Ok(())
+}
+
+#[cfg(feature = "x509")]
+#[tokio::test]
+async fn peer_certificate_metadata() -> io::Result<()> {
+    use tokio_rustls::{PeerCertificate, ReloadingClientCert};
+
+    let (acceptor, issue) = client_auth_setup();
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+
+    let (cert, key) = issue("client.example");
+    let cert = rustls_pemfile::certs(&mut cert.as_bytes())
+        .next()
+        .unwrap()?;
+    let key = rustls_pemfile::private_key(&mut key.as_bytes())?.unwrap();
+    let key = provider.key_provider.load_private_key(key).unwrap();
+    let identity = rustls::sign::CertifiedKey::new(vec![cert], key);
+    let connector = TlsConnector::from(cconfig)
+        .client_identity(Arc::new(ReloadingClientCert::new(Arc::new(identity))));
+
+    let seen = client_cert_seen_by(&acceptor, &connector).await?;
+    let info = PeerCertificate::parse(&seen, true)?;
+    assert_eq!(info.subject, "CN=rcgen self signed cert");
+    assert_eq!(info.sans, ["DNS:client.example"]);
+    assert_eq!(info.fingerprint.len(), 64);
+    assert!(info.not_before < info.not_after);
+
+    assert_eq!(info.spki_fingerprint.len(), 64);
+    assert_ne!(info.spki_fingerprint, info.fingerprint);
+
+    let headers = info.headers();
+    assert_eq!(headers[0], ("X-SSL-Client-Verify", "SUCCESS".to_owned()));
+    assert_eq!(
+        headers[3],
+        ("X-SSL-Client-SAN", "DNS:client.example".to_owned())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replace_io() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    client.write_all(b"before").await?;
+    client.flush().await?;
+    let mut buf = [0; 6];
+    server.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"before");
+
+    // Move both ends of the session onto a new transport.
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let old_client = client.replace_io(cstream);
+    let old_server = server.replace_io(sstream);
+    drop((old_client, old_server));
+
+    client.write_all(b"after").await?;
+    client.shutdown().await?;
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"after");
+
+    Ok(())
+}
+
+#[cfg(feature = "fuzzing")]
+#[test]
+fn fuzz_entry_points() {
+    use tokio_rustls::fuzz;
+
+    let (sconfig, cconfig) = utils::make_configs();
+
+    // A genuine ClientHello, served in a few chunk sizes and truncated at every length.
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let mut hello = Vec::new();
+    rustls::ClientConnection::new(cconfig.clone(), domain)
+        .unwrap()
+        .write_tls(&mut hello)
+        .unwrap();
+
+    for chunk in [0, 7, 255] {
+        let mut data = vec![chunk];
+        data.extend_from_slice(&hello);
+        for len in (0..=data.len()).step_by(13) {
+            fuzz::server(sconfig.clone(), &data[..len]);
+            fuzz::lazy_server(sconfig.clone(), &data[..len]);
+        }
+    }
+
+    for data in [
+        &b""[..],
+        b"\x00",
+        b"\xff\x16\x03\x01\x00\x02\x02\x00",
+        &[0x42; 64],
+    ] {
+        fuzz::server(sconfig.clone(), data);
+        fuzz::lazy_server(sconfig.clone(), data);
+        fuzz::client(cconfig.clone(), data);
+    }
+}
+
+#[tokio::test]
+async fn audit_events() -> io::Result<()> {
+    use std::sync::Mutex;
+
+    use rustls::crypto::CryptoProvider;
+    use rustls::NamedGroup;
+    use tokio_rustls::AuditEvent;
+
+    fn recorder() -> (
+        Arc<Mutex<Vec<String>>>,
+        impl Fn(&AuditEvent<'_>) + Send + Sync + 'static,
+    ) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let hook = move |event: &AuditEvent<'_>| {
+            let event = format!("{:?}: {}", event.side, event.kind);
+            sink.lock().unwrap().push(event);
+        };
+        (events, hook)
+    }
+
+    async fn handshake(acceptor: &TlsAcceptor, connector: &TlsConnector) {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let _ = futures_util::future::join(
+            connector.connect(domain, cstream),
+            acceptor.accept(sstream),
+        )
+        .await;
+    }
+
+    // The server requires a client certificate, which the client does not have.
+    let (acceptor, _) = client_auth_setup();
+    let (_, cconfig) = utils::make_configs();
+    let (server_events, hook) = recorder();
+    let acceptor = acceptor.audit(hook);
+    handshake(&acceptor, &TlsConnector::from(cconfig.clone())).await;
+    assert_eq!(
+        *server_events.lock().unwrap(),
+        [
+            "Server: client auth failed: peer sent no certificates",
+            "Server: sent fatal alert: peer sent no certificates",
+        ]
+    );
+
+    // The client does not trust the server certificate.
+    let (sconfig, _) = utils::make_configs();
+    let config = ClientConfig::builder()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    let (client_events, hook) = recorder();
+    let connector = TlsConnector::from(Arc::new(config)).audit(hook);
+    handshake(&TlsAcceptor::from(sconfig.clone()), &connector).await;
+    assert_eq!(
+        *client_events.lock().unwrap(),
+        [
+            "Client: certificate rejected: UnknownIssuer",
+            "Client: sent fatal alert UnknownCA",
+        ]
+    );
+
+    // The server only supports a key exchange group the client sends no key share for.
+    let provider = sconfig.crypto_provider();
+    let provider = CryptoProvider {
+        kx_groups: provider
+            .kx_groups
+            .iter()
+            .copied()
+            .filter(|group| group.name() == NamedGroup::secp384r1)
+            .collect(),
+        ..CryptoProvider::clone(provider)
+    };
+    let cert = certs(&mut BufReader::new(Cursor::new(CERT))).collect::<io::Result<Vec<_>>>()?;
+    let key = rsa_private_keys(&mut BufReader::new(Cursor::new(RSA)))
+        .next()
+        .unwrap()?;
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(provider))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(cert, key.into())
+        .unwrap();
+    let (server_events, hook) = recorder();
+    let acceptor = TlsAcceptor::from(Arc::new(config)).audit(hook);
+    handshake(&acceptor, &TlsConnector::from(cconfig)).await;
+    assert_eq!(
+        *server_events.lock().unwrap(),
+        ["Server: HelloRetryRequest"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn acceptor_stats() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+
+    let (_cstream, sstream) = tokio::io::duplex(4096);
+    let pending = acceptor.accept(sstream);
+    assert_eq!(acceptor.stats().handshaking, 1);
+    drop(pending);
+    assert_eq!(acceptor.stats().handshaking, 0);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let (client, server) = tokio::join!(
+        connector.connect(domain.clone(), cstream),
+        acceptor.accept(sstream)
+    );
+    let (_client, server) = (client?, server?);
+    let stats = acceptor.stats();
+    assert_eq!((stats.active, stats.handshaking), (1, 0));
+    assert_eq!((stats.accepted, stats.failed), (1, 0));
+    assert!(stats.accept_rate() > 0.0);
+    drop(server);
+    assert_eq!(acceptor.stats().active, 0);
+
+    // A client that does not trust the server aborts the handshake.
+    let config = ClientConfig::builder()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let (client, server) = tokio::join!(
+        TlsConnector::from(Arc::new(config)).connect(domain, cstream),
+        acceptor.accept(sstream)
+    );
+    assert!(client.is_err() && server.is_err());
+    let stats = acceptor.stats();
+    assert_eq!((stats.active, stats.handshaking), (0, 0));
+    assert_eq!((stats.accepted, stats.failed), (1, 1));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn config_accessors() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig.clone());
+    let connector = TlsConnector::from(cconfig.clone());
+    assert!(Arc::ptr_eq(&acceptor.config(), &sconfig));
+    assert!(Arc::ptr_eq(connector.config(), &cconfig));
+
+    let acceptor = acceptor.with_config(|config| config.alpn_protocols = vec![b"h2".to_vec()]);
+    let connector = connector.with_config(|config| config.alpn_protocols = vec![b"h2".to_vec()]);
+    assert!(sconfig.alpn_protocols.is_empty());
+    assert_eq!(acceptor.config().alpn_protocols, [b"h2"]);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    assert_eq!(client?.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+    assert_eq!(server?.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn swap_config() -> io::Result<()> {
+    use rustls::server::ResolvesServerCertUsingSni;
+    use tokio_rustls::AcceptOptions;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig.clone());
+    let clone = acceptor.clone();
+    let connector = TlsConnector::from(cconfig);
+    let options = AcceptOptions::new().alpn_protocols(vec![b"h2".to_vec()]);
+
+    // A config without certificates to serve, in place of a renewed one.
+    let mut renewed = rustls::ServerConfig::clone(&sconfig);
+    renewed.cert_resolver = Arc::new(ResolvesServerCertUsingSni::new());
+    let renewed = Arc::new(renewed);
+
+    let mut handshakes = Vec::new();
+    for config in [None, Some(renewed), Some(sconfig.clone())] {
+        if let Some(config) = config {
+            acceptor.swap_config(config);
+        }
+        for options in [None, Some(&options)] {
+            let (cstream, sstream) = tokio::io::duplex(4096);
+            let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+            let accept = match options {
+                None => clone.accept(sstream),
+                Some(options) => clone.accept_with_options(sstream, options),
+            };
+            let (client, server) = tokio::join!(connector.connect(domain, cstream), accept);
+            handshakes.push(client.is_ok() && server.is_ok());
+        }
+    }
+
+    // Clones and the copies made for options follow the swapped config.
+    assert_eq!(handshakes, [true, true, false, false, true, true]);
+    assert!(Arc::ptr_eq(&clone.config(), &acceptor.config()));
+
+    // The swapped config gets the wrappers of the acceptor too.
+    let acceptor = TlsAcceptor::from(sconfig.clone()).capture_client_hello();
+    acceptor.swap_config(sconfig);
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (_client, server) = (client?, server?);
+    assert!(server.client_hello().is_some());
+
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "sighup"))]
+#[tokio::test]
+async fn reload_on_sighup() -> io::Result<()> {
+    use tokio_rustls::ReloadOnSighup;
+
+    let (sconfig, _) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig.clone());
+    let renewed = Arc::new(rustls::ServerConfig::clone(&sconfig));
+
+    // The first load fails, as with a half-written certificate file.
+    let mut loads = vec![
+        Ok(renewed.clone()),
+        Err(io::Error::from(ErrorKind::NotFound)),
+    ];
+    let mut reload = ReloadOnSighup::new().server_config(acceptor.clone(), move || {
+        loads.pop().expect("loaded too often")
+    });
+    assert_eq!(
+        reload.reload().await.unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+    assert!(Arc::ptr_eq(&acceptor.config(), &sconfig));
+
+    let task = reload.spawn()?;
+    let status = std::process::Command::new("kill")
+        .args(["-HUP", &std::process::id().to_string()])
+        .status()?;
+    assert!(status.success());
+    for _ in 0..100 {
+        if Arc::ptr_eq(&acceptor.config(), &renewed) {
+            break;
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(Arc::ptr_eq(&acceptor.config(), &renewed));
+    task.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limit() -> io::Result<()> {
+    use tokio_rustls::RateLimited;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).rate_limit(1, 2);
+    let clone = acceptor.clone();
+    let connector = TlsConnector::from(cconfig);
+
+    let mut results = Vec::new();
+    for acceptor in [&acceptor, &clone, &acceptor] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (_, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        results.push(server.map(drop));
+    }
+
+    // The burst is spent by the first two; clones share the bucket.
+    assert!(results[0].is_ok() && results[1].is_ok());
+    let err = results.pop().unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert_eq!(err.get_ref().unwrap().downcast_ref(), Some(&RateLimited));
+    assert_eq!(acceptor.stats().failed, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn handshake_observer() -> io::Result<()> {
+    use rustls::Side;
+    use std::sync::Mutex;
+    use tokio_rustls::{CompletedHandshake, FailedHandshake, HandshakeObserver};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl HandshakeObserver for Recorder {
+        fn handshake_started(&self, side: Side) {
+            self.0.lock().unwrap().push(format!("{:?} started", side));
+        }
+
+        fn handshake_completed(&self, handshake: &CompletedHandshake) {
+            self.0.lock().unwrap().push(format!(
+                "{:?} completed {:?} resumed={}",
+                handshake.side,
+                handshake.protocol_version.unwrap(),
+                handshake.resumed
+            ));
+        }
+
+        fn handshake_failed(&self, handshake: &FailedHandshake<'_>) {
+            let tls = handshake.tls_error.is_some();
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{:?} failed tls={}", handshake.side, tls));
+        }
+    }
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let client = Arc::new(Recorder::default());
+    let server = Arc::new(Recorder::default());
+    let acceptor = TlsAcceptor::from(sconfig).observer(server.clone());
+    let connector = TlsConnector::from(cconfig).observer(client.clone());
+
+    for domain in ["foobar.com", "unknown.com"] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from(domain).unwrap();
+        let (cresult, sresult) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        drop((cresult, sresult));
+    }
+
+    assert_eq!(
+        *client.0.lock().unwrap(),
+        [
+            "Client started",
+            "Client completed TLSv1_3 resumed=false",
+            "Client started",
+            "Client failed tls=true",
+        ]
+    );
+    assert_eq!(
+        *server.0.lock().unwrap(),
+        [
+            "Server started",
+            "Server completed TLSv1_3 resumed=false",
+            "Server started",
+            "Server failed tls=true",
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn key_log_from_env() -> io::Result<()> {
+    let path = std::env::temp_dir().join(format!("tokio-rustls-keylog-{}", std::process::id()));
+    std::env::set_var("SSLKEYLOGFILE", &path);
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).key_log_from_env(false);
+    let connector = TlsConnector::from(cconfig).key_log_from_env(true);
+    std::env::remove_var("SSLKEYLOGFILE");
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (_client, _server) = (client?, server?);
+
+    // Only the client logs, as the acceptor was not enabled.
+    let log = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(log.matches("CLIENT_TRAFFIC_SECRET_0 ").count(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn encrypted_client_hello() -> io::Result<()> {
+    use pki_types::EchConfigListBytes;
+    use rustls::client::{EchConfig, EchStatus};
+
+    // A config list with one config of a version rustls doesn't know.
+    let list = [0, 6, 0xfe, 0x0c, 0, 2, 1, 2];
+
+    // ring has no HPKE, so no config can be used with it.
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let err = EchConfig::new(EchConfigListBytes::from(&list[..]), &[]).unwrap_err();
+    assert!(matches!(err, rustls::Error::InvalidEncryptedClientHello(_)));
+
+    // Without ECH, the status says so.
+    let acceptor = TlsAcceptor::from(sconfig);
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, _server) = (client?, server?);
+    assert_eq!(client.ech_status(), EchStatus::NotOffered);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn inspect_pending_handshake() -> io::Result<()> {
+    use futures_util::task::noop_waker_ref;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::Context;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let acceptor = TlsAcceptor::from(sconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(64 * 1024);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let mut connect = connector.connect(domain, cstream);
+    let mut accept = acceptor.accept(sstream);
+
+    // The client sends its hello and waits for the server.
+    let mut cx = Context::from_waker(noop_waker_ref());
+    assert!(Pin::new(&mut connect).poll(&mut cx).is_pending());
+    assert!(connect.get_ref().is_some());
+    let session = connect.session().unwrap();
+    assert!(session.is_handshaking());
+    assert!(session.alpn_protocol().is_none());
+
+    // The server has seen nothing until it is polled.
+    assert!(accept.session().unwrap().server_name().is_none());
+    assert!(Pin::new(&mut accept).poll(&mut cx).is_pending());
+    assert_eq!(accept.session().unwrap().server_name(), Some("foobar.com"));
+    assert!(accept.session_mut().is_some());
+
+    let (client, server) = tokio::join!(&mut connect, &mut accept);
+    let (_client, _server) = (client?, server?);
+    assert!(connect.session().is_none());
+    assert!(accept.get_mut().is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn maybe_tls_stream() -> io::Result<()> {
+    use tokio_rustls::MaybeTlsStream;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let acceptor = TlsAcceptor::from(sconfig);
+
+    for tls in [false, true] {
+        let (cstream, sstream) = tokio::io::duplex(1024);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) = tokio::join!(
+            MaybeTlsStream::connect(tls.then_some(&connector), domain, cstream),
+            MaybeTlsStream::accept(tls.then_some(&acceptor), sstream),
+        );
+        let (mut client, mut server) = (client?, server?);
+        assert_eq!(client.is_tls(), tls);
+        assert_eq!(server.is_tls(), tls);
+
+        client.write_all(b"ping").await?;
+        client.flush().await?;
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"ping");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn owned_buffer_stream() -> io::Result<()> {
+    use tokio::io::DuplexStream;
+    use tokio_rustls::{OwnedBufFuture, OwnedIo, OwnedTlsStream};
+
+    /// Completion-style IO, like tokio-uring's.
+    struct Owned(DuplexStream);
+
+    impl OwnedIo for Owned {
+        fn read(&mut self, mut buf: Vec<u8>) -> OwnedBufFuture<'_> {
+            Box::pin(async move { (self.0.read(&mut buf).await, buf) })
+        }
+
+        fn write(&mut self, buf: Vec<u8>) -> OwnedBufFuture<'_> {
+            Box::pin(async move { (self.0.write(&buf).await, buf) })
+        }
+    }
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(1024);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let session = rustls::ClientConnection::new(cconfig, domain).unwrap();
+    let mut client = OwnedTlsStream::new(Owned(cstream), session);
+
+    let data = vec![0x42; 64 * 1024];
+    let server = async {
+        let mut server = acceptor.accept(sstream).await?;
+        let mut received = vec![0; data.len()];
+        server.read_exact(&mut received).await?;
+        server.write_all(&received).await?;
+        server.shutdown().await?;
+        Ok::<_, io::Error>(())
+    };
+    let client = async {
+        client.handshake().await?;
+        assert!(!client.get_ref().1.is_handshaking());
+        client.write_all(&data).await?;
+        client.shutdown().await?;
+
+        let mut echoed = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            match client.read(&mut buf).await? {
+                0 => break,
+                n => echoed.extend_from_slice(&buf[..n]),
+            }
+        }
+        Ok::<_, io::Error>(echoed)
+    };
+    let (server, echoed) = tokio::join!(server, client);
+    server?;
+    assert_eq!(echoed?, data);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn as_fd() -> io::Result<()> {
+    use std::os::unix::io::{AsFd, AsRawFd};
+    use tokio_rustls::MaybeTlsStream;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let (cstream, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    let (cstream, (sstream, _)) = (cstream?, accepted?);
+    let (client_fd, server_fd) = (cstream.as_raw_fd(), sstream.as_raw_fd());
+
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, server) = (client?, server?);
+    assert_eq!(client.as_fd().as_raw_fd(), client_fd);
+    assert_eq!(server.as_fd().as_raw_fd(), server_fd);
+
+    let client = MaybeTlsStream::from(client);
+    assert_eq!(client.as_raw_fd(), client_fd);
+    let server = tokio_rustls::TlsStream::from(server);
+    assert_eq!(server.as_fd().as_raw_fd(), server_fd);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_without_sni() -> io::Result<()> {
+    use tokio_rustls::ConnectOptions;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+    let no_sni = ConnectOptions::new().sni(false);
+
+    for (options, sni) in [
+        (ConnectOptions::new(), Some("foobar.com")),
+        (no_sni.clone(), None),
+    ] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) = tokio::join!(
+            connector.connect_with_options(domain, cstream, &options),
+            acceptor.accept(sstream)
+        );
+        client?;
+        assert_eq!(server?.get_ref().1.server_name(), sni);
+    }
+
+    // The certificate is still verified against the expected name.
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("wrong.com").unwrap();
+    let (client, _) = tokio::join!(
+        connector.connect_with_options(domain, cstream, &no_sni),
+        acceptor.accept(sstream)
+    );
+    assert_eq!(client.unwrap_err().kind(), ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rotating_ticketer() -> io::Result<()> {
+    use rustls::crypto::ring::Ticketer;
+    use rustls::server::ProducesTickets;
+    use tokio_rustls::RotatingTicketer;
+
+    let hour = Duration::from_secs(60 * 60);
+    let ticketer = Arc::new(RotatingTicketer::new(Ticketer::new, hour, hour).unwrap());
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig)
+        .ticketer(ticketer.clone())
+        .track_resumption();
+    let connector = TlsConnector::from(cconfig);
+
+    // Tickets of the previous key are still accepted after a rotation.
+    for rotate in [false, true] {
+        if rotate {
+            ticketer.rotate().unwrap();
+        }
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        let (mut client, mut server) = (client?, server?);
+        // Tickets are sent after the handshake.
+        server.write_all(b"x").await?;
+        server.flush().await?;
+        client.read_exact(&mut [0]).await?;
+    }
+    let metrics = acceptor.resumption_metrics();
+    assert_eq!((metrics.handshakes, metrics.resumed), (2, 1));
+
+    // Without an overlap, they are not.
+    let ticketer = RotatingTicketer::new(Ticketer::new, hour, Duration::ZERO).unwrap();
+    let ticket = ticketer.encrypt(b"session").unwrap();
+    assert_eq!(ticketer.decrypt(&ticket).as_deref(), Some(&b"session"[..]));
+    ticketer.rotate().unwrap();
+    assert!(ticketer.decrypt(&ticket).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_without_resumption() -> io::Result<()> {
+    use rustls::HandshakeKind::{Full, Resumed};
+    use tokio_rustls::ConnectOptions;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let mut kinds = Vec::new();
+    for resumption in [true, false, true, false] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let acceptor = acceptor.clone();
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(sstream).await?;
+            stream.shutdown().await?;
+            Ok(()) as io::Result<()>
+        });
+
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let options = ConnectOptions::new().resumption(resumption);
+        let mut stream = connector
+            .connect_with_options(domain, cstream, &options)
+            .await?;
+        stream.read_to_end(&mut Vec::new()).await?;
+        kinds.push(stream.get_ref().1.handshake_kind().unwrap());
+        server.await.unwrap()?;
+    }
+
+    // Connections without resumption neither use nor replace the stored session.
+    assert_eq!(kinds, [Full, Full, Resumed, Full]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn accept_with_alpn_override() -> io::Result<()> {
+    use tokio_rustls::AcceptOptions;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig)
+        .with_config(|config| config.alpn_protocols = vec![b"h2".to_vec()]);
+    let connector = TlsConnector::from(cconfig)
+        .with_config(|config| config.alpn_protocols = vec![b"h2".to_vec(), b"acme-tls/1".to_vec()]);
+
+    let acme = AcceptOptions::new().alpn_protocols(vec![b"acme-tls/1".to_vec()]);
+    for (options, alpn) in [(AcceptOptions::new(), &b"h2"[..]), (acme, b"acme-tls/1")] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) = tokio::join!(
+            connector.connect(domain, cstream),
+            acceptor.accept_with_options(sstream, &options)
+        );
+        client?;
+        assert_eq!(server?.get_ref().1.alpn_protocol(), Some(alpn));
+    }
+
+    // The shared config is left as it is.
+    assert_eq!(acceptor.config().alpn_protocols, [b"h2"]);
+
+    Ok(())
+}
+
+#[cfg(feature = "events")]
+#[tokio::test]
+async fn stream_events() -> io::Result<()> {
+    use tokio_rustls::StreamEvent;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+    let mut client_events = client.subscribe();
+    let mut server_events = server.subscribe();
+
+    server.refresh_traffic_keys()?;
+    server.write_all(b"hello").await?;
+    server.shutdown().await?;
+    let mut buf = Vec::new();
+    client.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"hello");
+
+    client.shutdown().await?;
+    server.read_to_end(&mut Vec::new()).await?;
+
+    // The server sends two session tickets right after the handshake.
+    let mut events = Vec::new();
+    while let Ok(event) = client_events.try_recv() {
+        events.push(event);
+    }
+    assert_eq!(
+        events,
+        [
+            StreamEvent::TicketReceived,
+            StreamEvent::TicketReceived,
+            StreamEvent::CloseNotify
+        ]
+    );
+    assert_eq!(server_events.try_recv(), Ok(StreamEvent::KeyUpdate));
+    assert_eq!(server_events.try_recv(), Ok(StreamEvent::CloseNotify));
+
+    Ok(())
+}
+
+#[cfg(feature = "tcp")]
+#[tokio::test]
+async fn connect_addr() -> io::Result<()> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let key = pki_types::PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+    let sconfig = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)
+        .unwrap();
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let cconfig = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let acceptor = TlsAcceptor::from(Arc::new(sconfig));
+    let connector = TlsConnector::from(Arc::new(cconfig));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let server = async {
+        let (stream, _) = listener.accept().await?;
+        acceptor.accept(stream).await
+    };
+    let addr = format!("localhost:{}", port);
+    let (client, server) = tokio::join!(connector.connect_addr(&addr), server);
+    let (client, _server) = (client?, server?);
+    assert_eq!(client.peer_addr()?.port(), port);
+
+    for addr in ["localhost", "not a name:443"] {
+        let err = connector.connect_addr(addr).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "tcp")]
+#[tokio::test]
+async fn tcp_socket_options() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let server = async {
+        let (stream, _) = listener.accept().await?;
+        acceptor.accept(stream).await
+    };
+    let client = async {
+        let stream = TcpStream::connect(addr).await?;
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        connector.connect(domain, stream).await
+    };
+    let (client, server) = tokio::join!(client, server);
+    let (client, server) = (client?, server?);
+
+    assert_eq!(client.peer_addr()?, addr);
+    assert_eq!(server.local_addr()?, addr);
+    assert_eq!(server.peer_addr()?, client.local_addr()?);
+
+    client.set_nodelay(true)?;
+    assert!(client.nodelay()?);
+    server.set_keepalive(true)?;
+    assert!(server.keepalive()?);
+
+    let stream = tokio_rustls::TlsStream::from(server);
+    stream.set_nodelay(true)?;
+    assert!(stream.nodelay()?);
+    assert_eq!(stream.linger()?, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn typed_alpn_protocols() -> io::Result<()> {
+    use tokio_rustls::AlpnProtocol;
+
+    assert_eq!(AlpnProtocol::from(&b"h2"[..]), AlpnProtocol::H2);
+    assert_eq!(
+        AlpnProtocol::from(b"acme-tls/1".to_vec()),
+        AlpnProtocol::AcmeTls1
+    );
+    let spdy = AlpnProtocol::from(&b"spdy/3"[..]);
+    assert_eq!(spdy, AlpnProtocol::Other(b"spdy/3".to_vec()));
+    assert_eq!(Vec::from(spdy), b"spdy/3");
+    assert_eq!(AlpnProtocol::Http11.to_string(), "http/1.1");
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).alpn_protocols(&[AlpnProtocol::Http11]);
+    let connector = TlsConnector::from(cconfig).alpn_protocols(AlpnProtocol::HTTP);
+    assert_eq!(connector.config().alpn_protocols, [&b"h2"[..], b"http/1.1"]);
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let client = client?;
+    assert_eq!(client.alpn_protocol(), Some(AlpnProtocol::Http11));
+    assert_eq!(client.alpn_protocol_bytes(), Some(&b"http/1.1"[..]));
+    let server = server?;
+    assert_eq!(server.alpn_protocol_bytes(), Some(&b"http/1.1"[..]));
+    let server = tokio_rustls::TlsStream::from(server);
+    assert_eq!(server.alpn_protocol(), Some(AlpnProtocol::Http11));
+    assert_eq!(server.alpn_protocol_bytes(), Some(&b"http/1.1"[..]));
+
+    Ok(())
+}
+
+#[cfg(feature = "x509")]
+#[tokio::test]
+async fn cert_expiry_monitor() -> io::Result<()> {
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tokio_rustls::verify::Pinned;
+    use tokio_rustls::CertExpiryMonitor;
+
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+
+    let issue = |not_after| {
+        let mut params = rcgen::CertificateParams::new(vec!["foobar.com".into()]).unwrap();
+        params.not_after = not_after;
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        (cert.der().clone(), key.serialize_der())
+    };
+    let (soon, soon_key) = issue(rcgen::date_time_ymd(2030, 1, 1));
+    let (later, _) = issue(rcgen::date_time_ymd(2100, 1, 1));
+
+    // Warn about certificates that expire before 2050.
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let window = Duration::from_secs(2_524_608_000) - now;
+    let warned = Arc::new(Mutex::new(Vec::new()));
+    let monitor = Arc::new(CertExpiryMonitor::new(window, {
+        let warned = warned.clone();
+        move |expiry| warned.lock().unwrap().push(expiry.not_after)
+    }));
+
+    monitor.watch(&later)?;
+    assert!(warned.lock().unwrap().is_empty());
+    assert_eq!(monitor.certificates()[0].not_after, 4_102_444_800);
+
+    let sconfig = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![soon.clone()],
+            pki_types::PrivatePkcs8KeyDer::from(soon_key).into(),
+        )
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(sconfig)).monitor_expiry(monitor.clone());
+    let cconfig = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(Pinned::new(vec![soon], &provider)))
+        .with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(cconfig));
+
+    for _ in 0..2 {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        let (_client, _server) = (client?, server?);
+    }
+
+    // Served twice, registered and reported once.
+    assert_eq!(*warned.lock().unwrap(), [1_893_456_000]);
+    assert_eq!(monitor.certificates().len(), 2);
+
+    let expiring = monitor.check();
+    assert_eq!(expiring.len(), 1);
+    assert_eq!(expiring[0].sans, ["DNS:foobar.com"]);
+    assert_eq!(warned.lock().unwrap().len(), 2);
+
+    Ok(())
+}
+
+#[cfg(feature = "cancel")]
+#[tokio::test]
+async fn cancel_handshakes() -> io::Result<()> {
+    use futures_util::future::join;
+    use tokio_util::sync::CancellationToken;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let acceptor = TlsAcceptor::from(sconfig);
+
+    // Cancel a client once its ClientHello is out; the server sees close_notify.
+    let token = CancellationToken::new();
+    let (cstream, mut sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let connect = connector.connect(domain, cstream).cancel_on(token.clone());
+    let (client, rest) = join(connect, async {
+        let mut hello = [0; 4096];
+        assert!(sstream.read(&mut hello).await? > 0);
+        token.cancel();
+        let mut rest = Vec::new();
+        sstream.read_to_end(&mut rest).await?;
+        Ok::<_, io::Error>(rest)
+    })
+    .await;
+    assert_eq!(client.err().unwrap().kind(), ErrorKind::ConnectionAborted);
+    let rest = rest?;
+    assert_eq!((rest[0], &rest[rest.len() - 2..]), (0x15, &[1, 0][..]));
+
+    // A server waiting for a ClientHello gives back its IO.
+    let token = CancellationToken::new();
+    let (_cstream, sstream) = tokio::io::duplex(4096);
+    let accept = acceptor.accept(sstream).cancel_on(token.clone());
+    token.cancel();
+    let (err, _io) = accept.into_fallible().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::ConnectionAborted);
+
+    Ok(())
+}
+
+#[cfg(all(feature = "cancel", feature = "listener"))]
+#[tokio::test]
+async fn cancel_lazy_tls_listener() -> io::Result<()> {
+    use futures_util::StreamExt;
+    use tokio_rustls::LazyTlsListener;
+    use tokio_util::sync::CancellationToken;
+
+    let token = CancellationToken::new();
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mut listener = LazyTlsListener::new(listener).cancel_on(token.clone());
+    let _idle = TcpStream::connect(listener.local_addr()?).await?;
+
+    token.cancel();
+    let err = listener.accept().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::ConnectionAborted);
+    assert!(listener.next().await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_watermarks() -> io::Result<()> {
+    use futures_util::future::join;
+    use futures_util::task::noop_waker_ref;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::AsyncWrite;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig).write_watermarks(16 * 1024, 4 * 1024);
+    let acceptor = TlsAcceptor::from(sconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(1024);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    // While the server isn't reading, the client takes about `high` bytes, rather
+    // than filling rustls' 64 KiB buffer.
+    let data = vec![0x42; 256 * 1024];
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let mut accepted = 0;
+    while let Poll::Ready(n) = Pin::new(&mut client).poll_write(&mut cx, &data[accepted..]) {
+        accepted += n?;
+    }
+    assert!(accepted > 8 * 1024 && accepted <= 17 * 1024, "{}", accepted);
+
+    let write = async {
+        client.write_all(&data[accepted..]).await?;
+        client.shutdown().await
+    };
+    let mut received = Vec::new();
+    let (written, read) = join(write, server.read_to_end(&mut received)).await;
+    written?;
+    read?;
+    assert_eq!(received.len(), data.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn buffer_limit() -> io::Result<()> {
+    use futures_util::future::join;
+    use futures_util::task::noop_waker_ref;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::AsyncWrite;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig).buffer_limit(Some(8 * 1024));
+    let acceptor = TlsAcceptor::from(sconfig);
+
+    let (cstream, sstream) = tokio::io::duplex(1024);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, mut server) = (client?, server?);
+
+    // While the server isn't reading, the client takes about the limit.
+    let data = vec![0x42; 256 * 1024];
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let mut accepted = 0;
+    while let Poll::Ready(n) = Pin::new(&mut client).poll_write(&mut cx, &data[accepted..]) {
+        accepted += n?;
+    }
+    assert!(accepted > 4 * 1024 && accepted <= 9 * 1024, "{}", accepted);
+
+    // Without a limit, it takes everything.
+    client.set_buffer_limit(None);
+    while accepted < data.len() {
+        match Pin::new(&mut client).poll_write(&mut cx, &data[accepted..]) {
+            Poll::Ready(n) => accepted += n?,
+            Poll::Pending => panic!("write blocked without a buffer limit"),
+        }
+    }
+
+    let mut received = Vec::new();
+    let (shutdown, read) = join(client.shutdown(), server.read_to_end(&mut received)).await;
+    shutdown?;
+    read?;
+    assert_eq!(received.len(), data.len());
+
+    Ok(())
+}
+
+#[cfg(all(feature = "listener", target_os = "linux"))]
+#[tokio::test]
+async fn lazy_tls_listener_sharded() -> io::Result<()> {
+    use futures_util::future::{select, Either};
+    use tokio_rustls::LazyTlsListener;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let connector = TlsConnector::from(cconfig);
+    let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+    let mut shards = LazyTlsListener::bind_sharded(addr, 2)?;
+    let addr = shards[0].local_addr()?;
+    assert_eq!(shards[1].local_addr()?, addr);
+
+    let client = tokio::spawn(async move {
+        let stream = TcpStream::connect(addr).await?;
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let mut stream = connector.connect(domain, stream).await?;
+        stream.write_all(b"hello").await?;
+        stream.shutdown().await
+    });
+
+    // The kernel hands the connection to either shard.
+    let (first, second) = shards.split_at_mut(1);
+    let start = match select(Box::pin(first[0].accept()), Box::pin(second[0].accept())).await {
+        Either::Left((start, _)) | Either::Right((start, _)) => start?.0,
+    };
+    let mut stream = start.into_stream(sconfig).await?;
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await?;
+    assert_eq!(buf, b"hello");
+    client.await.unwrap()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reloading_roots() -> io::Result<()> {
+    use tokio_rustls::ReloadingRoots;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let provider = cconfig.crypto_provider().clone();
+
+    let other = rcgen::generate_simple_self_signed(vec!["other.example".into()]).unwrap();
+    let mut other_roots = rustls::RootCertStore::empty();
+    other_roots.add(other.cert.der().clone()).unwrap();
+    let roots = Arc::new(ReloadingRoots::new(other_roots.clone(), provider)?);
+    let connector = TlsConnector::from(cconfig).trust_anchors(roots.clone());
+
+    let connect = || async {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, _) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        client.map(drop)
+    };
+
+    let err = connect().await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    roots.load_pem_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/end.chain"))?;
+    connect().await?;
+
+    // An empty store is refused, keeping the current roots.
+    assert!(roots.set(rustls::RootCertStore::empty()).is_err());
+    connect().await?;
+
+    roots.set(other_roots)?;
+    assert!(connect().await.is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "timeout")]
+#[tokio::test]
+async fn accept_with_timeout() -> io::Result<()> {
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig.clone());
+    let timeout = Duration::from_millis(50);
+
+    // A client that never sends its ClientHello; the IO is handed back.
+    let (_cstream, sstream) = tokio::io::duplex(4096);
+    let accept = acceptor.accept_with_timeout(sstream, timeout);
+    let (err, _io) = accept.into_fallible().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    // Handshakes that finish in time are unaffected.
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) = tokio::join!(
+        TlsConnector::from(cconfig).connect(domain, cstream),
+        acceptor.accept_with_timeout(sstream, timeout)
+    );
+    client?;
+    server?;
+
+    // The lazy acceptor times out waiting for the ClientHello, and drops the IO.
+    let (mut cstream, sstream) = tokio::io::duplex(4096);
+    let acceptor = rustls::server::Acceptor::default();
+    let mut lazy = LazyConfigAcceptor::with_timeout(acceptor, sstream, timeout);
+    let err = (&mut lazy).await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(lazy.take_io().is_none());
+    assert_eq!(cstream.read(&mut [0; 1]).await?, 0);
+
+    Ok(())
+}
+
+#[cfg(feature = "hyper")]
+#[tokio::test]
+async fn https_connector() -> io::Result<()> {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use hyper_util::client::legacy::connect::Connection;
+    use hyper_util::rt::TokioIo;
+    use tokio_rustls::{AlpnProtocol, HttpsConnector, MaybeHttpsStream};
+    use tower_service::Service;
+
+    /// Connects to `addr` whatever the URI, so `foobar.com` needs no DNS.
+    #[derive(Clone)]
+    struct Local(SocketAddr);
+
+    impl Service<http::Uri> for Local {
+        type Response = TokioIo<TcpStream>;
+        type Error = io::Error;
+        type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: http::Uri) -> Self::Future {
+            let addr = self.0;
+            Box::pin(async move { TcpStream::connect(addr).await.map(TokioIo::new) })
+        }
+    }
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).alpn_protocols(AlpnProtocol::HTTP);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let mut stream = acceptor.accept(stream).await?;
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await?);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+            .await?;
+        stream.shutdown().await
+    });
+
+    let mut connector =
+        HttpsConnector::with_http(Local(addr), TlsConnector::from(cconfig)).https_only(true);
+    let uri = http::Uri::from_static("http://foobar.com/");
+    assert!(connector.call(uri).await.is_err());
+
+    let uri = http::Uri::from_static("https://foobar.com/");
+    let conn = connector.call(uri).await.unwrap();
+    assert!(matches!(conn, MaybeHttpsStream::Https(_)));
+    // Only HTTP/1.1 is offered by default.
+    assert!(!conn.connected().is_negotiated_h2());
+
+    let mut conn = TokioIo::new(conn);
+    conn.write_all(b"GET / HTTP/1.1\r\nhost: foobar.com\r\n\r\n")
+        .await?;
+    conn.flush().await?;
+    let mut response = Vec::new();
+    conn.read_to_end(&mut response).await?;
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with(b"hello"));
+    server.await.unwrap()?;
+
+    Ok(())
+}
+
+#[cfg(feature = "hyper")]
+#[tokio::test]
+async fn hyper_connection() -> io::Result<()> {
+    use hyper_util::client::legacy::connect::{Connection, HttpInfo};
+    use hyper_util::rt::TokioIo;
+    use tokio_rustls::AlpnProtocol;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig).alpn_protocols(AlpnProtocol::HTTP);
+    let connector = TlsConnector::from(cconfig).alpn_protocols(&[AlpnProtocol::H2]);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let connect = async {
+        let stream = TcpStream::connect(addr).await?;
+        connector.connect(domain, stream).await
+    };
+    let accept = async { acceptor.accept(listener.accept().await?.0).await };
+    let (client, server) = tokio::join!(connect, accept);
+    let (client, _server) = (client?, server?);
+
+    // What a connector for the hyper-util client returns.
+    let conn = TokioIo::new(client);
+    let connected = conn.connected();
+    assert!(connected.is_negotiated_h2());
+    let mut extensions = http::Extensions::new();
+    connected.get_extras(&mut extensions);
+    assert_eq!(extensions.get::<HttpInfo>().unwrap().remote_addr(), addr);
+
+    Ok(())
+}
+
+#[cfg(feature = "tonic")]
+#[tokio::test]
+async fn tonic_connect_info() -> io::Result<()> {
+    use tokio_rustls::{AlpnProtocol, ReloadingClientCert, TlsConnectInfo};
+    use tonic::transport::server::{Connected, TcpConnectInfo};
+
+    let (acceptor, issue) = client_auth_setup();
+    let acceptor = acceptor.alpn_protocols(&[AlpnProtocol::H2]);
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+
+    let (cert, key) = issue("client.example");
+    let cert = rustls_pemfile::certs(&mut cert.as_bytes())
+        .next()
+        .unwrap()?;
+    let key = rustls_pemfile::private_key(&mut key.as_bytes())?.unwrap();
+    let key = provider.key_provider.load_private_key(key).unwrap();
+    let identity = rustls::sign::CertifiedKey::new(vec![cert.clone()], key);
+    let connector = TlsConnector::from(cconfig)
+        .alpn_protocols(&[AlpnProtocol::H2])
+        .client_identity(Arc::new(ReloadingClientCert::new(Arc::new(identity))));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let connect = async {
+        connector
+            .connect(domain, TcpStream::connect(addr).await?)
+            .await
+    };
+    let accept = async { acceptor.accept(listener.accept().await?.0).await };
+    let (client, server) = tokio::join!(connect, accept);
+    let (client, server) = (client?, server?);
+
+    let info: TlsConnectInfo<TcpConnectInfo> = server.connect_info();
+    assert_eq!(info.get_ref().local_addr(), Some(addr));
+    assert_eq!(
+        info.get_ref().remote_addr(),
+        Some(client.get_ref().0.local_addr()?)
+    );
+    assert_eq!(info.peer_certificates(), Some(&[cert][..]));
+    assert_eq!(info.server_name(), Some("foobar.com"));
+    assert_eq!(info.alpn_protocol(), Some(&AlpnProtocol::H2));
+
+    Ok(())
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn tower_service() -> io::Result<()> {
+    use tower::timeout::TimeoutLayer;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = ServiceBuilder::new()
+        .layer(TimeoutLayer::new(Duration::from_secs(5)))
+        .service(TlsConnector::from(cconfig));
+
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let connect = connector.oneshot((domain, cstream));
+    let (client, server) = tokio::join!(connect, acceptor.accept(sstream));
+    let (mut client, mut server) = (client.unwrap(), server?);
+
+    server.write_all(b"hello").await?;
+    server.flush().await?;
+    let mut buf = [0; 5];
+    client.read_exact(&mut buf).await?;
+    assert_eq!(&buf, b"hello");
+
+    // The timeout wraps the handshake.
+    let (cstream, _sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let connector = ServiceBuilder::new()
+        .layer(TimeoutLayer::new(Duration::from_millis(10)))
+        .service(TlsConnector::from(utils::make_configs().1));
+    let err = connector.oneshot((domain, cstream)).await;
+    assert!(err.unwrap_err().is::<tower::timeout::error::Elapsed>());
+
+    Ok(())
+}
+
+#[cfg(feature = "timeout")]
+#[tokio::test]
+async fn timeout_stream() -> io::Result<()> {
+    use tokio_rustls::{StreamTimeout, TimeoutStream};
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+    let timeout = Duration::from_millis(50);
+    let timed_out = |err: io::Error| {
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        *err.get_ref()
+            .unwrap()
+            .downcast_ref::<StreamTimeout>()
+            .unwrap()
+    };
+
+    for (expected, read_timeout) in [(StreamTimeout::Read, true), (StreamTimeout::Idle, false)] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) =
+            tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+        let (client, mut server) = (client?, server?);
+        let mut client = match read_timeout {
+            true => TimeoutStream::new(client).read_timeout(timeout),
+            false => TimeoutStream::new(client).idle_timeout(timeout),
+        };
+
+        // Data arriving in time is read.
+        server.write_all(b"ping").await?;
+        server.flush().await?;
+        let mut buf = [0; 4];
+        client.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"ping");
+
+        let err = client.read(&mut buf).await.unwrap_err();
+        assert_eq!(timed_out(err), expected);
+    }
+
+    // A peer that doesn't read runs into the write timeout.
+    let (cstream, sstream) = tokio::io::duplex(1024);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (client, _server) = (client?, server?);
+    let mut client = TimeoutStream::new(client).write_timeout(timeout);
+    let err = client.write_all(&[0; 256 * 1024]).await.unwrap_err();
+    assert_eq!(timed_out(err), StreamTimeout::Write);
+
+    Ok(())
+}
+
+#[cfg(feature = "timeout")]
+#[tokio::test]
+async fn graceful_shutdown() -> io::Result<()> {
+    use tokio_rustls::EofReason;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+    let timeout = Duration::from_secs(5);
+
+    for peer_closes in [true, false] {
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let (client, server) = tokio::join!(
+            connector.connect(domain.clone(), cstream),
+            acceptor.accept(sstream)
+        );
+        let (mut client, mut server) = (client?, server?);
+
+        let peer = async {
+            // Data sent while the client closes is discarded.
+            server.write_all(b"bye").await?;
+            server.flush().await?;
+            match peer_closes {
+                true => server.graceful_shutdown(timeout).await,
+                false => {
+                    server.read_to_end(&mut Vec::new()).await?;
+                    // Drop the transport without `close_notify`.
+                    drop(server);
+                    Ok(EofReason::Transport)
+                }
+            }
+        };
+        let (closed, peer) = tokio::join!(client.graceful_shutdown(timeout), peer);
+        let expected = match peer_closes {
+            true => EofReason::CloseNotify,
+            false => EofReason::Transport,
+        };
+        assert_eq!(closed?, expected);
+        assert_eq!(peer?, expected);
+    }
+
+    // A peer that never closes runs into the timeout.
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (mut client, _server) = (client?, server?);
+    let err = client
+        .graceful_shutdown(Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    Ok(())
+}
+
+#[cfg(feature = "session-store")]
+#[tokio::test]
+async fn session_store() -> io::Result<()> {
+    use rustls::HandshakeKind::{Full, Resumed};
+    use tokio_rustls::LruSessionStore;
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let store = Arc::new(LruSessionStore::new(16));
+
+    let mut kinds = Vec::new();
+    for _ in 0..3 {
+        // A connector of its own each time, sharing only the store.
+        let connector = TlsConnector::from(cconfig.clone()).session_store(store.clone());
+        let (cstream, sstream) = tokio::io::duplex(4096);
+        let acceptor = acceptor.clone();
+        let server = tokio::spawn(async move {
+            let mut stream = acceptor.accept(sstream).await?;
+            stream.shutdown().await?;
+            Ok(()) as io::Result<()>
+        });
+
+        let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+        let mut stream = connector.connect(domain, cstream).await?;
+        stream.read_to_end(&mut Vec::new()).await?;
+        kinds.push(stream.get_ref().1.handshake_kind().unwrap());
+        server.await.unwrap()?;
+        // Let the tickets the client received reach the store.
+        tokio::task::yield_now().await;
+    }
+
+    assert_eq!(kinds, [Full, Resumed, Resumed]);
+
+    Ok(())
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn tracing_spans() -> io::Result<()> {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    /// Records span fields as `span.field=value`, and event messages.
+    #[derive(Default)]
+    struct Recorder {
+        next_id: AtomicU64,
+        spans: Mutex<HashMap<u64, &'static str>>,
+        lines: Mutex<Vec<String>>,
+    }
+
+    struct Visitor<'a>(&'a str, &'a mut Vec<String>);
+
+    impl Visit for Visitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.1
+                .push(format!("{}.{}={:?}", self.0, field.name(), value));
+        }
+    }
+
+    impl tracing::Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+            let name = span.metadata().name();
+            self.spans.lock().unwrap().insert(id, name);
+            span.record(&mut Visitor(name, &mut self.lines.lock().unwrap()));
+            Id::from_u64(id)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let name = self.spans.lock().unwrap()[&span.into_u64()];
+            values.record(&mut Visitor(name, &mut self.lines.lock().unwrap()));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut Visitor("event", &mut self.lines.lock().unwrap()));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    let recorder = Arc::new(Recorder::default());
+    let _guard = tracing::subscriber::set_default(recorder.clone());
+
+    let (sconfig, cconfig) = utils::make_configs();
+    let acceptor = TlsAcceptor::from(sconfig);
+    let connector = TlsConnector::from(cconfig);
+    let (cstream, sstream) = tokio::io::duplex(4096);
+    let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+    let (client, server) =
+        tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+    let (_client, _server) = (client?, server?);
+
+    let lines = recorder.lines.lock().unwrap();
+    for line in [
+        "connect.sni=foobar.com",
+        "accept.sni=\"foobar.com\"",
+        "accept.kind=Full",
+        "event.message=handshake started",
+        "event.message=handshake completed",
+    ] {
+        assert!(
+            lines.iter().any(|l| l == line),
+            "{} not in {:?}",
+            line,
+            lines
+        );
+    }
+    assert!(lines.iter().any(|l| l.starts_with("connect.duration_ms=")));
+
+    Ok(())
+}
+
+#[cfg(feature = "crl")]
+#[tokio::test]
+async fn crl_verifier() -> io::Result<()> {
+    use rustls::server::danger::ClientCertVerifier;
+    use tokio_rustls::{CrlFetcher, CrlFuture, CrlVerifier, ReloadingClientCert};
+
+    const URL: &str = "http://crl.example/ca.crl";
+
+    struct Distribution(Vec<u8>);
+
+    impl CrlFetcher for Distribution {
+        fn fetch<'a>(&'a self, url: &'a str) -> CrlFuture<'a> {
+            assert_eq!(url, URL);
+            let crl = self.0.clone();
+            Box::pin(async move { Ok(crl) })
+        }
+    }
+
+    // A CA, and a CRL of it that revokes serial number 2.
+    let ca_key = rcgen::KeyPair::generate().unwrap();
+    let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let distribution_point = rcgen::CrlDistributionPoint {
+        uris: vec![URL.into()],
+    };
+    let crl = rcgen::CertificateRevocationListParams {
+        this_update: rcgen::date_time_ymd(2024, 1, 1),
+        next_update: rcgen::date_time_ymd(2100, 1, 1),
+        crl_number: 1.into(),
+        issuing_distribution_point: Some(rcgen::CrlIssuingDistributionPoint {
+            distribution_point: distribution_point.clone(),
+            scope: None,
+        }),
+        revoked_certs: vec![rcgen::RevokedCertParams {
+            serial_number: 2.into(),
+            revocation_time: rcgen::date_time_ymd(2024, 1, 1),
+            reason_code: None,
+            invalidity_date: None,
+        }],
+        key_identifier_method: rcgen::KeyIdMethod::Sha256,
+    }
+    .signed_by(&ca, &ca_key)
+    .unwrap();
+
+    let (_, cconfig) = utils::make_configs();
+    let provider = cconfig.crypto_provider().clone();
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(ca.der().clone()).unwrap();
+    let fetcher = Arc::new(Distribution(crl.der().to_vec()));
+    let roots = Arc::new(roots);
+    let verifier = Arc::new(CrlVerifier::new(roots.clone(), provider.clone(), fetcher).unwrap());
+    let cert = certs(&mut BufReader::new(Cursor::new(CERT))).collect::<io::Result<Vec<_>>>()?;
+    let key = rsa_private_keys(&mut BufReader::new(Cursor::new(RSA)))
+        .next()
+        .unwrap()?;
+    let sconfig = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_client_cert_verifier(verifier.clone())
+        .with_single_cert(cert, key.into())
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(sconfig));
+
+    let connect = |serial: u64| {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["client.example".into()]).unwrap();
+        params.serial_number = Some(serial.into());
+        params.crl_distribution_points = vec![distribution_point.clone()];
+        let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+        let key = provider
+            .key_provider
+            .load_private_key(pki_types::PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+            .unwrap();
+        let identity = rustls::sign::CertifiedKey::new(vec![cert.der().clone()], key);
+        let connector = TlsConnector::from(cconfig.clone())
+            .client_identity(Arc::new(ReloadingClientCert::new(Arc::new(identity))));
+        let acceptor = acceptor.clone();
+        async move {
+            let (cstream, sstream) = tokio::io::duplex(4096);
+            let domain = pki_types::ServerName::try_from("foobar.com").unwrap();
+            let (_, server) =
+                tokio::join!(connector.connect(domain, cstream), acceptor.accept(sstream));
+            server.map(drop)
+        }
+    };
+
+    // Certificates are accepted before the CRL is fetched only when that is allowed.
+    let open = CrlVerifier::new(
+        roots.clone(),
+        provider.clone(),
+        Arc::new(Distribution(Vec::new())),
+    )
+    .unwrap()
+    .allow_unknown_revocation_status();
+    let key = rcgen::KeyPair::generate().unwrap();
+    let mut params = rcgen::CertificateParams::new(vec!["client.example".into()]).unwrap();
+    params.serial_number = Some(2.into());
+    let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+    let now = pki_types::UnixTime::now();
+    assert!(open.verify_client_cert(cert.der(), &[], now).is_ok());
+
+    // Until the CRL is fetched, certificates are rejected; their distribution point
+    // is picked up on the way.
+    let err = connect(1).await.unwrap_err();
+    assert!(
+        err.to_string().contains("UnknownRevocationStatus"),
+        "{}",
+        err
+    );
+    assert_eq!(verifier.urls(), [URL]);
+
+    verifier.refresh().await?;
+    connect(1).await?;
+    let err = connect(2).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("Revoked"), "{}", err);
+
+    Ok(())
+}
+
+#[cfg(feature = "ocsp")]
+#[tokio::test]
+async fn ocsp_stapler() -> io::Result<()> {
+    use rustls::sign::CertifiedKey;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tokio_rustls::{OcspFetcher, OcspFuture, OcspStapler};
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        assert!(contents.len() < 0x80);
+        [&[tag, contents.len() as u8][..], contents].concat()
+    }
+
+    struct Responder(Vec<u8>);
+
+    impl OcspFetcher for Responder {
+        fn fetch<'a>(&'a self, url: &'a str, request: &'a [u8]) -> OcspFuture<'a> {
+            assert_eq!(url, "http://ocsp.example/");
+            assert_eq!(request[0], 0x30);
+            let response = self.0.clone();
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    // A CA, and a certificate it issued that names an OCSP responder.
+    let ca_key = rcgen::KeyPair::generate().unwrap();
+    let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let key = rcgen::KeyPair::generate().unwrap();
+    let mut params = rcgen::CertificateParams::new(vec!["foobar.com".into()]).unwrap();
+    let access = der(
+        0x30,
+        &[
+            der(0x06, &[0x2b, 6, 1, 5, 5, 7, 48, 1]),
+            der(0x86, b"http://ocsp.example/"),
+        ]
+        .concat(),
+    );
+    params
+        .custom_extensions
+        .push(rcgen::CustomExtension::from_oid_content(
+            &[1, 3, 6, 1, 5, 5, 7, 1, 1],
+            der(0x30, &access),
+        ));
+    let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+
+    let provider = rustls::crypto::ring::default_provider();
+    let signing_key = provider
+        .key_provider
+        .load_private_key(pki_types::PrivatePkcs8KeyDer::from(key.serialize_der()).into())
+        .unwrap();
+    let certified = Arc::new(CertifiedKey::new(
+        vec![cert.der().clone(), ca.der().clone()],
+        signing_key,
+    ));
+
+    // A good response, valid until 2100; stapling doesn't look at the signature.
+    let time = |t: &[u8]| der(0x18, t);
+    let single = der(
+        0x30,
+        &[
+            der(0x30, &[]),
+            vec![0x80, 0],
+            time(b"20240101000000Z"),
+            der(0xa0, &time(b"21000101000000Z")),
+        ]
+        .concat(),
+    );
+    let data = der(
+        0x30,
+        &[
+            der(0xa2, &der(0x04, &[0; 20])),
+            time(b"20240101000000Z"),
+            der(0x30, &single),
+        ]
+        .concat(),
+    );
+    let basic = der(0x30, &[data, der(0x30, &[]), der(0x03, &[0])].concat());
+    let response_bytes = der(
+        0x30,
+        &[
+            der(0x06, &[0x2b, 6, 1, 5, 5, 7, 48, 1, 1]),
+            der(0x04, &basic),
+        ]
+        .concat(),
+    );
+    let response = der(
+        0x30,
+        &[der(0x0a, &[0]), der(0xa0, &response_bytes)].concat(),
+    );
+
+    let fetcher = Arc::new(Responder(response.clone()));
+    let stapler = Arc::new(OcspStapler::new(certified.clone(), fetcher)?);
+    assert_eq!(stapler.responder_url(), "http://ocsp.example/");
+    assert_eq!(stapler.current().ocsp, None);
+
+    let next_update = stapler.refresh().await?;
+    assert_eq!(
+        next_update,
+        Some(UNIX_EPOCH + Duration::from_secs(4_102_444_800))
+    );
+    assert_eq!(stapler.current().ocsp, Some(response));
+
+    // Responses reporting an error are not stapled.
+    let failing = Arc::new(Responder(der(0x30, &der(0x0a, &[6]))));
+    let unauthorized = OcspStapler::new(certified.clone(), failing.clone())?;
+    let err = unauthorized.refresh().await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(unauthorized.current().ocsp, None);
+
+    // Chains without the issuer are refused.
+    let mut leaf_only = CertifiedKey::clone(&certified);
+    leaf_only.cert.truncate(1);
+    let err = OcspStapler::new(Arc::new(leaf_only), failing).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    Ok(())
+}